@@ -1,85 +1,470 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+
 use crate::builtin_types;
 use crate::builtin_types::BuiltinTypeClass;
+use crate::expression::{Env, Expr};
 use crate::parser;
 use crate::parser::{ArrayLen, ElementTypeRef, StructDefn};
-use crate::type_check::TSchema;
+use crate::type_check::{StructLayout, TSchema};
+
+/// Maximum number of bytes shown in the value of a `bytes` element
+const BYTES_PREVIEW_LEN: usize = 16;
+
+/// Value given to an array or byte block whose length can't be used, eg. because it's negative
+const BAD_LENGTH_VALUE: &str = "<invalid length>";
+
+/// Added to the value of a byte block or text that runs past the end of the data
+const TRUNCATED_VALUE: &str = "<truncated>";
+
+/// Value given to a placed element whose placement can't be found in the data
+const BAD_PLACEMENT_VALUE: &str = "<invalid placement>";
+
+/// Value given to a builtin type that doesn't fit in the rest of the data
+const PAST_END_VALUE: &str = "<past end of data>";
 
 #[derive(PartialEq, Debug)]
 pub struct Nugget {
     pub start: usize,
     pub len: usize,
     pub name: String,
+    // Displayable value of a leaf nugget.  Timestamps are ISO 8601 strings in UTC, ending in `Z`,
+    // apart from `dos_datetime` values, which are local time with no record of the time zone.
     pub value: Option<String>,
+    // Underlying integer for values that are displayed in another form, such as timestamps
+    pub raw_value: Option<String>,
+    // Peek nuggets were decoded without consuming bytes from the enclosing struct
+    pub peek: bool,
     pub children: Vec<Nugget>,
 }
 
-pub fn apply_schema(schema: &TSchema, file_data: &[u8]) -> Nugget {
-    // We know this struct must exist, as we checked for it during the correctness checks
-    let root_struct = schema.types.get("root").unwrap();
-    let start = 0;
-    build_nugget(start, root_struct, "root", schema, file_data)
+/// Options controlling how a schema is applied to data
+#[derive(PartialEq, Debug, Clone)]
+pub struct ApplyOptions {
+    /// Group the elements that a struct inherits from its base type into a child nugget named
+    /// after the base type, rather than listing them alongside the struct's own elements.
+    pub group_inherited: bool,
+
+    /// How deeply structs may be nested inside each other.  Recursive types stop being expanded
+    /// at this depth, so that a list or tree that loops back on itself still terminates.
+    pub max_depth: usize,
+
+    /// Most nuggets that will be built.  Structs placed at overlapping offsets can need
+    /// exponentially many nuggets without ever looping, so elements past this limit are left as
+    /// empty placeholders.
+    pub max_nuggets: usize,
+}
+
+impl Default for ApplyOptions {
+    fn default() -> ApplyOptions {
+        ApplyOptions {
+            group_inherited: false,
+            max_depth: 64,
+            max_nuggets: 1_000_000,
+        }
+    }
+}
+
+/// Value given to a struct that wasn't expanded, as it was nested deeper than `max_depth`
+const MAX_DEPTH_VALUE: &str = "<max depth exceeded>";
+
+/// Value given to a struct that wasn't expanded, as it's inside another instance of the same struct
+/// at the same offset, which would repeat forever
+const RECURSIVE_VALUE: &str = "<recursive struct>";
+
+/// Value given to elements that weren't built, as `max_nuggets` had been reached
+const MAX_NUGGETS_VALUE: &str = "<too many nuggets>";
+
+/// Everything shared while the nuggets for a piece of data are built
+struct ApplyContext<'a> {
+    schema: &'a TSchema,
+    options: &'a ApplyOptions,
+    file_data: &'a [u8],
+    // How many more nuggets may be built
+    nuggets_left: Cell<usize>,
+    // Type and start of each struct currently being built, outermost first
+    open_structs: RefCell<Vec<(String, usize)>>,
+    // Where each `rfind` signature was last found in the data, so each is only searched for once
+    found_signatures: RefCell<HashMap<Vec<u8>, Option<usize>>>,
+}
+
+impl<'a> ApplyContext<'a> {
+    fn new(
+        schema: &'a TSchema,
+        options: &'a ApplyOptions,
+        file_data: &'a [u8],
+    ) -> ApplyContext<'a> {
+        ApplyContext {
+            schema,
+            options,
+            file_data,
+            nuggets_left: Cell::new(options.max_nuggets),
+            open_structs: RefCell::new(Vec::new()),
+            found_signatures: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+/// The struct that an element is being built in, which the element's expressions are evaluated
+/// against
+struct Scope<'a> {
+    ctx: &'a ApplyContext<'a>,
+    // Nuggets built so far for the struct's elements
+    siblings: &'a [Nugget],
+    struct_start: usize,
+    // Where the element starts, unless it's placed elsewhere
+    pos: usize,
+}
+
+impl<'a> Env for Scope<'a> {
+    fn value(&self, name: &str) -> Option<i64> {
+        get_sibling_value(name, self.siblings)?.parse().ok()
+    }
+
+    fn end(&self) -> Option<i64> {
+        i64::try_from(self.ctx.file_data.len()).ok()
+    }
+
+    fn find_last(&self, signature: &[u8]) -> Option<i64> {
+        // An empty signature doesn't pick out anywhere in particular
+        if signature.is_empty() {
+            return None;
+        }
+        let mut found = self.ctx.found_signatures.borrow_mut();
+        let pos = match found.get(signature) {
+            Some(pos) => *pos,
+            None => {
+                let pos = self
+                    .ctx
+                    .file_data
+                    .windows(signature.len())
+                    .rposition(|window| window == signature);
+                found.insert(signature.to_vec(), pos);
+                pos
+            }
+        }?;
+        i64::try_from(pos).ok()
+    }
+
+    fn size_of(&self, typename: &str) -> Option<i64> {
+        i64::try_from(self.ctx.schema.static_size(typename)?).ok()
+    }
+
+    fn offset_of(&self, name: &str) -> Option<i64> {
+        let sibling = self.siblings.iter().find(|nugget| nugget.name == name)?;
+        i64::try_from(sibling.start)
+            .ok()?
+            .checked_sub(i64::try_from(self.struct_start).ok()?)
+    }
+
+    fn position(&self) -> Option<i64> {
+        i64::try_from(self.pos).ok()
+    }
+
+    fn struct_start(&self) -> Option<i64> {
+        i64::try_from(self.struct_start).ok()
+    }
+}
+
+pub fn apply_schema(schema: &TSchema, file_data: &[u8], options: &ApplyOptions) -> Nugget {
+    // We know the default root must exist, as we checked for it during the correctness checks
+    let root_name = &schema.roots[0];
+    apply_struct(schema, root_name, file_data, 0, options).unwrap()
+}
+
+/// Apply the struct `name` to `file_data`, starting at `offset`.  Returns None if there is no
+/// struct with that name, or the offset is past the end of the data.
+pub fn apply_struct(
+    schema: &TSchema,
+    name: &str,
+    file_data: &[u8],
+    offset: usize,
+    options: &ApplyOptions,
+) -> Option<Nugget> {
+    let struct_defn = schema.types.get(name)?;
+    if offset > file_data.len() {
+        return None;
+    }
+    let ctx = ApplyContext::new(schema, options, file_data);
+    Some(build_nugget(offset, struct_defn, name, &ctx, 0))
+}
+
+/// Apply entry `index` of the array element `element_name` in the struct `name`, with the struct
+/// starting at `offset`.  The elements before the array are decoded to find its length and where
+/// it starts, but the entries before `index` are not, so the array's entries must have a fixed
+/// size.  Returns None if there's no such struct or array, the entries vary in size, or the index
+/// is past the end of the array.
+pub fn apply_array_entry(
+    schema: &TSchema,
+    name: &str,
+    element_name: &str,
+    file_data: &[u8],
+    offset: usize,
+    index: usize,
+    options: &ApplyOptions,
+) -> Option<Nugget> {
+    let struct_defn = schema.types.get(name)?;
+    let elem_index = struct_defn
+        .elements
+        .iter()
+        .position(|element| element.name == element_name)?;
+    let element = &struct_defn.elements[elem_index];
+    let array_defn = match &element.kind {
+        ElementTypeRef::ArrayElem(array_defn) => array_defn,
+        _ => return None,
+    };
+    let entry_size = schema.static_elem_size(&array_defn.kind)?;
+    if offset > file_data.len() {
+        return None;
+    }
+
+    let ctx = ApplyContext::new(schema, options, file_data);
+    ctx.open_structs
+        .borrow_mut()
+        .push((name.to_string(), offset));
+    let (siblings, len) = build_elements(offset, struct_defn, &ctx, 0, elem_index);
+    let scope = Scope {
+        ctx: &ctx,
+        siblings: &siblings,
+        struct_start: offset,
+        pos: element_pos(offset, struct_defn, schema.layout(name), elem_index, len),
+    };
+    let start = match &element.placement {
+        Some(placement) => resolve_placement(placement, &scope)?,
+        None => scope.pos,
+    };
+    let arr_len = get_elem_size_value(&array_defn.length, &scope)?;
+    if index >= arr_len as usize {
+        return None;
+    }
+    let entry_start = start + index * entry_size;
+    let (entry, _) =
+        build_element_val(&array_defn.kind, entry_start, &index.to_string(), &scope, 0);
+    Some(entry)
 }
 
+/// Build the nugget for a struct.  `depth` is the number of structs it's nested inside.
 fn build_nugget(
     start: usize,
     struct_defn: &StructDefn,
     name: &str,
-    schema: &TSchema,
-    file_data: &[u8],
+    ctx: &ApplyContext,
+    depth: usize,
 ) -> Nugget {
-    let mut len = 0;
+    ctx.open_structs
+        .borrow_mut()
+        .push((struct_defn.name.clone(), start));
+    let (mut children, mut len) =
+        build_elements(start, struct_defn, ctx, depth, struct_defn.elements.len());
+    ctx.open_structs.borrow_mut().pop();
 
-    let mut children = Vec::new();
-    for element in &struct_defn.elements {
-        let (nugget, size) = match &element.kind {
-            ElementTypeRef::TypeName(typename) => {
-                build_single_val(typename, start + len, file_data, &element.name, schema)
-            }
-            ElementTypeRef::ArrayElem(array_defn) => build_array_val(
-                array_defn,
-                start + len,
-                file_data,
-                &element.name,
-                schema,
-                &children,
-            ),
-        };
-        len += size;
-        children.push(nugget);
+    if let Some(size) = ctx
+        .schema
+        .layout(&struct_defn.name)
+        .and_then(|layout| layout.size)
+    {
+        len = size;
+    }
+    if ctx.options.group_inherited {
+        children = group_inherited(start, struct_defn, children, ctx.schema);
     }
     Nugget {
         start,
         len,
         name: name.to_string(),
         value: None,
+        raw_value: None,
+        peek: false,
         children,
     }
 }
 
+/// Build the nuggets for the first `count` elements of a struct.  Also returns the number of bytes
+/// they take up in the struct.
+fn build_elements(
+    start: usize,
+    struct_defn: &StructDefn,
+    ctx: &ApplyContext,
+    depth: usize,
+    count: usize,
+) -> (Vec<Nugget>, usize) {
+    let layout = ctx.schema.layout(&struct_defn.name);
+    let mut len = 0;
+    let mut children = Vec::new();
+    for (i, element) in struct_defn.elements.iter().enumerate().take(count) {
+        let pos = element_pos(start, struct_defn, layout, i, len);
+        let scope = Scope {
+            ctx,
+            siblings: &children,
+            struct_start: start,
+            pos,
+        };
+        let elem_start = match &element.placement {
+            Some(placement) => match resolve_placement(placement, &scope) {
+                Some(elem_start) => elem_start,
+                None => {
+                    // Placed elements take up no space in the struct, so carry on without it
+                    children.push(placeholder(pos, &element.name, BAD_PLACEMENT_VALUE));
+                    continue;
+                }
+            },
+            None => scope.pos,
+        };
+        let (mut nugget, size) =
+            build_element_val(&element.kind, elem_start, &element.name, &scope, depth);
+        if element.peek {
+            // Peek elements are available to later elements, but the next element starts at the
+            // same place as this one
+            nugget.peek = true;
+        } else if element.placement.is_some() {
+            // Placed elements are elsewhere in the data, so take up no space in this struct
+        } else if struct_defn.is_union {
+            len = len.max(size);
+        } else {
+            len += size;
+        }
+        children.push(nugget);
+    }
+    (children, len)
+}
+
+/// Where element `index` of a struct starts, unless it's placed elsewhere.  `len` is the number of
+/// bytes taken up by the elements before it.
+fn element_pos(
+    start: usize,
+    struct_defn: &StructDefn,
+    layout: Option<&StructLayout>,
+    index: usize,
+    len: usize,
+) -> usize {
+    // Use the element's static offset if it has one, rather than relying on the sizes of the
+    // elements before it
+    match layout.and_then(|layout| layout.offsets[index]) {
+        Some(offset) => start + offset,
+        // Union elements all start at the beginning of the union
+        None if struct_defn.is_union => start,
+        None => start + len,
+    }
+}
+
+/// Move the nuggets for the elements a struct inherited from its base type into a single child
+/// nugget, named after the base type.  The base type's own inherited elements are grouped in the
+/// same way.
+fn group_inherited(
+    start: usize,
+    struct_defn: &StructDefn,
+    mut children: Vec<Nugget>,
+    schema: &TSchema,
+) -> Vec<Nugget> {
+    let base_name = match &struct_defn.base {
+        Some(base_name) => base_name,
+        None => return children,
+    };
+
+    let own_children = children.split_off(struct_defn.num_inherited);
+    // Base type must exist, as typechecking has passed for the schema
+    let base_defn = schema.types.get(base_name).unwrap();
+    let inherited = group_inherited(start, base_defn, children, schema);
+
+    let mut grouped = vec![Nugget {
+        start,
+        len: inherited.iter().map(|child| child.len).sum(),
+        name: base_name.to_string(),
+        value: None,
+        raw_value: None,
+        peek: false,
+        children: inherited,
+    }];
+    grouped.extend(own_children);
+    grouped
+}
+
+/// Build the nugget for a value of any type.  `scope` is the enclosing struct, which array lengths
+/// and expressions are evaluated in.
+fn build_element_val(
+    kind: &ElementTypeRef,
+    start: usize,
+    name: &str,
+    scope: &Scope,
+    depth: usize,
+) -> (Nugget, usize) {
+    let nuggets_left = scope.ctx.nuggets_left.get();
+    if nuggets_left == 0 {
+        return (placeholder(start, name, MAX_NUGGETS_VALUE), 0);
+    }
+    scope.ctx.nuggets_left.set(nuggets_left - 1);
+
+    match kind {
+        ElementTypeRef::TypeName(typename) => {
+            build_single_val(typename, start, name, scope.ctx, depth)
+        }
+        ElementTypeRef::ArrayElem(array_defn) => {
+            build_array_val(array_defn, start, name, scope, depth)
+        }
+        ElementTypeRef::Bytes(length) => build_bytes_val(length, start, name, scope),
+        ElementTypeRef::Computed(expr) => build_computed_val(expr, start, name, scope),
+    }
+}
+
+/// Build the nugget for a value calculated from earlier elements.  It has a value, but covers no
+/// bytes.  The value is None if the expression can't be evaluated, eg. due to a division by zero.
+fn build_computed_val(expr: &Expr, start: usize, name: &str, scope: &Scope) -> (Nugget, usize) {
+    let value = expr.eval(scope);
+    (
+        Nugget {
+            start,
+            len: 0,
+            name: name.to_string(),
+            value: value.map(|value| value.to_string()),
+            raw_value: None,
+            peek: false,
+            children: Vec::new(),
+        },
+        0,
+    )
+}
+
 fn build_single_val(
     typename: &str,
     start: usize,
-    file_data: &[u8],
     name: &str,
-    schema: &TSchema,
+    ctx: &ApplyContext,
+    depth: usize,
 ) -> (Nugget, usize) {
-    if builtin_types::is_builtin_type(typename) {
-        let elem_data = file_data.get(start..).unwrap();
+    if let Some(size) = builtin_types::get_size(typename) {
+        let elem_data = match ctx.file_data.get(start..).and_then(|data| data.get(..size)) {
+            Some(elem_data) => elem_data,
+            None => return (placeholder(start, name, PAST_END_VALUE), 0),
+        };
 
-        // is_builtin_type returned true, so this value must exist
+        // get_size returned a size, so this is a builtin type and the value must exist
         let (size, value) = builtin_types::get_value(elem_data, typename).unwrap();
         let child = Nugget {
-            start: start,
+            start,
             len: size,
             name: name.to_string(),
             value: Some(value),
+            raw_value: builtin_types::get_raw_value(elem_data, typename),
+            peek: false,
             children: Vec::new(),
         };
         (child, size)
+    } else if depth >= ctx.options.max_depth {
+        // Too deeply nested to expand any further, most likely because of a recursive type that
+        // doesn't terminate.  Leave an empty placeholder instead.
+        (placeholder(start, name, MAX_DEPTH_VALUE), 0)
+    } else if ctx
+        .open_structs
+        .borrow()
+        .iter()
+        .any(|(open_name, open_start)| open_name == typename && *open_start == start)
+    {
+        // The struct would be built exactly as the enclosing one is, and so on forever
+        (placeholder(start, name, RECURSIVE_VALUE), 0)
     } else {
         // Must exist, as typechecking has passed for the schema
-        let child_kind = schema.types.get(typename).unwrap();
-        let child = build_nugget(start, child_kind, name, schema, file_data);
+        let child_kind = ctx.schema.types.get(typename).unwrap();
+        let child = build_nugget(start, child_kind, name, ctx, depth + 1);
         let len = child.len;
         (child, len)
     }
@@ -88,73 +473,175 @@ fn build_single_val(
 fn build_array_val(
     array_defn: &parser::ArrayDefn,
     start: usize,
-    file_data: &[u8],
     name: &str,
-    schema: &TSchema,
-    siblings: &Vec<Nugget>,
+    scope: &Scope,
+    depth: usize,
 ) -> (Nugget, usize) {
     let mut children = Vec::new();
     let mut size = 0;
-    let value: Option<String>;
 
-    // Get the array len
-    let arr_len = get_elem_size_value(&array_defn.length, siblings).unwrap();
+    let arr_len = match get_elem_size_value(&array_defn.length, scope) {
+        Some(arr_len) => arr_len,
+        None => return (placeholder(start, name, BAD_LENGTH_VALUE), 0),
+    };
 
     // If we have a text type, then build up the individual characters into a single text string
-    if builtin_types::is_type_class(&array_defn.kind, BuiltinTypeClass::Text) {
+    let value = if let Some(text_type) = get_text_type(&array_defn.kind) {
         let mut text_value = String::new();
+        let char_size = builtin_types::get_size(text_type).unwrap();
         for _ in 0..arr_len {
-            let elem_data = file_data.get((start + size)..).unwrap();
-            let (char_size, char_value) =
-                builtin_types::get_value(elem_data, &array_defn.kind).unwrap();
-            text_value += &char_value;
-            size += char_size;
+            let char_start = start + size;
+            let elem_data = scope.ctx.file_data.get(char_start..char_start + char_size);
+            match elem_data {
+                Some(elem_data) => {
+                    let (_, char_value) = builtin_types::get_value(elem_data, text_type).unwrap();
+                    text_value += &char_value;
+                    size += char_size;
+                }
+                None => {
+                    // Stop at the end of the data
+                    mark_truncated(&mut text_value);
+                    break;
+                }
+            }
         }
-        value = Some(text_value)
+        Some(text_value)
     } else {
-        // Otherwise, treat each array entry individually
+        // Otherwise, treat each array entry individually.  Entries with a fixed size are found
+        // directly from their index.
+        let entry_size = scope.ctx.schema.static_elem_size(&array_defn.kind);
         for i in 0..arr_len {
             let child_name = i.to_string();
-            let (child, len) = build_single_val(
-                &array_defn.kind,
-                start + size,
-                file_data,
-                &child_name,
-                schema,
-            );
+            let entry_start = match entry_size {
+                Some(entry_size) => start + i as usize * entry_size,
+                None => start + size,
+            };
+            let (child, len) =
+                build_element_val(&array_defn.kind, entry_start, &child_name, scope, depth);
             children.push(child);
-            size += len;
+            size = entry_start + len - start;
+            if scope.ctx.nuggets_left.get() == 0 {
+                // Don't add a placeholder for every remaining entry of a huge array
+                break;
+            }
         }
-        value = None;
-    }
+        None
+    };
     (
         Nugget {
             start,
             len: size,
             name: name.to_string(),
             value,
+            raw_value: None,
+            peek: false,
             children,
         },
         size,
     )
 }
 
-fn get_elem_size_value(len: &ArrayLen, nuggets: &Vec<Nugget>) -> Option<u32> {
+/// If `kind` is a builtin text type, return its name
+fn get_text_type(kind: &ElementTypeRef) -> Option<&str> {
+    match kind {
+        ElementTypeRef::TypeName(typename)
+            if builtin_types::is_type_class(typename, BuiltinTypeClass::Text) =>
+        {
+            Some(typename)
+        }
+        _ => None,
+    }
+}
+
+/// Build a single leaf nugget covering a block of bytes.  The value is a hex preview of the start of
+/// the block.  Blocks that run past the end of the data only cover the bytes that are there.
+fn build_bytes_val(length: &ArrayLen, start: usize, name: &str, scope: &Scope) -> (Nugget, usize) {
+    let (size, value) = match get_elem_size_value(length, scope) {
+        Some(size) => {
+            let available = scope.ctx.file_data.len().saturating_sub(start);
+            let truncated = size as usize > available;
+            let size = (size as usize).min(available);
+
+            let block = scope.ctx.file_data.get(start..start + size).unwrap_or(&[]);
+            let preview: Vec<String> = block
+                .iter()
+                .take(BYTES_PREVIEW_LEN)
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            let mut value = preview.join(" ");
+            if size > BYTES_PREVIEW_LEN {
+                value += " ...";
+            }
+            if truncated {
+                mark_truncated(&mut value);
+            }
+            (size, value)
+        }
+        None => (0, BAD_LENGTH_VALUE.to_string()),
+    };
+
+    (
+        Nugget {
+            start,
+            len: size,
+            name: name.to_string(),
+            value: Some(value),
+            raw_value: None,
+            peek: false,
+            children: Vec::new(),
+        },
+        size,
+    )
+}
+
+/// Add a note to a value that it was cut short by the end of the data
+fn mark_truncated(value: &mut String) {
+    if !value.is_empty() {
+        value.push(' ');
+    }
+    value.push_str(TRUNCATED_VALUE);
+}
+
+/// Leaf nugget for something that couldn't be decoded, with `value` saying why.  It covers no
+/// bytes.
+fn placeholder(start: usize, name: &str, value: &str) -> Nugget {
+    Nugget {
+        start,
+        len: 0,
+        name: name.to_string(),
+        value: Some(value.to_string()),
+        raw_value: None,
+        peek: false,
+        children: Vec::new(),
+    }
+}
+
+fn get_elem_size_value(len: &ArrayLen, scope: &Scope) -> Option<u32> {
     match len {
         ArrayLen::Identifier(name) => {
-            // Simple linear search among sibling nuggets for referenced value
-            for nugget in nuggets {
-                if nugget.name == *name {
-                    let value = &nugget.value.as_ref().unwrap();
-                    return Some(value.parse::<u32>().unwrap());
-                }
-            }
-            None
+            // Negative values, and computed values that couldn't be evaluated, aren't lengths
+            let value = get_sibling_value(name, scope.siblings)?;
+            value.parse::<u32>().ok()
         }
         ArrayLen::Static(i) => Some(*i),
+        ArrayLen::Expr(expr) => u32::try_from(expr.eval(scope)?).ok(),
     }
 }
 
+/// Value of the sibling nugget called `name`
+fn get_sibling_value<'a>(name: &str, nuggets: &'a [Nugget]) -> Option<&'a str> {
+    // Simple linear search among sibling nuggets for referenced value
+    let nugget = nuggets.iter().find(|nugget| nugget.name == name)?;
+    nugget.value.as_deref()
+}
+
+/// Find the offset in the data that an element is placed at.  Returns None if the placement is
+/// outside the data, or can't be evaluated, eg. because the signature it searches for isn't found.
+fn resolve_placement(placement: &Expr, scope: &Scope) -> Option<usize> {
+    let pos = usize::try_from(placement.eval(scope)?).ok()?;
+    Some(pos).filter(|pos| *pos <= scope.ctx.file_data.len())
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -164,7 +651,7 @@ mod test {
     fn u8_struct() {
         let schema =
             compile_schema_file("struct root {val1: int8, val2: int8, val3: int8}").unwrap();
-        let res = apply_schema(&schema, b"\x00\x01\x02");
+        let res = apply_schema(&schema, b"\x00\x01\x02", &ApplyOptions::default());
         assert_eq!(
             res,
             Nugget {
@@ -172,12 +659,16 @@ mod test {
                 len: 3,
                 name: "root".to_string(),
                 value: None,
+                raw_value: None,
+                peek: false,
                 children: vec![
                     Nugget {
                         start: 0,
                         len: 1,
                         name: "val1".to_string(),
                         value: Some("0".to_string()),
+                        raw_value: None,
+                        peek: false,
                         children: Vec::new(),
                     },
                     Nugget {
@@ -185,6 +676,8 @@ mod test {
                         len: 1,
                         name: "val2".to_string(),
                         value: Some("1".to_string()),
+                        raw_value: None,
+                        peek: false,
                         children: Vec::new(),
                     },
                     Nugget {
@@ -192,6 +685,8 @@ mod test {
                         len: 1,
                         name: "val3".to_string(),
                         value: Some("2".to_string()),
+                        raw_value: None,
+                        peek: false,
                         children: Vec::new(),
                     }
                 ],
@@ -203,7 +698,7 @@ mod test {
     fn u8_i16_struct() {
         let schema =
             compile_schema_file("struct root {val1: int8, val2: int16_le, val3: int8}").unwrap();
-        let res = apply_schema(&schema, b"\x00\x01\x00\x02");
+        let res = apply_schema(&schema, b"\x00\x01\x00\x02", &ApplyOptions::default());
         assert_eq!(
             res,
             Nugget {
@@ -211,12 +706,16 @@ mod test {
                 len: 4,
                 name: "root".to_string(),
                 value: None,
+                raw_value: None,
+                peek: false,
                 children: vec![
                     Nugget {
                         start: 0,
                         len: 1,
                         name: "val1".to_string(),
                         value: Some("0".to_string()),
+                        raw_value: None,
+                        peek: false,
                         children: Vec::new(),
                     },
                     Nugget {
@@ -224,6 +723,8 @@ mod test {
                         len: 2,
                         name: "val2".to_string(),
                         value: Some("1".to_string()),
+                        raw_value: None,
+                        peek: false,
                         children: Vec::new(),
                     },
                     Nugget {
@@ -231,6 +732,8 @@ mod test {
                         len: 1,
                         name: "val3".to_string(),
                         value: Some("2".to_string()),
+                        raw_value: None,
+                        peek: false,
                         children: Vec::new(),
                     }
                 ],
@@ -242,7 +745,7 @@ mod test {
     fn child_structs() {
         let schema =
             compile_schema_file("struct root {version1: Version, version2: Version} struct Version {major: int8, minor: int8}").unwrap();
-        let res = apply_schema(&schema, b"\x00\x01\x02\x03");
+        let res = apply_schema(&schema, b"\x00\x01\x02\x03", &ApplyOptions::default());
         assert_eq!(
             res,
             Nugget {
@@ -250,18 +753,24 @@ mod test {
                 len: 4,
                 name: "root".to_string(),
                 value: None,
+                raw_value: None,
+                peek: false,
                 children: vec![
                     Nugget {
                         start: 0,
                         len: 2,
                         name: "version1".to_string(),
                         value: None,
+                        raw_value: None,
+                        peek: false,
                         children: vec![
                             Nugget {
                                 start: 0,
                                 len: 1,
                                 name: "major".to_string(),
                                 value: Some("0".to_string()),
+                                raw_value: None,
+                                peek: false,
                                 children: Vec::new(),
                             },
                             Nugget {
@@ -269,6 +778,8 @@ mod test {
                                 len: 1,
                                 name: "minor".to_string(),
                                 value: Some("1".to_string()),
+                                raw_value: None,
+                                peek: false,
                                 children: Vec::new(),
                             }
                         ]
@@ -278,12 +789,16 @@ mod test {
                         len: 2,
                         name: "version2".to_string(),
                         value: None,
+                        raw_value: None,
+                        peek: false,
                         children: vec![
                             Nugget {
                                 start: 2,
                                 len: 1,
                                 name: "major".to_string(),
                                 value: Some("2".to_string()),
+                                raw_value: None,
+                                peek: false,
                                 children: Vec::new(),
                             },
                             Nugget {
@@ -291,6 +806,8 @@ mod test {
                                 len: 1,
                                 name: "minor".to_string(),
                                 value: Some("3".to_string()),
+                                raw_value: None,
+                                peek: false,
                                 children: Vec::new(),
                             }
                         ]
@@ -303,7 +820,7 @@ mod test {
     #[test]
     fn array() {
         let schema = compile_schema_file("struct root {len: int8, arr: [uint8; len]}").unwrap();
-        let res = apply_schema(&schema, b"\x02\x00\x01");
+        let res = apply_schema(&schema, b"\x02\x00\x01", &ApplyOptions::default());
         assert_eq!(
             res,
             Nugget {
@@ -311,12 +828,16 @@ mod test {
                 len: 3,
                 name: "root".to_string(),
                 value: None,
+                raw_value: None,
+                peek: false,
                 children: vec![
                     Nugget {
                         start: 0,
                         len: 1,
                         name: "len".to_string(),
                         value: Some("2".to_string()),
+                        raw_value: None,
+                        peek: false,
                         children: Vec::new(),
                     },
                     Nugget {
@@ -324,12 +845,16 @@ mod test {
                         len: 2,
                         name: "arr".to_string(),
                         value: None,
+                        raw_value: None,
+                        peek: false,
                         children: vec![
                             Nugget {
                                 start: 1,
                                 len: 1,
                                 name: "0".to_string(),
                                 value: Some("0".to_string()),
+                                raw_value: None,
+                                peek: false,
                                 children: Vec::new(),
                             },
                             Nugget {
@@ -337,6 +862,8 @@ mod test {
                                 len: 1,
                                 name: "1".to_string(),
                                 value: Some("1".to_string()),
+                                raw_value: None,
+                                peek: false,
                                 children: Vec::new(),
                             }
                         ],
@@ -346,7 +873,7 @@ mod test {
         );
 
         // Same schema, zero length array
-        let res = apply_schema(&schema, b"\x00");
+        let res = apply_schema(&schema, b"\x00", &ApplyOptions::default());
         assert_eq!(
             res,
             Nugget {
@@ -354,12 +881,16 @@ mod test {
                 len: 1,
                 name: "root".to_string(),
                 value: None,
+                raw_value: None,
+                peek: false,
                 children: vec![
                     Nugget {
                         start: 0,
                         len: 1,
                         name: "len".to_string(),
                         value: Some("0".to_string()),
+                        raw_value: None,
+                        peek: false,
                         children: Vec::new(),
                     },
                     Nugget {
@@ -367,10 +898,897 @@ mod test {
                         len: 0,
                         name: "arr".to_string(),
                         value: None,
+                        raw_value: None,
+                        peek: false,
+                        children: Vec::new(),
+                    }
+                ]
+            }
+        );
+    }
+
+    #[test]
+    fn timestamp() {
+        let schema = compile_schema_file("struct root {created: unix32_le}").unwrap();
+        let res = apply_schema(&schema, b"\x00\xe1\x0b\x5e", &ApplyOptions::default());
+        assert_eq!(
+            res,
+            Nugget {
+                start: 0,
+                len: 4,
+                name: "root".to_string(),
+                value: None,
+                raw_value: None,
+                peek: false,
+                children: vec![Nugget {
+                    start: 0,
+                    len: 4,
+                    name: "created".to_string(),
+                    value: Some("2020-01-01T00:00:00Z".to_string()),
+                    raw_value: Some("1577836800".to_string()),
+                    peek: false,
+                    children: Vec::new(),
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn bytes() {
+        let schema =
+            compile_schema_file("struct root {len: uint8, data: bytes[len], tail: bytes[2]}")
+                .unwrap();
+        let mut data = vec![20];
+        data.extend(0..22);
+        let res = apply_schema(&schema, &data, &ApplyOptions::default());
+        assert_eq!(
+            res,
+            Nugget {
+                start: 0,
+                len: 23,
+                name: "root".to_string(),
+                value: None,
+                raw_value: None,
+                peek: false,
+                children: vec![
+                    Nugget {
+                        start: 0,
+                        len: 1,
+                        name: "len".to_string(),
+                        value: Some("20".to_string()),
+                        raw_value: None,
+                        peek: false,
+                        children: Vec::new(),
+                    },
+                    Nugget {
+                        start: 1,
+                        len: 20,
+                        name: "data".to_string(),
+                        value: Some(
+                            "00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f ...".to_string()
+                        ),
+                        raw_value: None,
+                        peek: false,
+                        children: Vec::new(),
+                    },
+                    Nugget {
+                        start: 21,
+                        len: 2,
+                        name: "tail".to_string(),
+                        value: Some("14 15".to_string()),
+                        raw_value: None,
+                        peek: false,
                         children: Vec::new(),
                     }
                 ]
             }
         );
     }
+
+    #[test]
+    fn bad_bytes_len() {
+        let schema =
+            compile_schema_file("struct root {len: uint8, data: bytes[len - 4], rest: bytes[len]}")
+                .unwrap();
+        let res = apply_schema(&schema, b"\x02\xaa\xbb", &ApplyOptions::default());
+        let values: Vec<(usize, usize, Option<&str>)> = res
+            .children
+            .iter()
+            .map(|child| (child.start, child.len, child.value.as_deref()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                (0, 1, Some("2")),
+                (1, 0, Some(BAD_LENGTH_VALUE)),
+                (1, 2, Some("aa bb")),
+            ]
+        );
+
+        // Only the bytes in the data are covered
+        let res = apply_schema(&schema, b"\x07\xaa\xbb", &ApplyOptions::default());
+        assert_eq!(res.len, 3);
+        assert_eq!(res.children[1].len, 2);
+        assert_eq!(res.children[1].value, Some("aa bb <truncated>".to_string()));
+        assert_eq!(res.children[2].start, 3);
+        assert_eq!(res.children[2].len, 0);
+        assert_eq!(res.children[2].value, Some(TRUNCATED_VALUE.to_string()));
+    }
+
+    #[test]
+    fn bad_array_len() {
+        let schema = compile_schema_file(
+            "struct root {n: uint8, a: [uint8; n - 10], b: [ascii; n / (n - 2)], c: [[uint8; 1]; n - 3]}",
+        )
+        .unwrap();
+        let res = apply_schema(&schema, b"\x02\xaa\xbb", &ApplyOptions::default());
+        assert_eq!(res.len, 1);
+        for child in &res.children[1..] {
+            assert_eq!(child.start, 1);
+            assert_eq!(child.len, 0);
+            assert_eq!(child.value, Some(BAD_LENGTH_VALUE.to_string()));
+            assert!(child.children.is_empty());
+        }
+
+        // Lengths taken from negative or missing values
+        let schema = compile_schema_file(
+            "struct root {
+                n: uint8,
+                s: int8,
+                let m = n - 10;
+                let z = n / (n - 2);
+                a: [uint8; m],
+                b: bytes[m],
+                c: [uint8; s],
+                d: [ascii; z],
+            }",
+        )
+        .unwrap();
+        let res = apply_schema(&schema, b"\x02\xfe\xaa\xbb", &ApplyOptions::default());
+        assert_eq!(res.len, 2);
+        for child in &res.children[4..] {
+            assert_eq!(child.start, 2);
+            assert_eq!(child.len, 0);
+            assert_eq!(child.value, Some(BAD_LENGTH_VALUE.to_string()));
+        }
+    }
+
+    #[test]
+    fn nested_array() {
+        let schema = compile_schema_file(
+            "struct root {width: uint8, rows: [[uint8; width]; 2], names: [[ascii; 2]; 1]}",
+        )
+        .unwrap();
+        let res = apply_schema(&schema, b"\x02\x00\x01\x02\x03ab", &ApplyOptions::default());
+        let leaf = |start: usize, name: &str, value: &str| Nugget {
+            start,
+            len: 1,
+            name: name.to_string(),
+            value: Some(value.to_string()),
+            raw_value: None,
+            peek: false,
+            children: Vec::new(),
+        };
+        let row = |start: usize, name: &str, children: Vec<Nugget>| Nugget {
+            start,
+            len: children.iter().map(|c| c.len).sum(),
+            name: name.to_string(),
+            value: None,
+            raw_value: None,
+            peek: false,
+            children,
+        };
+        assert_eq!(
+            res,
+            row(
+                0,
+                "root",
+                vec![
+                    leaf(0, "width", "2"),
+                    row(
+                        1,
+                        "rows",
+                        vec![
+                            row(1, "0", vec![leaf(1, "0", "0"), leaf(2, "1", "1")]),
+                            row(3, "1", vec![leaf(3, "0", "2"), leaf(4, "1", "3")]),
+                        ]
+                    ),
+                    row(
+                        5,
+                        "names",
+                        vec![Nugget {
+                            start: 5,
+                            len: 2,
+                            name: "0".to_string(),
+                            value: Some("ab".to_string()),
+                            raw_value: None,
+                            peek: false,
+                            children: Vec::new(),
+                        }]
+                    ),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn inline_struct() {
+        let schema =
+            compile_schema_file("struct root {header: struct {major: int8, minor: int8}}").unwrap();
+        let res = apply_schema(&schema, b"\x01\x02", &ApplyOptions::default());
+        assert_eq!(
+            res,
+            Nugget {
+                start: 0,
+                len: 2,
+                name: "root".to_string(),
+                value: None,
+                raw_value: None,
+                peek: false,
+                children: vec![Nugget {
+                    start: 0,
+                    len: 2,
+                    name: "header".to_string(),
+                    value: None,
+                    raw_value: None,
+                    peek: false,
+                    children: vec![
+                        Nugget {
+                            start: 0,
+                            len: 1,
+                            name: "major".to_string(),
+                            value: Some("1".to_string()),
+                            raw_value: None,
+                            peek: false,
+                            children: Vec::new(),
+                        },
+                        Nugget {
+                            start: 1,
+                            len: 1,
+                            name: "minor".to_string(),
+                            value: Some("2".to_string()),
+                            raw_value: None,
+                            peek: false,
+                            children: Vec::new(),
+                        }
+                    ]
+                }]
+            }
+        );
+    }
+
+    #[test]
+    fn declared_root() {
+        let schema = compile_schema_file(
+            "root Header; struct Header {val: int8} struct Chunk {val: int16_be}",
+        )
+        .unwrap();
+        let res = apply_schema(&schema, b"\x01\x02\x03", &ApplyOptions::default());
+        assert_eq!(
+            res,
+            Nugget {
+                start: 0,
+                len: 1,
+                name: "Header".to_string(),
+                value: None,
+                raw_value: None,
+                peek: false,
+                children: vec![Nugget {
+                    start: 0,
+                    len: 1,
+                    name: "val".to_string(),
+                    value: Some("1".to_string()),
+                    raw_value: None,
+                    peek: false,
+                    children: Vec::new(),
+                }],
+            }
+        );
+
+        let res = apply_struct(
+            &schema,
+            "Chunk",
+            b"\x01\x02\x03",
+            1,
+            &ApplyOptions::default(),
+        );
+        assert_eq!(
+            res,
+            Some(Nugget {
+                start: 1,
+                len: 2,
+                name: "Chunk".to_string(),
+                value: None,
+                raw_value: None,
+                peek: false,
+                children: vec![Nugget {
+                    start: 1,
+                    len: 2,
+                    name: "val".to_string(),
+                    value: Some("515".to_string()),
+                    raw_value: None,
+                    peek: false,
+                    children: Vec::new(),
+                }],
+            })
+        );
+
+        assert_eq!(
+            apply_struct(&schema, "Unknown", b"\x01", 0, &ApplyOptions::default()),
+            None
+        );
+        assert_eq!(
+            apply_struct(&schema, "Chunk", b"\x01", 2, &ApplyOptions::default()),
+            None
+        );
+        assert_eq!(
+            apply_struct(
+                &schema,
+                "Chunk",
+                b"\x01",
+                usize::MAX,
+                &ApplyOptions::default()
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn inherited() {
+        let schema = compile_schema_file(
+            "struct root {header: V5}
+            struct Base {size: uint8}
+            struct V4 extends Base {red: uint8}
+            struct V5 extends V4 {data: [uint8; size]}",
+        )
+        .unwrap();
+        let leaf = |start: usize, name: &str, value: &str| Nugget {
+            start,
+            len: 1,
+            name: name.to_string(),
+            value: Some(value.to_string()),
+            raw_value: None,
+            peek: false,
+            children: Vec::new(),
+        };
+        let group = |start: usize, name: &str, children: Vec<Nugget>| Nugget {
+            start,
+            len: children.iter().map(|c| c.len).sum(),
+            name: name.to_string(),
+            value: None,
+            raw_value: None,
+            peek: false,
+            children,
+        };
+        let data = b"\x01\x02\x03";
+
+        let res = apply_schema(&schema, data, &ApplyOptions::default());
+        assert_eq!(
+            res,
+            group(
+                0,
+                "root",
+                vec![group(
+                    0,
+                    "header",
+                    vec![
+                        leaf(0, "size", "1"),
+                        leaf(1, "red", "2"),
+                        group(2, "data", vec![leaf(2, "0", "3")]),
+                    ]
+                )]
+            )
+        );
+
+        let options = ApplyOptions {
+            group_inherited: true,
+            ..ApplyOptions::default()
+        };
+        let res = apply_schema(&schema, data, &options);
+        assert_eq!(
+            res,
+            group(
+                0,
+                "root",
+                vec![group(
+                    0,
+                    "header",
+                    vec![
+                        group(
+                            0,
+                            "V4",
+                            vec![
+                                group(0, "Base", vec![leaf(0, "size", "1")]),
+                                leaf(1, "red", "2")
+                            ]
+                        ),
+                        group(2, "data", vec![leaf(2, "0", "3")]),
+                    ]
+                )]
+            )
+        );
+    }
+
+    #[test]
+    fn union() {
+        let schema = compile_schema_file(
+            "struct root {val: union {as_int: uint16_le, as_bytes: [uint8; 2], low: uint8}, next: int8}",
+        )
+        .unwrap();
+        let res = apply_schema(&schema, b"\x01\x02\x03", &ApplyOptions::default());
+        let leaf = |start: usize, len: usize, name: &str, value: &str| Nugget {
+            start,
+            len,
+            name: name.to_string(),
+            value: Some(value.to_string()),
+            raw_value: None,
+            peek: false,
+            children: Vec::new(),
+        };
+        let group = |start: usize, name: &str, len: usize, children: Vec<Nugget>| Nugget {
+            start,
+            len,
+            name: name.to_string(),
+            value: None,
+            raw_value: None,
+            peek: false,
+            children,
+        };
+        assert_eq!(
+            res,
+            group(
+                0,
+                "root",
+                3,
+                vec![
+                    group(
+                        0,
+                        "val",
+                        2,
+                        vec![
+                            leaf(0, 2, "as_int", "513"),
+                            group(
+                                0,
+                                "as_bytes",
+                                2,
+                                vec![leaf(0, 1, "0", "1"), leaf(1, 1, "1", "2")]
+                            ),
+                            leaf(0, 1, "low", "1"),
+                        ]
+                    ),
+                    leaf(2, 1, "next", "3"),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn peek() {
+        let schema = compile_schema_file(
+            "struct root {peek len: uint8, body: Body} struct Body {len: uint8, data: bytes[len]}",
+        )
+        .unwrap();
+        let res = apply_schema(&schema, b"\x01\xff", &ApplyOptions::default());
+        assert_eq!(
+            res,
+            Nugget {
+                start: 0,
+                len: 2,
+                name: "root".to_string(),
+                value: None,
+                raw_value: None,
+                peek: false,
+                children: vec![
+                    Nugget {
+                        start: 0,
+                        len: 1,
+                        name: "len".to_string(),
+                        value: Some("1".to_string()),
+                        raw_value: None,
+                        peek: true,
+                        children: Vec::new(),
+                    },
+                    Nugget {
+                        start: 0,
+                        len: 2,
+                        name: "body".to_string(),
+                        value: None,
+                        raw_value: None,
+                        peek: false,
+                        children: vec![
+                            Nugget {
+                                start: 0,
+                                len: 1,
+                                name: "len".to_string(),
+                                value: Some("1".to_string()),
+                                raw_value: None,
+                                peek: false,
+                                children: Vec::new(),
+                            },
+                            Nugget {
+                                start: 1,
+                                len: 1,
+                                name: "data".to_string(),
+                                value: Some("ff".to_string()),
+                                raw_value: None,
+                                peek: false,
+                                children: Vec::new(),
+                            }
+                        ],
+                    }
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn peek_array_len() {
+        let schema =
+            compile_schema_file("struct root {peek count: uint8, arr: [uint8; count]}").unwrap();
+        let res = apply_schema(&schema, b"\x02\x05", &ApplyOptions::default());
+        assert_eq!(res.len, 2);
+        assert_eq!(res.children[1].start, 0);
+        assert_eq!(res.children[1].children[1].value, Some("5".to_string()));
+    }
+
+    #[test]
+    fn placement() {
+        let schema = compile_schema_file(
+            "struct root {
+                first: uint8,
+                trailer: Trailer @ end - 2,
+                tag: uint8 @ rfind(0xaa, 0xbb) + 1,
+                second: uint8,
+            }
+            struct Trailer {offset: uint8, val: uint8, at_offset: uint8 @ offset}",
+        )
+        .unwrap();
+        let res = apply_schema(
+            &schema,
+            b"\x01\x02\xaa\xbb\x07\xaa\x03\x09",
+            &ApplyOptions::default(),
+        );
+        assert_eq!(res.len, 2);
+        let starts: Vec<usize> = res.children.iter().map(|child| child.start).collect();
+        assert_eq!(starts, vec![0, 6, 3, 1]);
+
+        let trailer = &res.children[1];
+        assert_eq!(trailer.len, 2);
+        assert_eq!(trailer.children[2].start, 3);
+        assert_eq!(trailer.children[2].value, Some("187".to_string()));
+        assert_eq!(res.children[2].value, Some("187".to_string()));
+        assert_eq!(res.children[3].value, Some("2".to_string()));
+    }
+
+    #[test]
+    fn find_last() {
+        let schema = compile_schema_file("struct root {a: uint8}").unwrap();
+        let options = ApplyOptions::default();
+        let ctx = ApplyContext::new(&schema, &options, b"\xaa\xbb\xaa\xbb\x01");
+        let scope = Scope {
+            ctx: &ctx,
+            siblings: &[],
+            struct_start: 0,
+            pos: 0,
+        };
+        assert_eq!(scope.find_last(&[0xaa, 0xbb]), Some(2));
+        assert_eq!(scope.find_last(&[0xcc]), None);
+        assert_eq!(scope.find_last(&[]), None);
+
+        // Each signature is only searched for once
+        assert_eq!(ctx.found_signatures.borrow().len(), 2);
+        assert_eq!(scope.find_last(&[0xaa, 0xbb]), Some(2));
+        assert_eq!(ctx.found_signatures.borrow().len(), 2);
+    }
+
+    #[test]
+    fn placement_outside_data() {
+        let schema = compile_schema_file(
+            "struct root {
+                first: uint8,
+                missing: uint8 @ rfind(0xaa, 0xbb),
+                before_start: uint8 @ end - 10,
+                at_end: uint16_le @ end,
+                near_end: uint16_le @ end - 1,
+                text: [ascii; 4] @ end - 2,
+                second: uint8,
+            }",
+        )
+        .unwrap();
+        let res = apply_schema(&schema, b"\x01\x02\x61\x62", &ApplyOptions::default());
+        assert_eq!(res.len, 2);
+        let values: Vec<(usize, usize, Option<&str>)> = res
+            .children
+            .iter()
+            .map(|child| (child.start, child.len, child.value.as_deref()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                (0, 1, Some("1")),
+                (1, 0, Some(BAD_PLACEMENT_VALUE)),
+                (1, 0, Some(BAD_PLACEMENT_VALUE)),
+                (4, 0, Some(PAST_END_VALUE)),
+                (3, 0, Some(PAST_END_VALUE)),
+                (2, 2, Some("ab <truncated>")),
+                (1, 1, Some("2")),
+            ]
+        );
+
+        // Structs can be applied at the very end of the data, where nothing fits
+        let res = apply_struct(&schema, "root", b"\x01", 1, &ApplyOptions::default()).unwrap();
+        assert_eq!(res.children[0].value.as_deref(), Some(PAST_END_VALUE));
+        assert_eq!(res.children[5].value.as_deref(), Some(BAD_PLACEMENT_VALUE));
+        assert_eq!(res.children[6].value.as_deref(), Some(PAST_END_VALUE));
+    }
+
+    #[test]
+    fn recursive_tree() {
+        let schema = compile_schema_file(
+            "struct root {tree: Node}
+            struct Node {value: uint8, count: uint8, children: [Node; count]}",
+        )
+        .unwrap();
+        let res = apply_schema(
+            &schema,
+            b"\x01\x02\x02\x00\x03\x01\x04\x00",
+            &ApplyOptions::default(),
+        );
+        let tree = &res.children[0];
+        assert_eq!(tree.len, 8);
+        let children = &tree.children[2].children;
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[0].children[0].value, Some("2".to_string()));
+        assert_eq!(children[1].start, 4);
+        let grandchild = &children[1].children[2].children[0];
+        assert_eq!(grandchild.start, 6);
+        assert_eq!(grandchild.children[0].value, Some("4".to_string()));
+    }
+
+    #[test]
+    fn recursion_max_depth() {
+        // A linked list where each entry points to the one after it
+        let schema = compile_schema_file(
+            "struct root {head: Node}
+            struct Node {step: uint8, next: Node @ _struct_start + step}",
+        )
+        .unwrap();
+        let options = ApplyOptions {
+            max_depth: 3,
+            ..ApplyOptions::default()
+        };
+        let res = apply_schema(&schema, &[1; 8], &options);
+
+        let mut depth = 1;
+        let mut node = &res.children[0];
+        while node.value.is_none() {
+            node = &node.children[1];
+            depth += 1;
+        }
+        assert_eq!(depth, 4);
+        assert_eq!(node.value, Some(MAX_DEPTH_VALUE.to_string()));
+        assert_eq!(node.len, 0);
+        assert!(node.children.is_empty());
+    }
+
+    #[test]
+    fn recursion_loop() {
+        // A linked list whose last entry points back to the first
+        let schema = compile_schema_file(
+            "struct root {head: Node}
+            struct Node {value: uint8, next_offset: uint8, next: Node @ next_offset}",
+        )
+        .unwrap();
+        let res = apply_schema(&schema, b"\x01\x02\x02\x00", &ApplyOptions::default());
+        let second = &res.children[0].children[2];
+        assert_eq!(second.start, 2);
+        let looped = &second.children[2];
+        assert_eq!(looped.start, 0);
+        assert_eq!(looped.value, Some(RECURSIVE_VALUE.to_string()));
+        assert!(looped.children.is_empty());
+
+        // Each struct contains two copies of itself at the same offset
+        let schema =
+            compile_schema_file("struct root {o: uint8, a: root @ o, b: root @ o}").unwrap();
+        let res = apply_schema(&schema, b"\x00", &ApplyOptions::default());
+        assert_eq!(res.children[1].value, Some(RECURSIVE_VALUE.to_string()));
+        assert_eq!(res.children[2].value, Some(RECURSIVE_VALUE.to_string()));
+    }
+
+    #[test]
+    fn max_nuggets() {
+        // Every node contains two copies of the next one, so the number of nuggets doubles with
+        // each level
+        let schema = compile_schema_file(
+            "struct root {head: Node}
+            struct Node {o: uint8, a: Node @ _struct_start + o, b: Node @ _struct_start + o}",
+        )
+        .unwrap();
+        let options = ApplyOptions {
+            max_nuggets: 1000,
+            ..ApplyOptions::default()
+        };
+        let res = apply_schema(&schema, &[1; 40], &options);
+
+        fn count(nugget: &Nugget) -> usize {
+            1 + nugget.children.iter().map(count).sum::<usize>()
+        }
+        fn limited(nugget: &Nugget) -> bool {
+            nugget.value.as_deref() == Some(MAX_NUGGETS_VALUE)
+                || nugget.children.iter().any(limited)
+        }
+        // Elements past the limit are left as placeholders, so there can be a few more nuggets
+        // than the limit
+        assert!(count(&res) < 2000);
+        assert!(limited(&res));
+
+        // Huge arrays stop once the limit is reached
+        let schema = compile_schema_file("struct root {n: uint32_le, a: [uint8; n]}").unwrap();
+        let res = apply_schema(&schema, b"\xff\xff\xff\xff", &options);
+        // n and the array itself take two of the nuggets
+        assert_eq!(res.children[1].children.len(), 998);
+    }
+
+    #[test]
+    fn element_named_end() {
+        // Earlier elements take the place of the builtin values with the same name
+        let schema = compile_schema_file(
+            "struct root {end: uint8, data: [uint8; end], last: uint8 @ end, tail: uint8 @ _pos}",
+        )
+        .unwrap();
+        let res = apply_schema(&schema, b"\x02\xaa\xbb\xcc", &ApplyOptions::default());
+        assert_eq!(res.children[1].len, 2);
+        assert_eq!(res.children[2].value, Some("187".to_string()));
+        assert_eq!(res.children[3].value, Some("204".to_string()));
+    }
+
+    #[test]
+    fn computed() {
+        let schema = compile_schema_file(
+            "struct root {
+                width: uint8,
+                height: uint8,
+                let num_pixels = width * height;
+                let num_bytes = num_pixels * 2 + 1;
+                pixels: bytes[num_bytes],
+                let ratio = width / (height - 2);
+                end: uint8,
+            }",
+        )
+        .unwrap();
+        let res = apply_schema(
+            &schema,
+            b"\x02\x02\x00\x01\x02\x03\x04\x05\x06\x07\x08\x09",
+            &ApplyOptions::default(),
+        );
+        assert_eq!(res.len, 12);
+        assert_eq!(
+            res.children[2],
+            Nugget {
+                start: 2,
+                len: 0,
+                name: "num_pixels".to_string(),
+                value: Some("4".to_string()),
+                raw_value: None,
+                peek: false,
+                children: Vec::new(),
+            }
+        );
+        assert_eq!(res.children[3].value, Some("9".to_string()));
+        assert_eq!(res.children[4].len, 9);
+        // Division by zero leaves the value empty
+        assert_eq!(res.children[5].start, 11);
+        assert_eq!(res.children[5].value, None);
+        assert_eq!(res.children[6].value, Some("9".to_string()));
+    }
+
+    #[test]
+    fn fixed_size_records() {
+        let schema = compile_schema_file(
+            "struct root {count: uint8, records: [Record; count], tail: uint8}
+            struct Record {id: uint16_le, peek kind: uint8, value: int8, pad: bytes[sizeof(uint8)]}",
+        )
+        .unwrap();
+        let res = apply_schema(
+            &schema,
+            b"\x03\x01\x00\x0a\x00\x02\x00\x14\x00\x03\x00\x1e\x00\xff",
+            &ApplyOptions::default(),
+        );
+        assert_eq!(res.len, 14);
+        let records = &res.children[1];
+        assert_eq!(records.len, 12);
+        assert_eq!(records.children.len(), 3);
+        for (i, record) in records.children.iter().enumerate() {
+            assert_eq!(record.start, 1 + i * 4);
+            assert_eq!(record.len, 4);
+            assert_eq!(record.children[0].value, Some((i + 1).to_string()));
+            assert_eq!(record.children[1].start, 3 + i * 4);
+            assert_eq!(record.children[2].value, Some(((i + 1) * 10).to_string()));
+            assert_eq!(record.children[3].start, 4 + i * 4);
+        }
+        assert_eq!(res.children[2].start, 13);
+        assert_eq!(res.children[2].value, Some("255".to_string()));
+    }
+
+    #[test]
+    fn array_entry() {
+        let schema = compile_schema_file(
+            "struct root {count: uint16_le, records: [Record; count]}
+            struct Record {id: uint16_le, value: uint8}
+            struct Placed {off: uint8, name: [ascii; 3] @ off}
+            struct Varying {count: uint8, entries: [bytes[count]; 2]}",
+        )
+        .unwrap();
+        let mut data = vec![0x88, 0x13];
+        for i in 0..5000u16 {
+            data.extend(i.to_le_bytes());
+            data.push(i as u8);
+        }
+
+        // Only enough nuggets to build the count and a single record
+        let options = ApplyOptions {
+            max_nuggets: 4,
+            ..ApplyOptions::default()
+        };
+        let entry =
+            apply_array_entry(&schema, "root", "records", &data, 0, 4321, &options).unwrap();
+        assert_eq!(entry.name, "4321");
+        assert_eq!(entry.start, 2 + 4321 * 3);
+        assert_eq!(entry.len, 3);
+        assert_eq!(entry.children[0].value, Some("4321".to_string()));
+        assert_eq!(entry.children[1].value, Some("225".to_string()));
+        let res = apply_schema(&schema, &data, &options);
+        assert_eq!(res.children[1].children.len(), 1);
+
+        let entry = apply_array_entry(&schema, "root", "records", &data, 0, 5000, &options);
+        assert_eq!(entry, None);
+        let entry = apply_array_entry(&schema, "root", "count", &data, 0, 0, &options);
+        assert_eq!(entry, None);
+        let entry = apply_array_entry(&schema, "Other", "records", &data, 0, 0, &options);
+        assert_eq!(entry, None);
+        let entry = apply_array_entry(
+            &schema,
+            "Varying",
+            "entries",
+            b"\x01\x02\x03",
+            0,
+            1,
+            &options,
+        );
+        assert_eq!(entry, None);
+
+        let entry = apply_array_entry(&schema, "Placed", "name", b"\xff\x02abc", 1, 2, &options);
+        assert_eq!(entry.unwrap().value, Some("c".to_string()));
+    }
+
+    #[test]
+    fn position_operators() {
+        let schema = compile_schema_file(
+            "struct root {skip: uint8, header: Header, after: uint8}
+            struct Header {
+                header_size: uint8,
+                version: uint8,
+                reserved: bytes[header_size - sizeof(Version) - offsetof(version)],
+                let here = _pos;
+                let start = _struct_start;
+                last: uint8 @ _struct_start + header_size - 1,
+            }
+            struct Version {major: uint8, minor: uint8}",
+        )
+        .unwrap();
+        let res = apply_schema(
+            &schema,
+            b"\xff\x06\x01\xaa\xbb\xcc\xdd\x07",
+            &ApplyOptions::default(),
+        );
+        let header = &res.children[1];
+        // reserved is 6 - 2 - 1 bytes long
+        assert_eq!(header.children[2].len, 3);
+        assert_eq!(header.children[2].value, Some("aa bb cc".to_string()));
+        assert_eq!(header.children[3].value, Some("6".to_string()));
+        assert_eq!(header.children[4].value, Some("1".to_string()));
+        assert_eq!(header.children[5].start, 6);
+        assert_eq!(header.children[5].value, Some("221".to_string()));
+        assert_eq!(res.children[2].start, 6);
+    }
 }
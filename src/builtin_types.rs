@@ -1,130 +1,598 @@
 use std::convert::TryFrom;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use crate::datetime;
 
 #[derive(PartialEq)]
 pub enum BuiltinTypeClass {
     Integer,
     Float,
     Text,
+    Boolean,
+    Timestamp,
+    Identifier,
+}
+
+/// Converts the raw bytes of a builtin type into a displayable value
+enum ValueFn {
+    Simple(fn(&[u8]) -> String),
+    // Parameterised fixed point types, which share one conversion
+    FixedPoint {
+        signed: bool,
+        frac_bits: u32,
+        big_endian: bool,
+    },
 }
 
-struct CartaBuiltinType<'a> {
+struct CartaBuiltinType {
     size: usize,
-    value: &'a dyn Fn(&[u8]) -> String,
+    value: ValueFn,
     class: BuiltinTypeClass,
 }
 
+impl CartaBuiltinType {
+    fn value(&self, data: &[u8]) -> String {
+        match self.value {
+            ValueFn::Simple(value) => value(data),
+            ValueFn::FixedPoint {
+                signed,
+                frac_bits,
+                big_endian,
+            } => fixed_point_to_string(&data[0..self.size], signed, frac_bits, big_endian),
+        }
+    }
+}
+
 fn to_arr<const SIZE: usize>(data: &[u8]) -> &[u8; SIZE] {
     <&[u8; SIZE]>::try_from(&data[0..SIZE]).unwrap()
 }
 
-fn get_builtin_types(name: &str) -> Option<CartaBuiltinType<'static>> {
+/// Read a 24-bit unsigned integer, given the byte order of the 3 bytes of input
+fn u24_from_bytes(data: &[u8], big_endian: bool) -> u32 {
+    if big_endian {
+        u32::from_be_bytes([0, data[0], data[1], data[2]])
+    } else {
+        u32::from_le_bytes([data[0], data[1], data[2], 0])
+    }
+}
+
+/// Read a 24-bit two's complement signed integer
+fn i24_from_bytes(data: &[u8], big_endian: bool) -> i32 {
+    // Shift the sign bit up to bit 31, then use an arithmetic shift to sign extend back down
+    ((u24_from_bytes(data, big_endian) << 8) as i32) >> 8
+}
+
+/// Read an unsigned integer of up to 8 bytes, using all of `data`
+fn uint_from_bytes(data: &[u8], big_endian: bool) -> u64 {
+    let fold = |acc: u64, b: &u8| (acc << 8) | *b as u64;
+    if big_endian {
+        data.iter().fold(0, fold)
+    } else {
+        data.iter().rev().fold(0, fold)
+    }
+}
+
+/// Parameterised fixed point types, eg. fixed16_16_be or ufixed2_14_le.  The first number is the
+/// count of integer bits (including the sign bit for signed types), the second the count of
+/// fractional bits.  The total must be a whole number of bytes we can read as an integer.
+fn get_fixed_point_type(name: &str) -> Option<CartaBuiltinType> {
+    let (signed, params) = if let Some(params) = name.strip_prefix("ufixed") {
+        (false, params)
+    } else if let Some(params) = name.strip_prefix("fixed") {
+        (true, params)
+    } else {
+        return None;
+    };
+
+    // No leading zeros, so that each type has only one name
+    let parse_bits = |s: &str| {
+        if !s.is_empty()
+            && s.chars().all(|c| c.is_ascii_digit())
+            && (s == "0" || !s.starts_with('0'))
+        {
+            s.parse::<u32>().ok()
+        } else {
+            None
+        }
+    };
+
+    let mut parts = params.split('_');
+    let int_bits = parse_bits(parts.next()?)?;
+    let frac_bits = parse_bits(parts.next()?)?;
+    let big_endian = match parts.next()? {
+        "be" => true,
+        "le" => false,
+        _ => return None,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+
+    let total_bits = int_bits.checked_add(frac_bits)?;
+    if ![8, 16, 32, 64].contains(&total_bits) {
+        return None;
+    }
+
+    Some(CartaBuiltinType {
+        size: (total_bits / 8) as usize,
+        value: ValueFn::FixedPoint {
+            signed,
+            frac_bits,
+            big_endian,
+        },
+        class: BuiltinTypeClass::Float,
+    })
+}
+
+/// Value of a fixed point number, which takes up all of `data`
+fn fixed_point_to_string(data: &[u8], signed: bool, frac_bits: u32, big_endian: bool) -> String {
+    let raw = uint_from_bytes(data, big_endian);
+    let raw = if signed {
+        // Sign extend up to 64 bits
+        let shift = 64 - data.len() as u32 * 8;
+        ((raw << shift) as i64 >> shift) as f64
+    } else {
+        raw as f64
+    };
+    (raw / 2f64.powi(frac_bits as i32)).to_string()
+}
+
+/// Format 16 bytes in the canonical 8-4-4-4-12 UUID form
+fn format_uuid(bytes: &[u8; 16]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        hex[0..4].concat(),
+        hex[4..6].concat(),
+        hex[6..8].concat(),
+        hex[8..10].concat(),
+        hex[10..16].concat()
+    )
+}
+
+/// Microsoft GUIDs store their first three fields little endian.  Swap them to the big endian
+/// order used by the textual form.
+fn format_guid_ms(data: &[u8]) -> String {
+    let mut bytes = *to_arr::<16>(data);
+    bytes[0..4].reverse();
+    bytes[4..6].reverse();
+    bytes[6..8].reverse();
+    format_uuid(&bytes)
+}
+
+fn format_mac(data: &[u8]) -> String {
+    let hex: Vec<String> = data[0..6].iter().map(|b| format!("{:02x}", b)).collect();
+    hex.join(":")
+}
+
+/// Render a rational number, stored as a numerator followed by a denominator, as its decimal value
+fn rational_to_string(numerator: f64, denominator: f64) -> String {
+    (numerator / denominator).to_string()
+}
+
+/// Convert an IEEE 754 half precision value to a single precision float.  Every half precision
+/// value is exactly representable as an f32.
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits >> 15) as u32) << 31;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+
+    match exponent {
+        // Zero or subnormal.  Value is mantissa * 2^-24
+        0 => {
+            let value = mantissa as f32 / (1 << 24) as f32;
+            if sign == 0 {
+                value
+            } else {
+                -value
+            }
+        }
+        // Infinity or NaN
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        // Normal number.  Rebias the exponent from 15 to 127
+        _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+/// Convert a bfloat16 value to a single precision float.  bfloat16 is the top half of an f32.
+fn bf16_to_f32(bits: u16) -> f32 {
+    f32::from_bits((bits as u32) << 16)
+}
+
+fn get_builtin_types(name: &str) -> Option<CartaBuiltinType> {
     match name {
         "int8" => Some(CartaBuiltinType {
             size: 1,
-            value: &|data| i8::from_le_bytes([data[0]]).to_string(),
+            value: ValueFn::Simple(|data| i8::from_le_bytes([data[0]]).to_string()),
             class: BuiltinTypeClass::Integer,
         }),
         "int16_be" => Some(CartaBuiltinType {
             size: 2,
-            value: &|data| i16::from_be_bytes(*to_arr::<2>(data)).to_string(),
+            value: ValueFn::Simple(|data| i16::from_be_bytes(*to_arr::<2>(data)).to_string()),
             class: BuiltinTypeClass::Integer,
         }),
         "int16_le" => Some(CartaBuiltinType {
             size: 2,
-            value: &|data| i16::from_le_bytes(*to_arr::<2>(data)).to_string(),
+            value: ValueFn::Simple(|data| i16::from_le_bytes(*to_arr::<2>(data)).to_string()),
             class: BuiltinTypeClass::Integer,
         }),
         "int32_be" => Some(CartaBuiltinType {
             size: 4,
-            value: &|data| i32::from_be_bytes(*to_arr::<4>(data)).to_string(),
+            value: ValueFn::Simple(|data| i32::from_be_bytes(*to_arr::<4>(data)).to_string()),
             class: BuiltinTypeClass::Integer,
         }),
         "int32_le" => Some(CartaBuiltinType {
             size: 4,
-            value: &|data| i32::from_le_bytes(*to_arr::<4>(data)).to_string(),
+            value: ValueFn::Simple(|data| i32::from_le_bytes(*to_arr::<4>(data)).to_string()),
             class: BuiltinTypeClass::Integer,
         }),
         "int64_be" => Some(CartaBuiltinType {
             size: 8,
-            value: &|data| i64::from_be_bytes(*to_arr::<8>(data)).to_string(),
+            value: ValueFn::Simple(|data| i64::from_be_bytes(*to_arr::<8>(data)).to_string()),
             class: BuiltinTypeClass::Integer,
         }),
         "int64_le" => Some(CartaBuiltinType {
             size: 8,
-            value: &|data| i64::from_le_bytes(*to_arr::<8>(data)).to_string(),
+            value: ValueFn::Simple(|data| i64::from_le_bytes(*to_arr::<8>(data)).to_string()),
+            class: BuiltinTypeClass::Integer,
+        }),
+        "int24_be" => Some(CartaBuiltinType {
+            size: 3,
+            value: ValueFn::Simple(|data| i24_from_bytes(data, true).to_string()),
+            class: BuiltinTypeClass::Integer,
+        }),
+        "int24_le" => Some(CartaBuiltinType {
+            size: 3,
+            value: ValueFn::Simple(|data| i24_from_bytes(data, false).to_string()),
+            class: BuiltinTypeClass::Integer,
+        }),
+        "int128_be" => Some(CartaBuiltinType {
+            size: 16,
+            value: ValueFn::Simple(|data| i128::from_be_bytes(*to_arr::<16>(data)).to_string()),
+            class: BuiltinTypeClass::Integer,
+        }),
+        "int128_le" => Some(CartaBuiltinType {
+            size: 16,
+            value: ValueFn::Simple(|data| i128::from_le_bytes(*to_arr::<16>(data)).to_string()),
             class: BuiltinTypeClass::Integer,
         }),
         "uint8" => Some(CartaBuiltinType {
             size: 1,
-            value: &|data| u8::from_le_bytes([data[0]]).to_string(),
+            value: ValueFn::Simple(|data| u8::from_le_bytes([data[0]]).to_string()),
             class: BuiltinTypeClass::Integer,
         }),
         "uint16_be" => Some(CartaBuiltinType {
             size: 2,
-            value: &|data| u16::from_be_bytes(*to_arr::<2>(data)).to_string(),
+            value: ValueFn::Simple(|data| u16::from_be_bytes(*to_arr::<2>(data)).to_string()),
             class: BuiltinTypeClass::Integer,
         }),
         "uint16_le" => Some(CartaBuiltinType {
             size: 2,
-            value: &|data| u16::from_le_bytes(*to_arr::<2>(data)).to_string(),
+            value: ValueFn::Simple(|data| u16::from_le_bytes(*to_arr::<2>(data)).to_string()),
             class: BuiltinTypeClass::Integer,
         }),
         "uint32_be" => Some(CartaBuiltinType {
             size: 4,
-            value: &|data| u32::from_be_bytes(*to_arr::<4>(data)).to_string(),
+            value: ValueFn::Simple(|data| u32::from_be_bytes(*to_arr::<4>(data)).to_string()),
             class: BuiltinTypeClass::Integer,
         }),
         "uint32_le" => Some(CartaBuiltinType {
             size: 4,
-            value: &|data| u32::from_le_bytes(*to_arr::<4>(data)).to_string(),
+            value: ValueFn::Simple(|data| u32::from_le_bytes(*to_arr::<4>(data)).to_string()),
             class: BuiltinTypeClass::Integer,
         }),
         "uint64_be" => Some(CartaBuiltinType {
             size: 8,
-            value: &|data| u64::from_be_bytes(*to_arr::<8>(data)).to_string(),
+            value: ValueFn::Simple(|data| u64::from_be_bytes(*to_arr::<8>(data)).to_string()),
             class: BuiltinTypeClass::Integer,
         }),
         "uint64_le" => Some(CartaBuiltinType {
             size: 8,
-            value: &|data| u64::from_le_bytes(*to_arr::<8>(data)).to_string(),
+            value: ValueFn::Simple(|data| u64::from_le_bytes(*to_arr::<8>(data)).to_string()),
+            class: BuiltinTypeClass::Integer,
+        }),
+        "uint24_be" => Some(CartaBuiltinType {
+            size: 3,
+            value: ValueFn::Simple(|data| u24_from_bytes(data, true).to_string()),
             class: BuiltinTypeClass::Integer,
         }),
+        "uint24_le" => Some(CartaBuiltinType {
+            size: 3,
+            value: ValueFn::Simple(|data| u24_from_bytes(data, false).to_string()),
+            class: BuiltinTypeClass::Integer,
+        }),
+        "uint128_be" => Some(CartaBuiltinType {
+            size: 16,
+            value: ValueFn::Simple(|data| u128::from_be_bytes(*to_arr::<16>(data)).to_string()),
+            class: BuiltinTypeClass::Integer,
+        }),
+        "uint128_le" => Some(CartaBuiltinType {
+            size: 16,
+            value: ValueFn::Simple(|data| u128::from_le_bytes(*to_arr::<16>(data)).to_string()),
+            class: BuiltinTypeClass::Integer,
+        }),
+        "f16_be" => Some(CartaBuiltinType {
+            size: 2,
+            value: ValueFn::Simple(|data| {
+                f16_to_f32(u16::from_be_bytes(*to_arr::<2>(data))).to_string()
+            }),
+            class: BuiltinTypeClass::Float,
+        }),
+        "f16_le" => Some(CartaBuiltinType {
+            size: 2,
+            value: ValueFn::Simple(|data| {
+                f16_to_f32(u16::from_le_bytes(*to_arr::<2>(data))).to_string()
+            }),
+            class: BuiltinTypeClass::Float,
+        }),
+        "bfloat16_be" => Some(CartaBuiltinType {
+            size: 2,
+            value: ValueFn::Simple(|data| {
+                bf16_to_f32(u16::from_be_bytes(*to_arr::<2>(data))).to_string()
+            }),
+            class: BuiltinTypeClass::Float,
+        }),
+        "bfloat16_le" => Some(CartaBuiltinType {
+            size: 2,
+            value: ValueFn::Simple(|data| {
+                bf16_to_f32(u16::from_le_bytes(*to_arr::<2>(data))).to_string()
+            }),
+            class: BuiltinTypeClass::Float,
+        }),
         "f32_be" => Some(CartaBuiltinType {
             size: 4,
-            value: &|data| f32::from_be_bytes(*to_arr::<4>(data)).to_string(),
+            value: ValueFn::Simple(|data| f32::from_be_bytes(*to_arr::<4>(data)).to_string()),
             class: BuiltinTypeClass::Float,
         }),
         "f32_le" => Some(CartaBuiltinType {
             size: 4,
-            value: &|data| f32::from_le_bytes(*to_arr::<4>(data)).to_string(),
+            value: ValueFn::Simple(|data| f32::from_le_bytes(*to_arr::<4>(data)).to_string()),
             class: BuiltinTypeClass::Float,
         }),
         "f64_be" => Some(CartaBuiltinType {
             size: 8,
-            value: &|data| f64::from_be_bytes(*to_arr::<8>(data)).to_string(),
+            value: ValueFn::Simple(|data| f64::from_be_bytes(*to_arr::<8>(data)).to_string()),
             class: BuiltinTypeClass::Float,
         }),
         "f64_le" => Some(CartaBuiltinType {
             size: 8,
-            value: &|data| f64::from_le_bytes(*to_arr::<8>(data)).to_string(),
+            value: ValueFn::Simple(|data| f64::from_le_bytes(*to_arr::<8>(data)).to_string()),
             class: BuiltinTypeClass::Float,
         }),
+        // EXIF style rationals: 32 bit numerator followed by a 32 bit denominator
+        "rational32_be" => Some(CartaBuiltinType {
+            size: 8,
+            value: ValueFn::Simple(|data| {
+                let numerator = u32::from_be_bytes(*to_arr::<4>(data));
+                let denominator = u32::from_be_bytes(*to_arr::<4>(&data[4..]));
+                rational_to_string(numerator as f64, denominator as f64)
+            }),
+            class: BuiltinTypeClass::Float,
+        }),
+        "rational32_le" => Some(CartaBuiltinType {
+            size: 8,
+            value: ValueFn::Simple(|data| {
+                let numerator = u32::from_le_bytes(*to_arr::<4>(data));
+                let denominator = u32::from_le_bytes(*to_arr::<4>(&data[4..]));
+                rational_to_string(numerator as f64, denominator as f64)
+            }),
+            class: BuiltinTypeClass::Float,
+        }),
+        "srational32_be" => Some(CartaBuiltinType {
+            size: 8,
+            value: ValueFn::Simple(|data| {
+                let numerator = i32::from_be_bytes(*to_arr::<4>(data));
+                let denominator = i32::from_be_bytes(*to_arr::<4>(&data[4..]));
+                rational_to_string(numerator as f64, denominator as f64)
+            }),
+            class: BuiltinTypeClass::Float,
+        }),
+        "srational32_le" => Some(CartaBuiltinType {
+            size: 8,
+            value: ValueFn::Simple(|data| {
+                let numerator = i32::from_le_bytes(*to_arr::<4>(data));
+                let denominator = i32::from_le_bytes(*to_arr::<4>(&data[4..]));
+                rational_to_string(numerator as f64, denominator as f64)
+            }),
+            class: BuiltinTypeClass::Float,
+        }),
+        // Timestamps.  Displayed as ISO 8601 strings, with the raw integer available through
+        // get_raw_value.  All are UTC except DOS timestamps, which are in an unknown local time.
+        "unix32_be" => Some(CartaBuiltinType {
+            size: 4,
+            value: ValueFn::Simple(|data| {
+                datetime::format_unix(i32::from_be_bytes(*to_arr::<4>(data)) as i64)
+            }),
+            class: BuiltinTypeClass::Timestamp,
+        }),
+        "unix32_le" => Some(CartaBuiltinType {
+            size: 4,
+            value: ValueFn::Simple(|data| {
+                datetime::format_unix(i32::from_le_bytes(*to_arr::<4>(data)) as i64)
+            }),
+            class: BuiltinTypeClass::Timestamp,
+        }),
+        "unix64_be" => Some(CartaBuiltinType {
+            size: 8,
+            value: ValueFn::Simple(|data| {
+                datetime::format_unix(i64::from_be_bytes(*to_arr::<8>(data)))
+            }),
+            class: BuiltinTypeClass::Timestamp,
+        }),
+        "unix64_le" => Some(CartaBuiltinType {
+            size: 8,
+            value: ValueFn::Simple(|data| {
+                datetime::format_unix(i64::from_le_bytes(*to_arr::<8>(data)))
+            }),
+            class: BuiltinTypeClass::Timestamp,
+        }),
+        "dos_datetime" => Some(CartaBuiltinType {
+            size: 4,
+            value: ValueFn::Simple(|data| {
+                let time = u16::from_le_bytes(*to_arr::<2>(data));
+                let date = u16::from_le_bytes(*to_arr::<2>(&data[2..]));
+                datetime::format_dos(time, date)
+            }),
+            class: BuiltinTypeClass::Timestamp,
+        }),
+        "filetime" => Some(CartaBuiltinType {
+            size: 8,
+            value: ValueFn::Simple(|data| {
+                datetime::format_filetime(u64::from_le_bytes(*to_arr::<8>(data)))
+            }),
+            class: BuiltinTypeClass::Timestamp,
+        }),
+        "hfs_time" => Some(CartaBuiltinType {
+            size: 4,
+            value: ValueFn::Simple(|data| {
+                datetime::format_hfs(u32::from_be_bytes(*to_arr::<4>(data)))
+            }),
+            class: BuiltinTypeClass::Timestamp,
+        }),
+        // GPS receivers mostly use little endian, so that's the default
+        "gps_time" | "gps_time_le" => Some(CartaBuiltinType {
+            size: 4,
+            value: ValueFn::Simple(|data| {
+                datetime::format_gps(u32::from_le_bytes(*to_arr::<4>(data)))
+            }),
+            class: BuiltinTypeClass::Timestamp,
+        }),
+        "gps_time_be" => Some(CartaBuiltinType {
+            size: 4,
+            value: ValueFn::Simple(|data| {
+                datetime::format_gps(u32::from_be_bytes(*to_arr::<4>(data)))
+            }),
+            class: BuiltinTypeClass::Timestamp,
+        }),
+        // Identifiers and network addresses
+        "uuid" => Some(CartaBuiltinType {
+            size: 16,
+            value: ValueFn::Simple(|data| format_uuid(to_arr::<16>(data))),
+            class: BuiltinTypeClass::Identifier,
+        }),
+        "guid_ms" => Some(CartaBuiltinType {
+            size: 16,
+            value: ValueFn::Simple(format_guid_ms),
+            class: BuiltinTypeClass::Identifier,
+        }),
+        "ipv4" => Some(CartaBuiltinType {
+            size: 4,
+            value: ValueFn::Simple(|data| Ipv4Addr::from(*to_arr::<4>(data)).to_string()),
+            class: BuiltinTypeClass::Identifier,
+        }),
+        "ipv6" => Some(CartaBuiltinType {
+            size: 16,
+            value: ValueFn::Simple(|data| Ipv6Addr::from(*to_arr::<16>(data)).to_string()),
+            class: BuiltinTypeClass::Identifier,
+        }),
+        "mac" => Some(CartaBuiltinType {
+            size: 6,
+            value: ValueFn::Simple(format_mac),
+            class: BuiltinTypeClass::Identifier,
+        }),
+        // Single byte boolean.  Any non-zero value is true
+        "bool8" => Some(CartaBuiltinType {
+            size: 1,
+            value: ValueFn::Simple(|data| (data[0] != 0).to_string()),
+            class: BuiltinTypeClass::Boolean,
+        }),
         // Single ascii character
         "ascii" => Some(CartaBuiltinType {
             size: 1,
-            value: &|data| (u8::from_le_bytes([data[0]]) as char).to_string(),
+            value: ValueFn::Simple(|data| (u8::from_le_bytes([data[0]]) as char).to_string()),
             class: BuiltinTypeClass::Text,
         }),
-        _ => None,
+        _ => get_fixed_point_type(name),
     }
 }
 
+/// Names of all the builtin types, apart from the parameterised fixed point types
+const BUILTIN_NAMES: &[&str] = &[
+    "int8",
+    "int16_be",
+    "int16_le",
+    "int32_be",
+    "int32_le",
+    "int64_be",
+    "int64_le",
+    "int24_be",
+    "int24_le",
+    "int128_be",
+    "int128_le",
+    "uint8",
+    "uint16_be",
+    "uint16_le",
+    "uint32_be",
+    "uint32_le",
+    "uint64_be",
+    "uint64_le",
+    "uint24_be",
+    "uint24_le",
+    "uint128_be",
+    "uint128_le",
+    "f16_be",
+    "f16_le",
+    "bfloat16_be",
+    "bfloat16_le",
+    "f32_be",
+    "f32_le",
+    "f64_be",
+    "f64_le",
+    "rational32_be",
+    "rational32_le",
+    "srational32_be",
+    "srational32_le",
+    "unix32_be",
+    "unix32_le",
+    "unix64_be",
+    "unix64_le",
+    "dos_datetime",
+    "filetime",
+    "hfs_time",
+    "gps_time",
+    "gps_time_be",
+    "gps_time_le",
+    "uuid",
+    "guid_ms",
+    "ipv4",
+    "ipv6",
+    "mac",
+    "bool8",
+    "ascii",
+];
+
 pub fn is_builtin_type(name: &str) -> bool {
     get_builtin_types(name).is_some()
 }
 
 pub fn get_value(data: &[u8], name: &str) -> Option<(usize, String)> {
-    get_builtin_types(name).map(|defn| (defn.size, (defn.value)(data)))
+    get_builtin_types(name).map(|defn| (defn.size, defn.value(data)))
+}
+
+/// Size in bytes of a builtin type
+pub fn get_size(name: &str) -> Option<usize> {
+    get_builtin_types(name).map(|defn| defn.size)
+}
+
+/// Integer type holding the underlying value for builtins whose display value is not the raw integer
+fn raw_integer_type(name: &str) -> Option<&'static str> {
+    match name {
+        "unix32_be" => Some("int32_be"),
+        "unix32_le" => Some("int32_le"),
+        "unix64_be" => Some("int64_be"),
+        "unix64_le" => Some("int64_le"),
+        "dos_datetime" => Some("uint32_le"),
+        "filetime" => Some("uint64_le"),
+        "hfs_time" => Some("uint32_be"),
+        "gps_time_be" => Some("uint32_be"),
+        "gps_time" | "gps_time_le" => Some("uint32_le"),
+        _ => None,
+    }
+}
+
+/// Get the raw integer value of a builtin type, if it's displayed as something other than an integer
+pub fn get_raw_value(data: &[u8], name: &str) -> Option<String> {
+    raw_integer_type(name).and_then(|raw_type| get_value(data, raw_type).map(|(_, value)| value))
 }
 
 pub fn is_type_class(name: &str, class: BuiltinTypeClass) -> bool {
@@ -132,3 +600,234 @@ pub fn is_type_class(name: &str, class: BuiltinTypeClass) -> bool {
         .map(|defn| defn.class == class)
         .unwrap_or(false)
 }
+
+/// Names of the builtin types, for suggesting corrections to misspelt types
+pub fn builtin_type_names() -> impl Iterator<Item = &'static str> {
+    BUILTIN_NAMES.iter().copied()
+}
+
+/// Integer types whose values fit in the 64 bit arithmetic used for lengths and expressions.  That's
+/// all of them apart from the 128 bit types.
+pub fn is_length_integer(name: &str) -> bool {
+    is_type_class(name, BuiltinTypeClass::Integer) && get_size(name).is_some_and(|size| size <= 8)
+}
+
+/// Integer types that can hold negative values
+pub fn is_signed_integer(name: &str) -> bool {
+    is_type_class(name, BuiltinTypeClass::Integer) && name.starts_with("int")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn value(data: &[u8], name: &str) -> String {
+        get_value(data, name).unwrap().1
+    }
+
+    #[test]
+    fn int24() {
+        assert_eq!(value(b"\x01\x02\x03", "uint24_le"), "197121");
+        assert_eq!(value(b"\x01\x02\x03", "uint24_be"), "66051");
+        assert_eq!(value(b"\xff\xff\xff", "uint24_le"), "16777215");
+        assert_eq!(value(b"\xff\xff\xff", "int24_le"), "-1");
+        assert_eq!(value(b"\x80\x00\x00", "int24_be"), "-8388608");
+        assert_eq!(value(b"\xff\xff\x7f", "int24_le"), "8388607");
+        assert!(is_type_class("uint24_be", BuiltinTypeClass::Integer));
+        assert!(is_length_integer("uint24_be"));
+    }
+
+    #[test]
+    fn builtin_names() {
+        assert!(builtin_type_names().all(is_builtin_type));
+        assert!(builtin_type_names().any(|name| name == "uint32_le"));
+    }
+
+    #[test]
+    fn signed_integers() {
+        assert!(is_signed_integer("int8"));
+        assert!(is_signed_integer("int24_le"));
+        assert!(!is_signed_integer("uint32_be"));
+        assert!(!is_signed_integer("fixed16_16_le"));
+        assert!(!is_signed_integer("interface"));
+    }
+
+    #[test]
+    fn int128() {
+        let mut data = [0xff; 16];
+        assert_eq!(value(&data, "int128_le"), "-1");
+        assert_eq!(value(&data, "uint128_be"), u128::MAX.to_string());
+        data[15] = 0x7f;
+        assert_eq!(value(&data, "int128_le"), i128::MAX.to_string());
+        // Too large to use as lengths
+        assert!(!is_length_integer("uint128_le"));
+        assert!(is_length_integer("uint64_le"));
+    }
+
+    #[test]
+    fn half_precision() {
+        assert_eq!(value(b"\x3c\x00", "f16_be"), "1");
+        assert_eq!(value(b"\x00\xc0", "f16_le"), "-2");
+        assert_eq!(value(b"\x7b\xff", "f16_be"), "65504");
+        assert_eq!(value(b"\x00\x01", "f16_be"), "0.000000059604645");
+        assert_eq!(value(b"\x80\x00", "f16_be"), "-0");
+        assert_eq!(value(b"\x7c\x00", "f16_be"), "inf");
+        assert_eq!(value(b"\x7e\x00", "f16_be"), "NaN");
+    }
+
+    #[test]
+    fn bfloat16() {
+        assert_eq!(value(b"\x3f\x80", "bfloat16_be"), "1");
+        assert_eq!(value(b"\x20\xc0", "bfloat16_le"), "-2.5");
+        assert!(is_type_class("bfloat16_le", BuiltinTypeClass::Float));
+    }
+
+    #[test]
+    fn fixed_point() {
+        assert_eq!(value(b"\x00\x01\x80\x00", "fixed16_16_be"), "1.5");
+        assert_eq!(value(b"\x00\x80\xfe\xff", "fixed16_16_le"), "-1.5");
+        assert_eq!(value(b"\x00\x80\xfe\xff", "ufixed16_16_le"), "65534.5");
+        assert_eq!(value(b"\x70\x00", "fixed2_14_be"), "1.75");
+        assert_eq!(value(b"\xc0\x00", "fixed2_14_be"), "-1");
+        assert_eq!(value(b"\x80\x01", "ufixed8_8_le"), "1.5");
+        assert_eq!(value(b"\x0c", "ufixed4_4_be"), "0.75");
+        assert_eq!(value(b"\xc0", "ufixed0_8_le"), "0.75");
+        assert_eq!(
+            value(&[0, 0, 0, 0, 0, 0, 0, 0x80], "fixed32_32_le"),
+            "-2147483648"
+        );
+        assert!(is_type_class("fixed16_16_be", BuiltinTypeClass::Float));
+    }
+
+    #[test]
+    fn bad_fixed_point() {
+        for name in &[
+            "fixed16_16",
+            "fixed16_15_be",
+            "fixed16_16_be_le",
+            "fixed_16_be",
+            "fixed+8_8_le",
+            "fixed16_16_xe",
+            "ufixed64_64_le",
+            "fixed4294967295_1_le",
+            "fixed016_16_be",
+            "ufixed8_08_le",
+            "fixed00_8_le",
+        ] {
+            assert!(!is_builtin_type(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rational() {
+        assert_eq!(
+            value(b"\x00\x00\x00\x01\x00\x00\x00\x02", "rational32_be"),
+            "0.5"
+        );
+        assert_eq!(
+            value(b"\x48\x00\x00\x00\x01\x00\x00\x00", "rational32_le"),
+            "72"
+        );
+        assert_eq!(
+            value(b"\xff\xff\xff\xfd\x00\x00\x00\x04", "srational32_be"),
+            "-0.75"
+        );
+        assert_eq!(
+            value(b"\x01\x00\x00\x00\x00\x00\x00\x00", "srational32_le"),
+            "inf"
+        );
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(
+            value(b"\x5e\x0b\xe1\x00", "unix32_be"),
+            "2020-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            value(b"\x00\xe1\x0b\x5e", "unix32_le"),
+            "2020-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            value(b"\xff\xff\xff\xff", "unix32_le"),
+            "1969-12-31T23:59:59Z"
+        );
+        assert_eq!(
+            value(&[0, 0, 0, 0, 0x5e, 0x0b, 0xe1, 0x00], "unix64_be"),
+            "2020-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            value(b"\xaf\x6d\xcf\x4e", "dos_datetime"),
+            "2019-06-15T13:45:30"
+        );
+        assert_eq!(value(&[0; 8], "filetime"), "1601-01-01T00:00:00Z");
+        assert_eq!(value(&[0; 4], "hfs_time"), "1904-01-01T00:00:00Z");
+        assert_eq!(value(&[0; 4], "gps_time_le"), "1980-01-06T00:00:00Z");
+        assert_eq!(
+            value(b"\x00\x01\x00\x00", "gps_time"),
+            "1980-01-06T00:04:16Z"
+        );
+        assert_eq!(
+            value(b"\x00\x01\x00\x00", "gps_time_be"),
+            "1980-01-06T18:12:16Z"
+        );
+        assert_eq!(value(&[0; 4], "dos_datetime"), "<invalid DOS date/time>");
+        assert!(is_type_class("filetime", BuiltinTypeClass::Timestamp));
+        assert!(!is_type_class("unix32_le", BuiltinTypeClass::Integer));
+    }
+
+    #[test]
+    fn raw_values() {
+        assert_eq!(
+            get_raw_value(b"\x5e\x0b\xe1\x00", "unix32_be"),
+            Some("1577836800".to_string())
+        );
+        assert_eq!(
+            get_raw_value(b"\xff\xff\xff\xff", "unix32_le"),
+            Some("-1".to_string())
+        );
+        assert_eq!(
+            get_raw_value(b"\xaf\x6d\xcf\x4e", "dos_datetime"),
+            Some("1322216879".to_string())
+        );
+        assert_eq!(
+            get_raw_value(&[0; 4], "dos_datetime"),
+            Some("0".to_string())
+        );
+        assert_eq!(
+            get_raw_value(b"\x00\x01\x00\x00", "gps_time"),
+            Some("256".to_string())
+        );
+        assert_eq!(get_raw_value(b"\x01", "uint8"), None);
+    }
+
+    #[test]
+    fn identifiers() {
+        let data: Vec<u8> = (0..16).collect();
+        assert_eq!(value(&data, "uuid"), "00010203-0405-0607-0809-0a0b0c0d0e0f");
+        assert_eq!(
+            value(&data, "guid_ms"),
+            "03020100-0504-0706-0809-0a0b0c0d0e0f"
+        );
+        assert_eq!(value(b"\xc0\xa8\x00\x01", "ipv4"), "192.168.0.1");
+        assert_eq!(value(&data, "ipv6"), "1:203:405:607:809:a0b:c0d:e0f");
+        let mut loopback = [0; 16];
+        loopback[15] = 1;
+        assert_eq!(value(&loopback, "ipv6"), "::1");
+        assert_eq!(
+            value(b"\x00\x1b\x63\x84\x45\xe6", "mac"),
+            "00:1b:63:84:45:e6"
+        );
+        assert!(is_type_class("mac", BuiltinTypeClass::Identifier));
+        assert!(!is_type_class("ipv4", BuiltinTypeClass::Text));
+    }
+
+    #[test]
+    fn bool8() {
+        assert_eq!(value(b"\x00", "bool8"), "false");
+        assert_eq!(value(b"\x01", "bool8"), "true");
+        assert_eq!(value(b"\x80", "bool8"), "true");
+        assert!(is_type_class("bool8", BuiltinTypeClass::Boolean));
+        assert!(!is_type_class("bool8", BuiltinTypeClass::Integer));
+    }
+}
@@ -0,0 +1,439 @@
+/*!
+ * Caching compiled schemas
+ *
+ * Compiling a large schema takes a while, so a compiled schema can be saved, eg. to disk, and
+ * loaded again later without going through the compiler.  There are two encodings: JSON, which is
+ * readable, and a more compact binary encoding.
+ *
+ * Each encoding records the version of the format and of this library that wrote it, and a hash of
+ * the schema source.  Loading checks all of these, so that a cached schema is never used with a
+ * different version of the library, or after its source has changed.  The schema itself is also
+ * checked, as the hash doesn't cover it, so a damaged cache is rejected rather than misbehaving
+ * when it's applied.
+ *
+ * The binary encoding is a header followed by two MessagePack values:
+ *
+ * ```text
+ * "CRTA"  format version (u32 LE)  [crate version, source hash]  schema
+ * ```
+ */
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::builtin_types;
+use crate::error::CacheError;
+use crate::type_check::TSchema;
+
+/// Version of the cache format.  Bump this whenever the encoding changes.
+pub const FORMAT_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 4] = b"CRTA";
+
+const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Everything that must match for a cached schema to be used
+#[derive(Serialize, Deserialize)]
+struct Header {
+    crate_version: String,
+    source_hash: String,
+}
+
+impl Header {
+    fn new(source: &str) -> Header {
+        Header {
+            crate_version: CRATE_VERSION.to_string(),
+            source_hash: source_hash(source),
+        }
+    }
+
+    fn check(&self, source: &str) -> Result<(), CacheError> {
+        if self.crate_version != CRATE_VERSION {
+            return Err(CacheError::WrongVersion(self.crate_version.clone()));
+        }
+        if self.source_hash != source_hash(source) {
+            return Err(CacheError::Stale);
+        }
+        Ok(())
+    }
+}
+
+/// A cached schema in JSON
+#[derive(Serialize)]
+struct JsonCache<'a> {
+    format_version: u32,
+
+    #[serde(flatten)]
+    header: Header,
+
+    schema: &'a TSchema,
+}
+
+/// Check that a loaded schema holds together the way a compiled one does, so that applying it
+/// can't fail
+fn check_schema(schema: &TSchema) -> Result<(), CacheError> {
+    let bad_format = |reason: String| Err(CacheError::BadFormat(reason));
+
+    if schema.roots.is_empty() {
+        return bad_format("no root structs".to_string());
+    }
+    for root in &schema.roots {
+        if !schema.types.contains_key(root) {
+            return bad_format(format!("unknown root struct {}", root));
+        }
+    }
+
+    for (name, struct_defn) in schema.types.iter() {
+        if struct_defn.name != name {
+            return bad_format(format!("struct {} stored as {}", struct_defn.name, name));
+        }
+        match schema.layouts.get(name) {
+            Some(layout) if layout.offsets.len() == struct_defn.elements.len() => {}
+            _ => return bad_format(format!("bad layout for struct {}", name)),
+        }
+
+        // Inherited elements are copied from the base type
+        let num_inherited = match &struct_defn.base {
+            Some(base) => match schema.types.get(base) {
+                Some(base_defn) => base_defn.elements.len(),
+                None => return bad_format(format!("unknown base type {}", base)),
+            },
+            None => 0,
+        };
+        if struct_defn.num_inherited != num_inherited {
+            return bad_format(format!("bad inherited elements in struct {}", name));
+        }
+
+        for element in &struct_defn.elements {
+            if let Some(typename) = element.kind.type_name() {
+                if !builtin_types::is_builtin_type(typename) && !schema.types.contains_key(typename)
+                {
+                    return bad_format(format!("unknown type {}", typename));
+                }
+            }
+            for expr in element.exprs() {
+                if expr
+                    .signatures()
+                    .iter()
+                    .any(|signature| signature.is_empty())
+                {
+                    return bad_format(format!("empty rfind signature in struct {}", name));
+                }
+            }
+        }
+
+        let mut seen = HashSet::new();
+        let mut base = struct_defn.base.as_deref();
+        while let Some(base_name) = base {
+            if !seen.insert(base_name) {
+                return bad_format(format!("struct {} extends itself", name));
+            }
+            base = schema
+                .types
+                .get(base_name)
+                .and_then(|base_defn| base_defn.base.as_deref());
+        }
+    }
+    Ok(())
+}
+
+/// Hash of a schema's source, as 16 hex digits.  Used to spot when a cached schema is out of date,
+/// so it's a fast hash (64 bit FNV-1a) rather than a cryptographic one.
+pub fn source_hash(source: &str) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in source.bytes() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    format!("{:016x}", hash)
+}
+
+impl TSchema {
+    /// Encode the schema as JSON.  `source` is the schema source it was compiled from.
+    pub fn to_json(&self, source: &str) -> String {
+        let cache = JsonCache {
+            format_version: FORMAT_VERSION,
+            header: Header::new(source),
+            schema: self,
+        };
+        // Schemas only contain strings, numbers and lists, so always serialise
+        serde_json::to_string(&cache).unwrap()
+    }
+
+    /// Load a schema encoded with `to_json`.  `source` is the current schema source, which must be
+    /// the same as the source the cached schema was compiled from.
+    pub fn from_json(data: &str, source: &str) -> Result<TSchema, CacheError> {
+        let bad_format = |err: serde_json::Error| CacheError::BadFormat(err.to_string());
+
+        // Check the versions before trying to read the rest, as the schema may have a different
+        // shape in other versions
+        let mut value: serde_json::Value = serde_json::from_str(data).map_err(bad_format)?;
+        let format_version = value.get("format_version").and_then(|v| v.as_u64());
+        match format_version {
+            Some(version) if version == FORMAT_VERSION as u64 => {}
+            Some(version) => return Err(CacheError::WrongVersion(format!("format {}", version))),
+            None => return Err(CacheError::BadFormat("missing format_version".to_string())),
+        }
+        let header = Header::deserialize(&value).map_err(bad_format)?;
+        header.check(source)?;
+
+        let schema = value
+            .get_mut("schema")
+            .ok_or_else(|| CacheError::BadFormat("missing schema".to_string()))?
+            .take();
+        let schema = TSchema::deserialize(schema).map_err(bad_format)?;
+        check_schema(&schema)?;
+        Ok(schema)
+    }
+
+    /// Encode the schema in the compact binary format.  `source` is the schema source it was
+    /// compiled from.
+    pub fn to_binary(&self, source: &str) -> Vec<u8> {
+        let mut data = MAGIC.to_vec();
+        data.extend(FORMAT_VERSION.to_le_bytes());
+        rmp_serde::encode::write(&mut data, &Header::new(source)).unwrap();
+        rmp_serde::encode::write(&mut data, self).unwrap();
+        data
+    }
+
+    /// Load a schema encoded with `to_binary`.  `source` is the current schema source, which must
+    /// be the same as the source the cached schema was compiled from.
+    pub fn from_binary(data: &[u8], source: &str) -> Result<TSchema, CacheError> {
+        let bad_format = |err: rmp_serde::decode::Error| CacheError::BadFormat(err.to_string());
+
+        let mut data = data
+            .strip_prefix(MAGIC)
+            .ok_or_else(|| CacheError::BadFormat("missing header".to_string()))?;
+        let version = data
+            .get(..4)
+            .ok_or_else(|| CacheError::BadFormat("missing format version".to_string()))?;
+        let version = u32::from_le_bytes(version.try_into().unwrap());
+        if version != FORMAT_VERSION {
+            return Err(CacheError::WrongVersion(format!("format {}", version)));
+        }
+        data = &data[4..];
+
+        // Reading from the slice moves it on past each value
+        let header: Header = rmp_serde::decode::from_read(&mut data).map_err(bad_format)?;
+        header.check(source)?;
+        let schema = rmp_serde::decode::from_read(&mut data).map_err(bad_format)?;
+        if !data.is_empty() {
+            return Err(CacheError::BadFormat("trailing data".to_string()));
+        }
+        check_schema(&schema)?;
+        Ok(schema)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::span::Span;
+    use crate::{apply_schema, compile_schema_file};
+
+    const SOURCE: &str = "
+        struct root {
+            header: Header,
+            count: uint8,
+            entries: [Entry; count],
+            let total = count * sizeof(Entry);
+            trailer: bytes[4] @ end - 4,
+            let last_ff = rfind(0xff);
+        }
+        struct Header extends Magic {version: uint16_le}
+        struct Magic {magic: [ascii; 2]}
+        union Entry {a: uint16_be, b: struct {x: uint8, y: int8}}
+    ";
+
+    const DATA: &[u8] = b"MZ\x01\x00\x02\x00\x01\x00\x02\xff\xff\xff\xff";
+
+    /// Source text covered by a span
+    fn source_text(span: Span) -> &'static str {
+        &SOURCE[span.start.offset..span.end.offset]
+    }
+
+    /// Check that a loaded schema's spans still point at the right source text
+    fn check_spans(loaded: &TSchema) {
+        let root = loaded.get_struct("root").unwrap();
+        assert_eq!(root.span.start.line, 2);
+        assert!(source_text(root.span).starts_with("struct root {"));
+        let elements = &root.elements;
+        assert_eq!(source_text(elements[0].span), "header: Header");
+        assert_eq!(
+            (elements[0].span.start.line, elements[0].span.start.column),
+            (3, 13)
+        );
+        assert_eq!(
+            source_text(elements[3].span),
+            "let total = count * sizeof(Entry)"
+        );
+        assert_eq!(source_text(elements[4].span), "trailer: bytes[4] @ end - 4");
+
+        // Inherited elements point at the struct they were defined in
+        let header = loaded.get_struct("Header").unwrap();
+        assert_eq!(
+            source_text(header.span),
+            "struct Header extends Magic {version: uint16_le}"
+        );
+        assert_eq!(source_text(header.elements[0].span), "magic: [ascii; 2]");
+        assert_eq!(source_text(header.elements[1].span), "version: uint16_le");
+    }
+
+    #[test]
+    fn json() {
+        let schema = compile_schema_file(SOURCE).unwrap();
+        let json = schema.to_json(SOURCE);
+        assert!(json.starts_with("{\"format_version\":1,"));
+        let loaded = TSchema::from_json(&json, SOURCE).unwrap();
+        assert_eq!(loaded, schema);
+        check_spans(&loaded);
+
+        // The loaded schema keeps its order and layouts
+        let names: Vec<&str> = loaded.types().keys().collect();
+        let expected: Vec<&str> = schema.types().keys().collect();
+        assert_eq!(names, expected);
+        assert_eq!(loaded.layouts, schema.layouts);
+        assert_eq!(apply_schema(&loaded, DATA), apply_schema(&schema, DATA));
+    }
+
+    #[test]
+    fn binary() {
+        let schema = compile_schema_file(SOURCE).unwrap();
+        let data = schema.to_binary(SOURCE);
+        assert!(data.starts_with(b"CRTA\x01\x00\x00\x00"));
+        assert!(data.len() < schema.to_json(SOURCE).len());
+        let loaded = TSchema::from_binary(&data, SOURCE).unwrap();
+        assert_eq!(loaded, schema);
+        check_spans(&loaded);
+        assert_eq!(apply_schema(&loaded, DATA), apply_schema(&schema, DATA));
+    }
+
+    #[test]
+    fn stale() {
+        let schema = compile_schema_file(SOURCE).unwrap();
+        let changed = SOURCE.replace("uint16_le", "uint32_le");
+        assert_eq!(
+            TSchema::from_json(&schema.to_json(SOURCE), &changed),
+            Err(CacheError::Stale)
+        );
+        assert_eq!(
+            TSchema::from_binary(&schema.to_binary(SOURCE), &changed),
+            Err(CacheError::Stale)
+        );
+    }
+
+    #[test]
+    fn wrong_version() {
+        let schema = compile_schema_file(SOURCE).unwrap();
+
+        let json = schema
+            .to_json(SOURCE)
+            .replace("\"format_version\":1", "\"format_version\":2");
+        assert_eq!(
+            TSchema::from_json(&json, SOURCE),
+            Err(CacheError::WrongVersion("format 2".to_string()))
+        );
+        let json = schema.to_json(SOURCE).replace(
+            &format!("\"crate_version\":\"{}\"", CRATE_VERSION),
+            "\"crate_version\":\"0.0.1\"",
+        );
+        assert_eq!(
+            TSchema::from_json(&json, SOURCE),
+            Err(CacheError::WrongVersion("0.0.1".to_string()))
+        );
+
+        let mut data = schema.to_binary(SOURCE);
+        data[4] = 2;
+        assert_eq!(
+            TSchema::from_binary(&data, SOURCE),
+            Err(CacheError::WrongVersion("format 2".to_string()))
+        );
+    }
+
+    #[test]
+    fn bad_format() {
+        let schema = compile_schema_file(SOURCE).unwrap();
+        let data = schema.to_binary(SOURCE);
+        for bad in [&b"CRTB"[..], &data[..6], &data[..data.len() - 1]] {
+            assert!(matches!(
+                TSchema::from_binary(bad, SOURCE),
+                Err(CacheError::BadFormat(_))
+            ));
+        }
+        let mut extra = data.clone();
+        extra.push(0);
+        assert_eq!(
+            TSchema::from_binary(&extra, SOURCE),
+            Err(CacheError::BadFormat("trailing data".to_string()))
+        );
+
+        for bad in ["", "{}", "{\"format_version\":1}", "[1, 2]"] {
+            assert!(matches!(
+                TSchema::from_json(bad, SOURCE),
+                Err(CacheError::BadFormat(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn bad_schema() {
+        // The header still matches, but the schema itself has been damaged
+        let schema = compile_schema_file(SOURCE).unwrap();
+        let json = schema.to_json(SOURCE);
+        for (from, to) in [
+            ("\"roots\":[\"root\"]", "\"roots\":[]"),
+            ("\"roots\":[\"root\"]", "\"roots\":[\"Missing\"]"),
+            ("{\"TypeName\":\"Header\"}", "{\"TypeName\":\"Missing\"}"),
+            ("\"base\":\"Magic\"", "\"base\":\"Missing\""),
+            ("\"num_inherited\":1", "\"num_inherited\":0"),
+            ("\"FindLast\":[255]", "\"FindLast\":[]"),
+            ("\"offsets\":[0,", "\"offsets\":["),
+            (
+                "[\"Magic\",{\"name\":\"Magic\"",
+                "[\"Magic\",{\"name\":\"Other\"",
+            ),
+        ] {
+            assert!(json.contains(from), "{}", from);
+            let bad = json.replacen(from, to, 1);
+            assert!(
+                matches!(
+                    TSchema::from_json(&bad, SOURCE),
+                    Err(CacheError::BadFormat(_))
+                ),
+                "{}",
+                to
+            );
+        }
+
+        // Structs that extend each other
+        let source = "struct root {a: A} struct A extends B {} struct B {}";
+        let json = compile_schema_file(source)
+            .unwrap()
+            .to_json(source)
+            .replace("\"base\":null", "\"base\":\"A\"");
+        assert!(matches!(
+            TSchema::from_json(&json, source),
+            Err(CacheError::BadFormat(_))
+        ));
+
+        // Point the root's first element at a type that doesn't exist
+        let mut data = schema.to_binary(SOURCE);
+        let pos = data
+            .windows(6)
+            .position(|window| window == b"Header")
+            .unwrap();
+        data[pos + 1] = b'x';
+        assert!(matches!(
+            TSchema::from_binary(&data, SOURCE),
+            Err(CacheError::BadFormat(_))
+        ));
+    }
+
+    #[test]
+    fn hash() {
+        assert_eq!(source_hash(""), "cbf29ce484222325");
+        assert_eq!(source_hash("a"), "af63dc4c8601ec8c");
+        assert_ne!(source_hash(SOURCE), source_hash(&SOURCE.replace('8', "9")));
+    }
+}
@@ -1,62 +1,152 @@
 use crate::builtin_types;
-use crate::builtin_types::BuiltinTypeClass;
+use crate::error;
 use crate::error::CartaError;
-use crate::parser::{ArrayDefn, ArrayLen, ElementTypeRef, StructDefn};
+use crate::expression::Expr;
+use crate::parser::{ArrayLen, Element, ElementTypeRef, StructDefn};
 use crate::type_check::TSchema;
 
+/// Check the schema, stopping at the first error.  Used by tests that check for one error.
+#[cfg(test)]
 pub fn check_schema(schema: &TSchema) -> Result<(), CartaError> {
-    check_root_element(schema)?;
-    check_array_lengths(schema)?;
-    Ok(())
+    error::first_error((), check_schema_with_errors(schema))
+}
+
+/// Check the schema, carrying on after errors to find as many as possible
+pub fn check_schema_with_errors(schema: &TSchema) -> Vec<CartaError> {
+    let mut errors = Vec::new();
+    errors.extend(check_root_element(schema).err());
+    check_array_lengths(schema, &mut errors);
+    check_expressions(schema, &mut errors);
+    error::sort_errors(&mut errors);
+    errors
 }
 
+const MISSING_ROOT_HELP: &str =
+    "add a struct named \"root\", or declare the entry points with `root <name>;`";
+
 fn check_root_element(schema: &TSchema) -> Result<(), CartaError> {
-    return if !schema.types.contains_key("root") {
-        Err(CartaError::new_missing_root_element(0))
-    } else {
+    // Declared entry points have already been checked during type checking, so the only root that
+    // can be missing is the default "root" struct
+    if schema
+        .roots
+        .iter()
+        .all(|root| schema.types.contains_key(root))
+    {
         Ok(())
-    };
+    } else {
+        Err(CartaError::new_missing_root_element().with_help(MISSING_ROOT_HELP))
+    }
 }
 
-fn check_array_lengths(schema: &TSchema) -> Result<(), CartaError> {
-    for (_name, struct_defn) in &schema.types {
+fn check_array_lengths(schema: &TSchema, errors: &mut Vec<CartaError>) {
+    for struct_defn in schema.types.values() {
         for i in 0..struct_defn.elements.len() {
-            if let ElementTypeRef::ArrayElem(arr) = &struct_defn.elements[i].kind {
-                check_array_elem(struct_defn, arr, i)?;
+            errors.extend(check_type_lengths(struct_defn, &struct_defn.elements[i].kind, i).err());
+        }
+    }
+}
+
+/// Check the lengths of any arrays in `kind`, which is the type of element `elem_idx`.  Nested
+/// arrays are checked all the way down, as their lengths are all relative to the enclosing struct.
+fn check_type_lengths(
+    struct_defn: &StructDefn,
+    kind: &ElementTypeRef,
+    elem_idx: usize,
+) -> Result<(), CartaError> {
+    match kind {
+        ElementTypeRef::ArrayElem(arr) => {
+            check_array_elem(struct_defn, &arr.length, elem_idx)?;
+            check_type_lengths(struct_defn, &arr.kind, elem_idx)
+        }
+        ElementTypeRef::Bytes(length) => check_array_elem(struct_defn, length, elem_idx),
+        // Expressions are checked separately
+        ElementTypeRef::Computed(_) | ElementTypeRef::TypeName(_) => Ok(()),
+    }
+}
+
+/// Elements with an integer value, which can be used in lengths, placements and expressions.
+/// Either a builtin integer type small enough for their 64 bit arithmetic, or a computed value.
+fn is_integer_element(element: &Element) -> bool {
+    match &element.kind {
+        ElementTypeRef::TypeName(typename) => builtin_types::is_length_integer(typename),
+        ElementTypeRef::Computed(_) => true,
+        _ => false,
+    }
+}
+
+/// Check the expressions used by each element, for placements, array lengths and computed values
+fn check_expressions(schema: &TSchema, errors: &mut Vec<CartaError>) {
+    for struct_defn in schema.types.values() {
+        for (i, element) in struct_defn.elements.iter().enumerate() {
+            for expr in element.exprs() {
+                errors.extend(check_expr(schema, struct_defn, expr, i).err());
             }
         }
     }
+}
 
+/// Expressions can only refer to elements listed earlier in the struct, which are known by the
+/// time the expression is evaluated.  Elements used for their value must be integers, and types
+/// used with sizeof must have a fixed size.
+fn check_expr(
+    schema: &TSchema,
+    struct_defn: &StructDefn,
+    expr: &Expr,
+    elem_idx: usize,
+) -> Result<(), CartaError> {
+    let span = struct_defn.elements[elem_idx].span;
+    let earlier = &struct_defn.elements[..elem_idx];
+    for id in expr.identifiers() {
+        let elem = earlier.iter().find(|elem| elem.name == id);
+        if !elem.is_some_and(is_integer_element) {
+            return Err(CartaError::new_bad_expression(span, id));
+        }
+    }
+    for id in expr.offsetof_fields() {
+        if !earlier.iter().any(|elem| elem.name == id) {
+            return Err(CartaError::new_bad_offsetof(span, id));
+        }
+    }
+    for typename in expr.sizeof_types() {
+        if schema.static_size(typename).is_none() {
+            return Err(CartaError::new_bad_sizeof(span, typename));
+        }
+    }
     Ok(())
 }
 
 fn check_array_elem(
     struct_defn: &StructDefn,
-    arr: &ArrayDefn,
+    length: &ArrayLen,
     arr_idx: usize,
 ) -> Result<(), CartaError> {
-    match &arr.length {
-        // Nothing to check
-        ArrayLen::Static(_) => Ok(()),
+    match length {
+        // Nothing to check.  Expressions are checked separately.
+        ArrayLen::Static(_) | ArrayLen::Expr(_) => Ok(()),
         // Check that the element we reference is a builtin integer type
         ArrayLen::Identifier(id) => {
             // Array length must be listed earlier in the struct than the array, so we know what the length of the array
             // is.  Iterate only up to arr_idx.
-            for j in 0..arr_idx {
-                if struct_defn.elements[j].name == *id {
-                    // Check that this element is a builtin type that is an integer type
-                    if let ElementTypeRef::TypeName(typename) = &struct_defn.elements[j].kind {
-                        if builtin_types::is_type_class(typename, BuiltinTypeClass::Integer) {
-                            return Ok(());
-                        } else {
-                            return Err(CartaError::new_bad_array_len_type(struct_defn.elements[arr_idx].line_no, id));
-                        }
-                    } else {
-                        return Err(CartaError::new_bad_array_len_type(struct_defn.elements[arr_idx].line_no, id));
-                    }
+            match struct_defn.elements[..arr_idx]
+                .iter()
+                .find(|elem| elem.name == *id)
+            {
+                // Check that this element has an integer value
+                Some(elem) if is_integer_element(elem) => Ok(()),
+                Some(_) => Err(CartaError::new_bad_array_len_type(
+                    struct_defn.elements[arr_idx].span,
+                    id,
+                )),
+                None => {
+                    let earlier = struct_defn.elements[..arr_idx]
+                        .iter()
+                        .map(|elem| elem.name.as_str());
+                    Err(
+                        CartaError::new_bad_array_len(struct_defn.elements[arr_idx].span, id)
+                            .with_suggestion(id, earlier),
+                    )
                 }
             }
-            Err(CartaError::new_bad_array_len(struct_defn.elements[arr_idx].line_no, id))
         }
     }
 }
@@ -64,24 +154,45 @@ fn check_array_elem(
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::ordered_map::OrderedMap;
     use crate::parser;
     use crate::parser::{ArrayDefn, Element, StructDefn};
+    use crate::span::{LineSpans, Span};
     use crate::tokeniser;
     use crate::type_check;
 
-    use std::collections::HashMap;
-
     fn build_schema_with_elem(name: String) -> TSchema {
-        let mut types = HashMap::new();
+        let mut types = OrderedMap::new();
         types.insert(
             name.clone(),
             StructDefn {
                 name,
                 elements: Vec::new(),
-                line_no: 1,
+                span: Span::from(1),
+                base: None,
+                num_inherited: 0,
+                is_union: false,
             },
         );
-        TSchema { types }
+        TSchema {
+            types,
+            roots: vec!["root".to_string()],
+            layouts: OrderedMap::new(),
+        }
+    }
+
+    fn type_checked(data: &str) -> TSchema {
+        let tokeniser = tokeniser::Tokeniser::new(data).unwrap();
+        let schema = parser::compile_schema(tokeniser).unwrap();
+        type_check::type_check_schema(schema).unwrap()
+    }
+
+    fn check(data: &str) -> Result<(), CartaError> {
+        check_schema(&type_checked(data)).line_spans()
+    }
+
+    fn errors(data: &str) -> Vec<CartaError> {
+        check_schema_with_errors(&type_checked(data)).line_spans()
     }
 
     #[test]
@@ -94,8 +205,19 @@ mod test {
     #[test]
     fn no_root() {
         let schema = build_schema_with_elem("notroot".to_string());
-        let res = check_schema(&schema);
-        assert_eq!(res, Err(CartaError::new_missing_root_element(0)));
+        let res = check_schema(&schema).line_spans();
+        assert_eq!(
+            res,
+            Err(CartaError::new_missing_root_element().with_help(MISSING_ROOT_HELP))
+        );
+    }
+
+    #[test]
+    fn declared_root() -> Result<(), CartaError> {
+        let mut schema = build_schema_with_elem("Png".to_string());
+        schema.roots = vec!["Png".to_string()];
+        check_schema(&schema)?;
+        Ok(())
     }
 
     #[test]
@@ -108,36 +230,177 @@ mod test {
                 elements: vec![Element {
                     name: "foo".to_string(),
                     kind: ElementTypeRef::ArrayElem(ArrayDefn {
-                        kind: "int8".to_string(),
+                        kind: Box::new(ElementTypeRef::TypeName("int8".to_string())),
                         length: ArrayLen::Identifier("unknown".to_string()),
                     }),
-                    line_no: 2,
+                    span: Span::from(2),
+                    peek: false,
+                    placement: None,
                 }],
-                line_no: 1
+                span: Span::from(1),
+                base: None,
+                num_inherited: 0,
+                is_union: false,
             },
         );
-        let res = check_schema(&schema);
-        assert_eq!(res, Err(CartaError::new_bad_array_len(2, "unknown")));
+        let res = check_schema(&schema).line_spans();
+        assert_eq!(
+            res,
+            Err(CartaError::new_bad_array_len(Span::from(2), "unknown"))
+        );
     }
 
     #[test]
     fn arr_len_not_builtin() {
         let data =
             "struct root {var1: Version, var2: [uint16_be; var1]} struct Version {major: f64_le}";
-        let tokeniser = tokeniser::Tokeniser::new(&data).unwrap();
-        let schema = parser::compile_schema(tokeniser).unwrap();
-        let tschema = type_check::type_check_schema(schema).unwrap();
-        let res = check_schema(&tschema);
-        assert_eq!(res, Err(CartaError::new_bad_array_len_type(1, "var1")));
+        assert_eq!(
+            check(data),
+            Err(CartaError::new_bad_array_len_type(Span::from(1), "var1"))
+        );
+    }
+
+    #[test]
+    fn nested_arr_bad_len() {
+        let data = "struct root {var1: uint8, var2: [[uint8; var1]; var3]}";
+        assert_eq!(
+            check(data),
+            Err(CartaError::new_bad_array_len(Span::from(1), "var3")
+                .with_help("did you mean `var1`?"))
+        );
+
+        let data = "struct root {var1: uint8, var2: [[uint8; var3]; var1]}";
+        assert_eq!(
+            check(data),
+            Err(CartaError::new_bad_array_len(Span::from(1), "var3")
+                .with_help("did you mean `var1`?"))
+        );
+    }
+
+    #[test]
+    fn bytes_len_not_integer() {
+        let data = "struct root {var1: f32_be, var2: bytes[var1]}";
+        assert_eq!(
+            check(data),
+            Err(CartaError::new_bad_array_len_type(Span::from(1), "var1"))
+        );
+    }
+
+    #[test]
+    fn bytes_bad_len() {
+        let data = "struct root {var1: uint8, var2: bytes[var3]}";
+        assert_eq!(
+            check(data),
+            Err(CartaError::new_bad_array_len(Span::from(1), "var3")
+                .with_help("did you mean `var1`?"))
+        );
+    }
+
+    #[test]
+    fn bad_len_suggestions() {
+        let data = "struct root {count: uint8, a: [uint8; cuont]}";
+        assert_eq!(
+            check(data),
+            Err(CartaError::new_bad_array_len(Span::from(1), "cuont")
+                .with_help("did you mean `count`?"))
+        );
+
+        // Only earlier elements can be used as lengths, so only they are suggested
+        let data = "struct root {a: [uint8; cuont], count: uint8}";
+        assert_eq!(
+            check(data),
+            Err(CartaError::new_bad_array_len(Span::from(1), "cuont"))
+        );
     }
 
     #[test]
     fn arr_len_not_integer() {
         let data = "struct root {var1: f32_be, var2: [uint16_le; var1]}";
-        let tokeniser = tokeniser::Tokeniser::new(&data).unwrap();
-        let schema = parser::compile_schema(tokeniser).unwrap();
-        let tschema = type_check::type_check_schema(schema).unwrap();
-        let res = check_schema(&tschema);
-        assert_eq!(res, Err(CartaError::new_bad_array_len_type(1, "var1")));
+        assert_eq!(
+            check(data),
+            Err(CartaError::new_bad_array_len_type(Span::from(1), "var1"))
+        );
+
+        let data = "struct root {var1: uint128_le, var2: bytes[var1], var3: [uint8; var1 + 1]}";
+        assert_eq!(
+            errors(data),
+            vec![
+                CartaError::new_bad_array_len_type(Span::from(1), "var1"),
+                CartaError::new_bad_expression(Span::from(1), "var1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_placement() {
+        for (data, id) in [
+            ("struct root {var1: uint8 @ var2, var2: uint32_le}", "var2"),
+            ("struct root {var1: f32_le, var2: uint8 @ var1 + 4}", "var1"),
+        ] {
+            assert_eq!(
+                check(data),
+                Err(CartaError::new_bad_expression(Span::from(1), id))
+            );
+        }
+
+        let data = "struct root {var1: uint32_le, var2: uint8 @ var1 + 4}";
+        assert_eq!(check(data), Ok(()));
+    }
+
+    #[test]
+    fn sizeof_offsetof() {
+        let data = "struct root {a: uint8, b: Fixed, c: bytes[sizeof(Fixed) - offsetof(b)]}
+            struct Fixed {x: uint16_le, y: [uint8; 2], let z = x; peek w: uint32_le}";
+        assert_eq!(check(data), Ok(()));
+
+        let data =
+            "struct root {a: uint8, b: bytes[sizeof(Var)]} struct Var {n: uint8, d: bytes[n]}";
+        assert_eq!(
+            check(data),
+            Err(CartaError::new_bad_sizeof(Span::from(1), "Var"))
+        );
+
+        let data = "struct root {a: uint8, b: bytes[offsetof(c)], c: uint8}";
+        assert_eq!(
+            check(data),
+            Err(CartaError::new_bad_offsetof(Span::from(1), "c"))
+        );
+    }
+
+    #[test]
+    fn computed() {
+        let data = "struct root {a: uint8, let b = a * 2; c: bytes[b], d: uint8 @ b}";
+        assert_eq!(check(data), Ok(()));
+
+        for (data, id) in [
+            ("struct root {let b = a * 2; a: uint8}", "a"),
+            ("struct root {a: f32_le, let b = a * 2;}", "a"),
+            ("struct root {a: uint8, let b = a * c;}", "c"),
+        ] {
+            assert_eq!(
+                check(data),
+                Err(CartaError::new_bad_expression(Span::from(1), id))
+            );
+        }
+    }
+
+    #[test]
+    fn multiple_errors() {
+        let data = "
+            struct root {a: [uint8; x], b: f32_le, c: bytes[b]}
+            struct Base {d: uint8 @ sizeof(Other), e: uint8}
+            struct Derived extends Base {let f = offsetof(g);}
+            struct Other {len: uint8, data: bytes[len]}
+        ";
+        // Errors in inherited elements are only reported once
+        assert_eq!(
+            errors(data),
+            vec![
+                CartaError::new_bad_array_len(Span::from(2), "x"),
+                CartaError::new_bad_array_len_type(Span::from(2), "b"),
+                CartaError::new_bad_sizeof(Span::from(3), "Other"),
+                CartaError::new_bad_offsetof(Span::from(4), "g"),
+            ]
+        );
     }
 }
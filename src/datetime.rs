@@ -0,0 +1,239 @@
+/*!
+ * Date and time formatting for the timestamp builtin types.
+ *
+ * All conversions are done with plain integer arithmetic on the proleptic Gregorian calendar, so
+ * there is no dependence on the host's time zone database.
+ */
+
+/// Seconds between the Unix epoch (1970-01-01) and the Windows FILETIME epoch (1601-01-01)
+const FILETIME_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// Seconds between the Mac HFS epoch (1904-01-01) and the Unix epoch
+const HFS_EPOCH_OFFSET: i64 = 2_082_844_800;
+
+/// Unix time of the GPS epoch (1980-01-06)
+const GPS_EPOCH: i64 = 315_964_800;
+
+/// Unix times at which each leap second since the GPS epoch took effect.  GPS time does not
+/// include leap seconds, so it runs ahead of UTC by one second per entry.
+const LEAP_SECONDS: [i64; 18] = [
+    362_793_600,   // 1981-07-01
+    394_329_600,   // 1982-07-01
+    425_865_600,   // 1983-07-01
+    489_024_000,   // 1985-07-01
+    567_993_600,   // 1988-01-01
+    631_152_000,   // 1990-01-01
+    662_688_000,   // 1991-01-01
+    709_948_800,   // 1992-07-01
+    741_484_800,   // 1993-07-01
+    773_020_800,   // 1994-07-01
+    820_454_400,   // 1996-01-01
+    867_715_200,   // 1997-07-01
+    915_148_800,   // 1999-01-01
+    1_136_073_600, // 2006-01-01
+    1_230_768_000, // 2009-01-01
+    1_341_100_800, // 2012-07-01
+    1_435_708_800, // 2015-07-01
+    1_483_228_800, // 2017-01-01
+];
+
+/// Convert a count of days since 1970-01-01 to a (year, month, day) civil date.
+///
+/// Algorithm from Howard Hinnant's "chrono-Compatible Low-Level Date Algorithms".
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Months counted from March, so the leap day is at the end of the year
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+fn format_year(year: i64) -> String {
+    // ISO 8601 requires an explicit sign for years outside of 0000-9999
+    if (0..=9999).contains(&year) {
+        format!("{:04}", year)
+    } else {
+        format!("{:+05}", year)
+    }
+}
+
+/// Format a count of seconds since the Unix epoch as an ISO 8601 UTC timestamp.  `nanos` are only
+/// displayed when non-zero.
+fn format_unix_nanos(secs: i64, nanos: u32) -> String {
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let secs_of_day = secs.rem_euclid(86_400);
+    let time = format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        format_year(year),
+        month,
+        day,
+        secs_of_day / 3600,
+        (secs_of_day / 60) % 60,
+        secs_of_day % 60
+    );
+    if nanos == 0 {
+        time + "Z"
+    } else {
+        // Trim trailing zeros from the fractional seconds
+        let frac = format!("{:09}", nanos);
+        format!("{}.{}Z", time, frac.trim_end_matches('0'))
+    }
+}
+
+pub fn format_unix(secs: i64) -> String {
+    format_unix_nanos(secs, 0)
+}
+
+/// Windows FILETIME: 100 nanosecond intervals since 1601-01-01 UTC
+pub fn format_filetime(ticks: u64) -> String {
+    let secs = (ticks / 10_000_000) as i64 - FILETIME_EPOCH_OFFSET;
+    let nanos = (ticks % 10_000_000) as u32 * 100;
+    format_unix_nanos(secs, nanos)
+}
+
+/// Mac HFS/HFS+ time: seconds since 1904-01-01 UTC
+pub fn format_hfs(secs: u32) -> String {
+    format_unix(secs as i64 - HFS_EPOCH_OFFSET)
+}
+
+/// GPS time: seconds since 1980-01-06, without leap seconds
+pub fn format_gps(secs: u32) -> String {
+    let gps_unix = secs as i64 + GPS_EPOCH;
+    let mut leap_seconds = 0;
+    for leap in LEAP_SECONDS.iter() {
+        if gps_unix - (leap_seconds + 1) >= *leap {
+            leap_seconds += 1;
+        }
+    }
+    format_unix(gps_unix - leap_seconds)
+}
+
+/// Value shown for DOS timestamps whose fields are out of range, eg. month 0
+pub const INVALID_DOS_VALUE: &str = "<invalid DOS date/time>";
+
+fn is_leap_year(year: u32) -> bool {
+    year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// MS-DOS date and time, as stored in FAT directory entries and ZIP headers: a 16-bit time
+/// followed by a 16-bit date, with 2 second resolution.  DOS timestamps are in local time, with no
+/// record of the time zone, so no UTC designator is added.
+pub fn format_dos(time: u16, date: u16) -> String {
+    let year = 1980 + (date >> 9) as u32;
+    let month = ((date >> 5) & 0xf) as u32;
+    let day = (date & 0x1f) as u32;
+    let hour = time >> 11;
+    let minute = (time >> 5) & 0x3f;
+    let second = (time & 0x1f) * 2;
+
+    // Unused timestamps are often left as zero, which isn't a valid date
+    if !(1..=12).contains(&month)
+        || day == 0
+        || day > days_in_month(year, month)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return INVALID_DOS_VALUE.to_string();
+    }
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year, month, day, hour, minute, second
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unix() {
+        assert_eq!(format_unix(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_unix(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(format_unix(i32::MAX as i64), "2038-01-19T03:14:07Z");
+        assert_eq!(format_unix(-1), "1969-12-31T23:59:59Z");
+        assert_eq!(format_unix(i32::MIN as i64), "1901-12-13T20:45:52Z");
+        assert_eq!(format_unix(253_402_300_800), "+10000-01-01T00:00:00Z");
+        assert_eq!(format_unix(-62_167_219_201), "-0001-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn filetime() {
+        assert_eq!(format_filetime(0), "1601-01-01T00:00:00Z");
+        assert_eq!(
+            format_filetime(116_444_736_000_000_000),
+            "1970-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            format_filetime(132_223_104_001_234_500),
+            "2020-01-01T00:00:00.12345Z"
+        );
+    }
+
+    #[test]
+    fn hfs() {
+        assert_eq!(format_hfs(0), "1904-01-01T00:00:00Z");
+        assert_eq!(format_hfs(u32::MAX), "2040-02-06T06:28:15Z");
+    }
+
+    #[test]
+    fn gps() {
+        assert_eq!(format_gps(0), "1980-01-06T00:00:00Z");
+        // 2017-01-01 00:00:00 UTC, 18 leap seconds after the GPS epoch
+        assert_eq!(format_gps(1_167_264_018), "2017-01-01T00:00:00Z");
+        assert_eq!(format_gps(1_167_264_016), "2016-12-31T23:59:59Z");
+    }
+
+    #[test]
+    fn dos() {
+        assert_eq!(format_dos(0, 0x21), "1980-01-01T00:00:00");
+        // 2019-06-15 13:45:30
+        assert_eq!(format_dos(0x6daf, 0x4ecf), "2019-06-15T13:45:30");
+        // 2000-02-29, a leap day
+        assert_eq!(format_dos(0, 0x285d), "2000-02-29T00:00:00");
+    }
+
+    #[test]
+    fn invalid_dos() {
+        for (time, date) in [
+            // Month 0, day 0
+            (0, 0),
+            // Month 13
+            (0, 0x01a1),
+            // 2019-02-29, not a leap year
+            (0, 0x4e5d),
+            // 2019-04-31
+            (0, 0x4e9f),
+            // 24:00:00
+            (0xc000, 0x21),
+            // 00:60:00
+            (0x0780, 0x21),
+            // 00:00:62
+            (0x001f, 0x21),
+        ] {
+            assert_eq!(
+                format_dos(time, date),
+                INVALID_DOS_VALUE,
+                "{:04x} {:04x}",
+                time,
+                date
+            );
+        }
+    }
+}
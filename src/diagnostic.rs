@@ -0,0 +1,348 @@
+/*!
+ * Diagnostics
+ *
+ * Render compile errors for display, in the same style as rustc: the error message, where it is,
+ * and the source it refers to with the offending text underlined.  For example:
+ *
+ * ```text
+ * error[E0002]: Duplicate definition for type: Header
+ *  --> png.carta:7:1
+ *   |
+ * 2 | struct Header {
+ *   | ------------- first defined here
+ * ...
+ * 7 | struct Header {}
+ *   | ^^^^^^^^^^^^^^^^
+ * ```
+ */
+
+use crate::error::{CartaError, CartaWarning, Label};
+use crate::span::Span;
+
+const RED: &str = "1;31";
+const YELLOW: &str = "1;33";
+const BLUE: &str = "1;34";
+const BOLD: &str = "1";
+
+/// Renders errors along with the schema source they were found in
+pub struct Renderer<'a> {
+    file_name: &'a str,
+    source: &'a str,
+    colour: bool,
+}
+
+/// Text underlined in the source, with a note on what it is
+struct Annotation<'a> {
+    span: Span,
+    mark: char,
+    colour: &'static str,
+    message: &'a str,
+}
+
+impl<'a> Renderer<'a> {
+    /// Renderer for errors in `source`, which was read from `file_name`.  Output is plain text.
+    pub fn new(file_name: &'a str, source: &'a str) -> Renderer<'a> {
+        Renderer {
+            file_name,
+            source,
+            colour: false,
+        }
+    }
+
+    /// Highlight the output with ANSI colour codes, for display on a terminal
+    pub fn with_colour(mut self, colour: bool) -> Renderer<'a> {
+        self.colour = colour;
+        self
+    }
+
+    /// Render a list of errors, separated by blank lines
+    pub fn render_all(&self, errors: &[CartaError]) -> String {
+        errors
+            .iter()
+            .map(|err| self.render(err))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn render(&self, err: &CartaError) -> String {
+        let level = format!("error[{}]", err.code.id());
+        let message = err.code.to_string();
+        self.render_diagnostic(&level, RED, &message, err.span, &err.labels, &err.help)
+    }
+
+    /// Render a list of warnings, separated by blank lines
+    pub fn render_warnings(&self, warnings: &[CartaWarning]) -> String {
+        warnings
+            .iter()
+            .map(|warning| self.render_warning(warning))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn render_warning(&self, warning: &CartaWarning) -> String {
+        let level = format!("warning[{}]", warning.code.id());
+        let message = warning.code.to_string();
+        self.render_diagnostic(&level, YELLOW, &message, warning.span, &warning.labels, &[])
+    }
+
+    /// Render an error or warning.  `level` is the kind of diagnostic, shown in `colour`.
+    fn render_diagnostic(
+        &self,
+        level: &str,
+        colour: &'static str,
+        message: &str,
+        span: Span,
+        labels: &[Label],
+        help: &[String],
+    ) -> String {
+        let mut annotations = vec![Annotation {
+            span,
+            mark: '^',
+            colour,
+            message: "",
+        }];
+        for label in labels {
+            annotations.push(Annotation {
+                span: label.span,
+                mark: '-',
+                colour: BLUE,
+                message: &label.message,
+            });
+        }
+        // Errors that aren't anywhere in particular in the source don't have a snippet
+        annotations.retain(|annotation| {
+            !annotation.span.is_nowhere() && self.line(annotation.span.start.line).is_some()
+        });
+        annotations.sort_by_key(|annotation| annotation.span.start);
+
+        // Line numbers in the gutter are all right aligned
+        let width = annotations
+            .iter()
+            .map(|annotation| annotation.span.start.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = self.paint(&format!("{} |", " ".repeat(width)), BLUE);
+
+        let mut out = format!(
+            "{}{}\n",
+            self.paint(level, colour),
+            self.paint(&format!(": {}", message), BOLD)
+        );
+        out += &format!(
+            "{}{} {}\n",
+            " ".repeat(width),
+            self.paint("-->", BLUE),
+            self.location(span)
+        );
+
+        if !annotations.is_empty() {
+            out += &format!("{}\n", gutter);
+        }
+        let mut prev_line = None;
+        for annotation in &annotations {
+            let line_no = annotation.span.start.line;
+            let line = self.line(line_no).unwrap();
+            if prev_line != Some(line_no) {
+                // Show that lines have been skipped between annotations
+                if prev_line.is_some_and(|prev| line_no > prev + 1) {
+                    out += &format!("{}\n", self.paint("...", BLUE));
+                }
+                let number = self.paint(&format!("{:>width$} |", line_no, width = width), BLUE);
+                out += &format!("{} {}\n", number, line);
+            }
+            prev_line = Some(line_no);
+
+            let (indent, len) = underline_range(annotation.span, line);
+            let marks: String = std::iter::repeat(annotation.mark).take(len).collect();
+            let mut underline = format!("{}{}", indent, marks);
+            if !annotation.message.is_empty() {
+                underline += &format!(" {}", annotation.message);
+            }
+            out += &format!("{} {}\n", gutter, self.paint(&underline, annotation.colour));
+        }
+
+        if !help.is_empty() {
+            out += &format!("{}\n", gutter);
+        }
+        for help in help {
+            out += &format!(
+                "{} {} {}\n",
+                " ".repeat(width),
+                self.paint("= help:", BOLD),
+                help
+            );
+        }
+        out
+    }
+
+    /// Line of the source, by line number starting from 1
+    fn line(&self, line_no: usize) -> Option<&str> {
+        line_no
+            .checked_sub(1)
+            .and_then(|idx| self.source.lines().nth(idx))
+    }
+
+    /// File name, line and column of the start of a span
+    fn location(&self, span: Span) -> String {
+        match (span.start.line, span.start.column) {
+            _ if span.is_nowhere() => self.file_name.to_string(),
+            (line, 0) => format!("{}:{}", self.file_name, line),
+            (line, column) => format!("{}:{}:{}", self.file_name, line, column),
+        }
+    }
+
+    fn paint(&self, text: &str, colour: &str) -> String {
+        if self.colour {
+            format!("\x1b[{}m{}\x1b[0m", colour, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// Whitespace to line up an underline with the start of the span in `line`, and the number of
+/// characters to underline.  Spans over several lines are underlined to the end of their first
+/// line.  Spans with an unknown column underline the whole line.
+fn underline_range(span: Span, line: &str) -> (String, usize) {
+    let chars: Vec<char> = line.chars().collect();
+    let (start, end) = if span.start.column == 0 {
+        let start = chars.iter().take_while(|c| c.is_whitespace()).count();
+        let end = chars.len() - chars.iter().rev().take_while(|c| c.is_whitespace()).count();
+        (start, end)
+    } else if span.end.line == span.start.line {
+        (span.start.column - 1, span.end.column - 1)
+    } else {
+        (span.start.column - 1, chars.len())
+    };
+    let start = start.min(chars.len());
+
+    // Keep tabs so the underline lines up however wide they're displayed
+    let indent = chars[..start]
+        .iter()
+        .map(|&c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+    (indent, end.saturating_sub(start).max(1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{compile_schema_file, compile_schema_file_with_warnings};
+
+    fn render(source: &str) -> String {
+        let errors = compile_schema_file(source).unwrap_err();
+        Renderer::new("test.carta", source).render_all(&errors)
+    }
+
+    #[test]
+    fn underline() {
+        let source = "struct root {\n\ta: uint8,\n\tb: Unknown,\n}";
+        assert_eq!(
+            render(source),
+            "error[E0001]: Unrecognized type: Unknown
+ --> test.carta:3:2
+  |
+3 | \tb: Unknown,
+  | \t^^^^^^^^^^
+"
+        );
+    }
+
+    #[test]
+    fn labels_and_help() {
+        let source = "struct A {}\nstruct root {}\n\n\nstruct A {x: uint8}";
+        assert_eq!(
+            render(source),
+            "error[E0002]: Duplicate definition for type: A
+ --> test.carta:5:1
+  |
+1 | struct A {}
+  | ----------- first defined here
+...
+5 | struct A {x: uint8}
+  | ^^^^^^^^^^^^^^^^^^^
+"
+        );
+
+        let source = "struct A {}";
+        assert_eq!(
+            render(source),
+            "error[E0008]: Missing struct \"root\"
+ --> test.carta
+  |
+  = help: add a struct named \"root\", or declare the entry points with `root <name>;`
+"
+        );
+    }
+
+    #[test]
+    fn multiple_errors() {
+        let source = "struct root {\n  a b,\n  c: [uint8; 2 }";
+        assert_eq!(
+            render(source),
+            "error[E0007]: Parse error!  Expected ':', found 'b'
+ --> test.carta:2:5
+  |
+2 |   a b,
+  |     ^
+
+error[E0007]: Parse error!  Expected ']', found '}'
+ --> test.carta:3:16
+  |
+3 |   c: [uint8; 2 }
+  |                ^
+"
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        let source = "struct root {a: uint8, a: uint8}";
+        let (_, warnings) = compile_schema_file_with_warnings(source).unwrap();
+        assert_eq!(
+            Renderer::new("test.carta", source).render_warnings(&warnings),
+            "warning[W0002]: Duplicate element name: a
+ --> test.carta:1:24
+  |
+1 | struct root {a: uint8, a: uint8}
+  |              -------- first defined here
+  |                        ^^^^^^^^
+"
+        );
+    }
+
+    #[test]
+    fn line_only_spans() {
+        let err = CartaError::new_unknown_type(Span::from(2), "X".to_string());
+        let rendered = Renderer::new("a.carta", "struct root {\n    x: X,\n}").render(&err);
+        assert_eq!(
+            rendered,
+            "error[E0001]: Unrecognized type: X
+ --> a.carta:2
+  |
+2 |     x: X,
+  |     ^^^^^
+"
+        );
+    }
+
+    #[test]
+    fn colour() {
+        let err = CartaError::new_unknown_type(Span::from(1), "X".to_string())
+            .with_help("check the name");
+        let rendered = Renderer::new("a.carta", "x: X")
+            .with_colour(true)
+            .render(&err);
+        assert_eq!(
+            rendered,
+            "\x1b[1;31merror[E0001]\x1b[0m\x1b[1m: Unrecognized type: X\x1b[0m
+ \x1b[1;34m-->\x1b[0m a.carta:1
+\x1b[1;34m  |\x1b[0m
+\x1b[1;34m1 |\x1b[0m x: X
+\x1b[1;34m  |\x1b[0m \x1b[1;31m^^^^\x1b[0m
+\x1b[1;34m  |\x1b[0m
+  \x1b[1m= help:\x1b[0m check the name
+"
+        );
+    }
+}
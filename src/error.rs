@@ -1,144 +1,499 @@
-use failure_derive::Fail;
+use std::error::Error;
+use std::fmt;
 
-#[derive(Fail, Debug, PartialEq)]
-#[fail(display = "Line {}: {}", _0, _1)]
+use crate::explain;
+use crate::span::Span;
+use crate::suggest;
+
+/// An error found while compiling a schema
+#[derive(Debug, PartialEq)]
 pub struct CartaError {
-    pub line_no: usize,
-    pub code: CartaErrorCode
+    pub code: CartaErrorCode,
+
+    // Exact source text the error refers to
+    pub span: Span,
+
+    // Other source text related to the error, eg. the first definition of a duplicate type
+    pub labels: Vec<Label>,
+
+    // Notes on how the error could be fixed
+    pub help: Vec<String>,
 }
 
-#[derive(Fail, Debug, PartialEq)]
+/// A secondary location shown alongside an error, with a message explaining its relevance
+#[derive(Debug, PartialEq, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// The kind of error, with the details needed to describe it.  More kinds of error may be added
+/// in future versions.
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
 pub enum CartaErrorCode {
-    #[fail(display = "Unrecognized type: {}", _0)]
+    /// A type name that isn't a builtin type or a declared struct
     UnknownType(String),
 
-    #[fail(display = "Duplicate definition for type: {}", _0)]
+    /// Two structs with the same name
     DuplicateType(String),
 
-    #[fail(display = "Recursive types: {:?}", _0)]
+    /// Structs that contain each other, so would take up infinite space
     RecursiveTypes(Vec<String>),
 
-    #[fail(display = "Unrecognized symbol: {}", _0)]
+    /// A character that can't appear anywhere in a schema
     UnknownSymbol(char),
 
-    #[fail(display = "Unexpected symbol.  Expected {}, found: {}", _0, _1)]
+    /// A character that can't appear where it was found.  Holds what was expected.
     UnexpectedSymbol(&'static str, char),
 
-    #[fail(display = "Unclosed block comment at end of file")]
     UnclosedBlockComment(),
 
-    #[fail(display = "Parse error!  Expected '{}', found '{}'", _0, _1)]
+    /// A token that can't appear where it was found.  Holds what was expected.
     ParseError(&'static str, String),
 
-    #[fail(display = "Missing struct \"root\"")]
+    /// No struct named "root", and no entry points declared
     MissingRootElement(),
 
-    #[fail(display = "Bad array length: {}", _0)]
+    /// An array length naming something that isn't an earlier element
     BadArrayLen(String),
 
-    #[fail(display = "Array length must be builtin integer type: {}", _0)]
+    /// An array length naming an element that isn't an integer
     BadArrayLenType(String),
 
-    #[fail(display = "Cannot start number with leading zero")]
     LeadingZero(),
 
-    #[fail(display = "Integer too large: Must be 9 digits or less")]
     IntegerTooLarge(),
 
-    #[fail(display = "Incomplete input")]
+    /// The schema ended part way through a declaration
     IncompleteInput(),
+
+    /// A struct extending a builtin type or a union
+    BadBaseType(String),
+
+    /// An expression using something other than an earlier integer element
+    BadExpression(String),
+
+    /// offsetof naming something that isn't an earlier element
+    BadOffsetOf(String),
+
+    /// sizeof of a type whose size depends on the data
+    BadSizeOf(String),
 }
 
-// Make errors slightly easier to construct
-impl CartaError {
-    pub fn new_unknown_type(line_no: usize, kind: String) -> CartaError {
-        CartaError {
-            line_no,
-            code: CartaErrorCode::UnknownType(kind),
+impl CartaErrorCode {
+    /// Stable identifier for this kind of error, eg. `E0001`.  Identifiers are never reused or
+    /// renumbered, so can be matched on by tools, and looked up with `explain`.
+    pub fn id(&self) -> &'static str {
+        match self {
+            CartaErrorCode::UnknownType(_) => "E0001",
+            CartaErrorCode::DuplicateType(_) => "E0002",
+            CartaErrorCode::RecursiveTypes(_) => "E0003",
+            CartaErrorCode::UnknownSymbol(_) => "E0004",
+            CartaErrorCode::UnexpectedSymbol(..) => "E0005",
+            CartaErrorCode::UnclosedBlockComment() => "E0006",
+            CartaErrorCode::ParseError(..) => "E0007",
+            CartaErrorCode::MissingRootElement() => "E0008",
+            CartaErrorCode::BadArrayLen(_) => "E0009",
+            CartaErrorCode::BadArrayLenType(_) => "E0010",
+            CartaErrorCode::LeadingZero() => "E0011",
+            CartaErrorCode::IntegerTooLarge() => "E0012",
+            CartaErrorCode::IncompleteInput() => "E0013",
+            CartaErrorCode::BadBaseType(_) => "E0014",
+            CartaErrorCode::BadExpression(_) => "E0015",
+            CartaErrorCode::BadOffsetOf(_) => "E0016",
+            CartaErrorCode::BadSizeOf(_) => "E0017",
         }
     }
 
-    pub fn new_duplicate_type(line_no: usize, kind: String) -> CartaError {
-        CartaError {
-            line_no,
-            code: CartaErrorCode::DuplicateType(kind),
+    /// Long form explanation of this kind of error, with examples
+    pub fn explanation(&self) -> &'static str {
+        explain::explain(self.id()).unwrap()
+    }
+}
+
+impl fmt::Display for CartaErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartaErrorCode::UnknownType(kind) => write!(f, "Unrecognized type: {}", kind),
+            CartaErrorCode::DuplicateType(kind) => {
+                write!(f, "Duplicate definition for type: {}", kind)
+            }
+            CartaErrorCode::RecursiveTypes(kinds) => write!(f, "Recursive types: {:?}", kinds),
+            CartaErrorCode::UnknownSymbol(sym) => write!(f, "Unrecognized symbol: {}", sym),
+            CartaErrorCode::UnexpectedSymbol(expected, got) => {
+                write!(
+                    f,
+                    "Unexpected symbol.  Expected {}, found: {}",
+                    expected, got
+                )
+            }
+            CartaErrorCode::UnclosedBlockComment() => {
+                write!(f, "Unclosed block comment at end of file")
+            }
+            CartaErrorCode::ParseError(expected, got) => {
+                write!(f, "Parse error!  Expected '{}', found '{}'", expected, got)
+            }
+            CartaErrorCode::MissingRootElement() => write!(f, "Missing struct \"root\""),
+            CartaErrorCode::BadArrayLen(len_desc) => write!(f, "Bad array length: {}", len_desc),
+            CartaErrorCode::BadArrayLenType(kind) => {
+                write!(f, "Array length must be builtin integer type: {}", kind)
+            }
+            CartaErrorCode::LeadingZero() => write!(f, "Cannot start number with leading zero"),
+            CartaErrorCode::IntegerTooLarge() => {
+                write!(f, "Integer too large: Must be 9 digits or less")
+            }
+            CartaErrorCode::IncompleteInput() => write!(f, "Incomplete input"),
+            CartaErrorCode::BadBaseType(kind) => write!(
+                f,
+                "Can only extend struct types, not builtins or unions: {}",
+                kind
+            ),
+            CartaErrorCode::BadExpression(id) => write!(
+                f,
+                "Expression can only refer to earlier integer elements: {}",
+                id
+            ),
+            CartaErrorCode::BadOffsetOf(id) => {
+                write!(f, "offsetof must refer to an earlier element: {}", id)
+            }
+            CartaErrorCode::BadSizeOf(kind) => {
+                write!(f, "sizeof requires a type with a fixed size: {}", kind)
+            }
         }
     }
+}
 
-    pub fn new_recursive_types(line_no: usize, kinds: Vec<String>) -> CartaError {
-        CartaError {
-            line_no,
-            code: CartaErrorCode::RecursiveTypes(kinds),
+impl fmt::Display for CartaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.span.is_nowhere() {
+            write!(f, "{}", self.code)
+        } else {
+            write!(f, "Line {}: {}", self.span.line_no(), self.code)
         }
     }
+}
+
+impl Error for CartaError {}
 
-    pub fn new_unknown_symbol(line_no: usize, sym: char) -> CartaError {
+// Make errors slightly easier to construct
+impl CartaError {
+    pub fn new(span: Span, code: CartaErrorCode) -> CartaError {
         CartaError {
-            line_no,
-            code: CartaErrorCode::UnknownSymbol(sym),
+            code,
+            span,
+            labels: Vec::new(),
+            help: Vec::new(),
         }
     }
 
-    pub fn new_unexpected_symbol(line_no: usize, expected: &'static str, got: char) -> CartaError {
-        CartaError {
-            line_no,
-            code: CartaErrorCode::UnexpectedSymbol(expected, got),
+    pub fn with_label(mut self, span: Span, message: &str) -> CartaError {
+        self.labels.push(Label {
+            span,
+            message: message.to_string(),
+        });
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> CartaError {
+        self.help.push(help.to_string());
+        self
+    }
+
+    /// Suggest the closest of `candidates` to the unrecognised `name`, if there's one that's close
+    pub fn with_suggestion<'a>(
+        self,
+        name: &str,
+        candidates: impl IntoIterator<Item = &'a str>,
+    ) -> CartaError {
+        match suggest::closest_match(name, candidates) {
+            Some(suggestion) => self.with_help(&format!("did you mean `{}`?", suggestion)),
+            None => self,
         }
     }
 
-    pub fn new_unclosed_block_comment(line_no: usize) -> CartaError {
-        CartaError {
-            line_no,
-            code: CartaErrorCode::UnclosedBlockComment(),
+    pub fn new_unknown_type(span: Span, kind: String) -> CartaError {
+        CartaError::new(span, CartaErrorCode::UnknownType(kind))
+    }
+
+    pub fn new_duplicate_type(span: Span, kind: String) -> CartaError {
+        CartaError::new(span, CartaErrorCode::DuplicateType(kind))
+    }
+
+    pub fn new_recursive_types(span: Span, kinds: Vec<String>) -> CartaError {
+        CartaError::new(span, CartaErrorCode::RecursiveTypes(kinds))
+    }
+
+    pub fn new_unknown_symbol(span: Span, sym: char) -> CartaError {
+        CartaError::new(span, CartaErrorCode::UnknownSymbol(sym))
+    }
+
+    pub fn new_unexpected_symbol(span: Span, expected: &'static str, got: char) -> CartaError {
+        CartaError::new(span, CartaErrorCode::UnexpectedSymbol(expected, got))
+    }
+
+    pub fn new_unclosed_block_comment(span: Span) -> CartaError {
+        CartaError::new(span, CartaErrorCode::UnclosedBlockComment())
+    }
+
+    pub fn new_parse_error(span: Span, expected: &'static str, got: String) -> CartaError {
+        CartaError::new(span, CartaErrorCode::ParseError(expected, got))
+    }
+
+    pub fn new_missing_root_element() -> CartaError {
+        CartaError::new(Span::nowhere(), CartaErrorCode::MissingRootElement())
+    }
+
+    pub fn new_bad_array_len(span: Span, len_desc: &str) -> CartaError {
+        CartaError::new(span, CartaErrorCode::BadArrayLen(len_desc.to_string()))
+    }
+
+    pub fn new_bad_array_len_type(span: Span, kind: &str) -> CartaError {
+        CartaError::new(span, CartaErrorCode::BadArrayLenType(kind.to_string()))
+    }
+
+    pub fn new_leading_zero(span: Span) -> CartaError {
+        CartaError::new(span, CartaErrorCode::LeadingZero())
+    }
+
+    pub fn new_integer_too_large(span: Span) -> CartaError {
+        CartaError::new(span, CartaErrorCode::IntegerTooLarge())
+    }
+
+    pub fn new_incomplete_input(span: Span) -> CartaError {
+        CartaError::new(span, CartaErrorCode::IncompleteInput())
+    }
+
+    pub fn new_bad_base_type(span: Span, kind: &str) -> CartaError {
+        CartaError::new(span, CartaErrorCode::BadBaseType(kind.to_string()))
+    }
+
+    pub fn new_bad_expression(span: Span, id: &str) -> CartaError {
+        CartaError::new(span, CartaErrorCode::BadExpression(id.to_string()))
+    }
+
+    pub fn new_bad_offsetof(span: Span, id: &str) -> CartaError {
+        CartaError::new(span, CartaErrorCode::BadOffsetOf(id.to_string()))
+    }
+
+    pub fn new_bad_sizeof(span: Span, kind: &str) -> CartaError {
+        CartaError::new(span, CartaErrorCode::BadSizeOf(kind.to_string()))
+    }
+}
+
+/// Something suspicious about a schema that still compiles, eg. a struct that's never used
+#[derive(Debug, PartialEq)]
+pub struct CartaWarning {
+    pub code: CartaWarningCode,
+
+    // Exact source text the warning refers to
+    pub span: Span,
+
+    // Other source text related to the warning, eg. the first element with a duplicated name
+    pub labels: Vec<Label>,
+}
+
+/// The kind of warning, with the details needed to describe it.  More kinds of warning may be
+/// added in future versions.
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum CartaWarningCode {
+    /// A struct that can't be reached from any of the roots
+    UnusedStruct(String),
+
+    /// An element with the same name as an earlier element in its struct
+    DuplicateElement(String),
+
+    /// An element named after a builtin type
+    ShadowsBuiltin(String),
+
+    EmptyStruct(String),
+
+    /// An array length taken from a signed integer element
+    SignedArrayLen(String),
+}
+
+impl CartaWarningCode {
+    /// Stable identifier for this kind of warning, eg. `W0001`.  Like error identifiers, these are
+    /// never reused, and can be looked up with `explain`.
+    pub fn id(&self) -> &'static str {
+        match self {
+            CartaWarningCode::UnusedStruct(_) => "W0001",
+            CartaWarningCode::DuplicateElement(_) => "W0002",
+            CartaWarningCode::ShadowsBuiltin(_) => "W0003",
+            CartaWarningCode::EmptyStruct(_) => "W0004",
+            CartaWarningCode::SignedArrayLen(_) => "W0005",
         }
     }
 
-    pub fn new_parse_error(line_no: usize, expected: &'static str, got: String) -> CartaError {
-        CartaError {
-            line_no,
-            code: CartaErrorCode::ParseError(expected, got),
+    /// Long form explanation of this kind of warning, with examples
+    pub fn explanation(&self) -> &'static str {
+        explain::explain(self.id()).unwrap()
+    }
+}
+
+impl fmt::Display for CartaWarningCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartaWarningCode::UnusedStruct(name) => write!(f, "Struct is never used: {}", name),
+            CartaWarningCode::DuplicateElement(name) => {
+                write!(f, "Duplicate element name: {}", name)
+            }
+            CartaWarningCode::ShadowsBuiltin(name) => {
+                write!(f, "Element name shadows a builtin type: {}", name)
+            }
+            CartaWarningCode::EmptyStruct(name) => write!(f, "Struct has no elements: {}", name),
+            CartaWarningCode::SignedArrayLen(name) => {
+                write!(f, "Array length uses a signed type: {}", name)
+            }
         }
     }
+}
 
-    pub fn new_missing_root_element(line_no: usize) -> CartaError {
-        CartaError {
-            line_no,
-            code: CartaErrorCode::MissingRootElement(),
+impl fmt::Display for CartaWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {}: {}", self.span.line_no(), self.code)
+    }
+}
+
+impl CartaWarning {
+    pub fn new(span: Span, code: CartaWarningCode) -> CartaWarning {
+        CartaWarning {
+            code,
+            span,
+            labels: Vec::new(),
         }
     }
 
-    pub fn new_bad_array_len(line_no: usize, len_desc: &str) -> CartaError {
-        CartaError {
-            line_no,
-            code: CartaErrorCode::BadArrayLen(len_desc.to_string()),
+    pub fn with_label(mut self, span: Span, message: &str) -> CartaWarning {
+        self.labels.push(Label {
+            span,
+            message: message.to_string(),
+        });
+        self
+    }
+
+    pub fn new_unused_struct(span: Span, name: &str) -> CartaWarning {
+        CartaWarning::new(span, CartaWarningCode::UnusedStruct(name.to_string()))
+    }
+
+    pub fn new_duplicate_element(span: Span, name: &str) -> CartaWarning {
+        CartaWarning::new(span, CartaWarningCode::DuplicateElement(name.to_string()))
+    }
+
+    pub fn new_shadows_builtin(span: Span, name: &str) -> CartaWarning {
+        CartaWarning::new(span, CartaWarningCode::ShadowsBuiltin(name.to_string()))
+    }
+
+    pub fn new_empty_struct(span: Span, name: &str) -> CartaWarning {
+        CartaWarning::new(span, CartaWarningCode::EmptyStruct(name.to_string()))
+    }
+
+    pub fn new_signed_array_len(span: Span, name: &str) -> CartaWarning {
+        CartaWarning::new(span, CartaWarningCode::SignedArrayLen(name.to_string()))
+    }
+}
+
+/// Why a cached compiled schema couldn't be loaded.  The schema needs to be compiled from its
+/// source again.
+#[cfg(feature = "serde")]
+#[derive(Debug, PartialEq)]
+#[non_exhaustive]
+pub enum CacheError {
+    /// The data isn't a cached schema, or is corrupt
+    BadFormat(String),
+
+    /// The schema was cached by a different version of this library.  Holds the version.
+    WrongVersion(String),
+
+    /// The schema was compiled from different source
+    Stale,
+}
+
+#[cfg(feature = "serde")]
+impl fmt::Display for CacheError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CacheError::BadFormat(reason) => write!(f, "Not a cached schema: {}", reason),
+            CacheError::WrongVersion(version) => {
+                write!(f, "Schema was cached by a different version: {}", version)
+            }
+            CacheError::Stale => write!(f, "Schema source has changed since it was cached"),
         }
     }
+}
 
-    pub fn new_bad_array_len_type(line_no: usize, kind: &str) -> CartaError {
-        CartaError {
-            line_no,
-            code: CartaErrorCode::BadArrayLenType(kind.to_string()),
+#[cfg(feature = "serde")]
+impl Error for CacheError {}
+
+/// Sort errors into the order they appear in the source, dropping any that were reported more
+/// than once
+pub fn sort_errors(errors: &mut Vec<CartaError>) {
+    errors.sort_by_key(|err| err.span.start);
+    errors.dedup();
+}
+
+/// Keep only the first of a compilation stage's errors, for tests that check for one error
+#[cfg(test)]
+pub fn first_error<T>(value: T, errors: Vec<CartaError>) -> Result<T, CartaError> {
+    match errors.into_iter().next() {
+        Some(err) => Err(err),
+        None => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::span::LineSpans;
+
+    impl LineSpans for CartaError {
+        fn line_spans(self) -> CartaError {
+            CartaError {
+                span: self.span.line_spans(),
+                labels: self.labels.line_spans(),
+                ..self
+            }
         }
     }
 
-    pub fn new_leading_zero(line_no: usize) -> CartaError {
-        CartaError {
-            line_no,
-            code: CartaErrorCode::LeadingZero(),
+    impl LineSpans for CartaWarning {
+        fn line_spans(self) -> CartaWarning {
+            CartaWarning {
+                span: self.span.line_spans(),
+                labels: self.labels.line_spans(),
+                ..self
+            }
         }
     }
 
-    pub fn new_integer_too_large(line_no: usize) -> CartaError {
-        CartaError {
-            line_no,
-            code: CartaErrorCode::IntegerTooLarge(),
+    impl LineSpans for Label {
+        fn line_spans(self) -> Label {
+            Label {
+                span: self.span.line_spans(),
+                ..self
+            }
         }
     }
 
-    pub fn new_incomplete_input(line_no: usize) -> CartaError {
-        CartaError {
-            line_no,
-            code: CartaErrorCode::IncompleteInput(),
+    #[test]
+    fn display() {
+        let err = CartaError::new_parse_error(Span::from(3), ":", "b".to_string());
+        assert_eq!(
+            err.to_string(),
+            "Line 3: Parse error!  Expected ':', found 'b'"
+        );
+        let warning = CartaWarning::new_unused_struct(Span::from(2), "Header");
+        assert_eq!(warning.to_string(), "Line 2: Struct is never used: Header");
+    }
+
+    #[test]
+    fn std_error() {
+        fn compile() -> Result<(), Box<dyn Error>> {
+            Err(CartaError::new_missing_root_element())?
         }
+        let err = compile().unwrap_err();
+        assert_eq!(err.to_string(), "Missing struct \"root\"");
+        assert!(err.downcast_ref::<CartaError>().is_some());
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,344 @@
+/*!
+ * Long form explanations of errors and warnings, looked up by their stable identifiers.  These go
+ * into more detail than the one line messages, and show an example of the problem and its fix.
+ */
+
+/// Explanation of the error or warning with the given identifier, eg. `E0001` or `W0001`.  Returns
+/// None for identifiers that aren't used.
+pub fn explain(id: &str) -> Option<&'static str> {
+    let explanation = match id {
+        "E0001" => E0001,
+        "E0002" => E0002,
+        "E0003" => E0003,
+        "E0004" => E0004,
+        "E0005" => E0005,
+        "E0006" => E0006,
+        "E0007" => E0007,
+        "E0008" => E0008,
+        "E0009" => E0009,
+        "E0010" => E0010,
+        "E0011" => E0011,
+        "E0012" => E0012,
+        "E0013" => E0013,
+        "E0014" => E0014,
+        "E0015" => E0015,
+        "E0016" => E0016,
+        "E0017" => E0017,
+        "W0001" => W0001,
+        "W0002" => W0002,
+        "W0003" => W0003,
+        "W0004" => W0004,
+        "W0005" => W0005,
+        _ => return None,
+    };
+    Some(explanation.trim())
+}
+
+const E0001: &str = "
+A type was used that isn't a builtin type or a struct declared in the schema.
+
+    struct root {
+        magic: uint32le,    // error: should be uint32_le
+        header: Header,     // error: Header isn't declared
+    }
+
+Check the spelling of the type, and declare any structs that are used.  Builtin integer and float
+types always include their byte order, eg. `uint32_le` or `f64_be`, apart from single byte types
+such as `uint8`.  Names of types are case sensitive.
+
+The same error is given for `root <name>;` declarations, `extends` and `sizeof` naming a type that
+doesn't exist.
+";
+
+const E0002: &str = "
+Two structs or unions were declared with the same name.
+
+    struct Header {magic: uint32_le}
+    struct Header {size: uint16_le}   // error
+
+Every type needs a unique name, so that elements can refer to it.  Rename or remove one of the
+declarations.
+";
+
+const E0003: &str = "
+Structs contain each other, so would take up an infinite amount of space.
+
+    struct Node {
+        value: uint8,
+        next: Node,         // error: a Node always contains another Node
+    }
+
+Recursion is allowed where it can stop, depending on the data.  That's through arrays whose length
+comes from the data, and elements placed with `@`:
+
+    struct Node {
+        count: uint8,
+        children: [Node; count],
+    }
+";
+
+const E0004: &str = "
+The schema contains a character that can't appear anywhere in a schema, outside of comments.
+
+    struct root {
+        a: uint8,   # error: comments start with '//'
+    }
+
+Check for stray punctuation, or text that should have been commented out.  Names can contain
+letters, digits and underscores.
+";
+
+const E0005: &str = "
+A character appeared where it isn't allowed.  The message shows what was expected instead.
+
+    struct root {a: [uint8; 0x]}    // error: expected a hex digit after 0x
+";
+
+const E0006: &str = "
+A block comment was started with `/*`, but never closed with `*/` before the end of the schema.
+
+    /* Header of the file
+    struct root {magic: uint32_le}  // error: this is all part of the comment
+
+Close the comment.  Block comments can't be nested, so the first `*/` ends the comment.
+";
+
+const E0007: &str = "
+The schema doesn't follow the syntax of the language at this point.  The message shows what was
+expected, and the token that was found instead.
+
+    struct root {
+        a uint8,            // error: expected ':'
+    }
+
+Elements are written as `name: Type`, and are separated by commas.  Structs are written as
+`struct Name { elements }`.
+";
+
+const E0008: &str = "
+The schema doesn't have an entry point.
+
+Applying a schema starts from a struct named `root`, unless other entry points are declared with
+`root <name>;`.
+
+    struct Header {magic: uint32_le}  // error: no root struct
+
+Either rename the first struct to `root`, or declare it as an entry point:
+
+    root Header;
+    struct Header {magic: uint32_le}
+";
+
+const E0009: &str = "
+The length of an array or byte block names something that isn't an element earlier in the same
+struct.
+
+    struct root {
+        data: [uint8; count],   // error: count comes after data
+        count: uint8,
+    }
+
+Lengths are read from the data before the array, so must be elements that come before it.  Move
+the length element before the array.
+";
+
+const E0010: &str = "
+The length of an array or byte block names an element that doesn't hold an integer.
+
+    struct root {
+        count: f32_le,
+        data: [uint8; count],   // error: count is a float
+    }
+
+Use an integer type for the length element, or a computed value: `let len = count_field * 2;`.
+Lengths are worked out with 64 bit integers, so the 128 bit types `int128_*` and `uint128_*` can't
+be used.
+";
+
+const E0011: &str = "
+A number starts with a zero.  Numbers are written in decimal, and a leading zero is usually a
+mistake for another base.
+
+    struct root {data: [uint8; 010]}  // error
+
+Write the number without the leading zero.  Use the `0x` prefix for hexadecimal numbers, eg.
+`0x1f`.
+";
+
+const E0012: &str = "
+A number in the schema is too large.  Decimal numbers can have at most 9 digits, and hex numbers
+at most 8, so that every number fits in 32 bits.
+
+    struct root {data: [uint8; 1000000000000]}  // error
+";
+
+const E0013: &str = "
+The schema ended part way through a declaration, eg. a struct without its closing brace.
+
+    struct root {
+        a: uint8,
+    // error: missing '}'
+
+Finish the declaration, and check that every `{` and `[` has a matching `}` and `]`.
+";
+
+const E0014: &str = "
+A struct extends something other than another struct.
+
+    struct Packet extends uint32_le {}  // error
+    union Value {i: int32_le, f: f32_le}
+    struct Typed extends Value {}       // error
+
+Only structs can be extended, as their elements are copied into the start of the new struct.  Use
+an element of the type instead.
+";
+
+const E0015: &str = "
+An expression refers to something that isn't an earlier integer element in the same struct.
+
+    struct root {
+        name: [ascii; 4],
+        data: bytes[name * 2],  // error: name isn't an integer
+    }
+
+Expressions are evaluated when the element is read, so can only use integer values that have
+already been read from earlier in the struct, or computed with `let`.  The 128 bit integer types
+are too large to use.
+";
+
+const E0016: &str = "
+`offsetof` names something that isn't an element earlier in the same struct.
+
+    struct root {
+        let start = offsetof(data);     // error: data comes later
+        data: uint32_le,
+    }
+
+The offset of an element is only known once it has been read.
+";
+
+const E0017: &str = "
+`sizeof` is used with a type whose size depends on the data.
+
+    struct Text {len: uint8, chars: [ascii; len]}
+    struct root {
+        skip: bytes[sizeof(Text)],  // error: the size of Text varies
+    }
+
+`sizeof` works for builtin types, and structs made up only of elements with fixed sizes.
+";
+
+const W0001: &str = "
+A struct is declared, but can't be reached from any of the schema's entry points, so is never used
+to decode any data.
+
+    struct root {magic: uint32_le}
+    struct Header {size: uint16_le}   // warning: Header isn't used
+
+Either use the struct from an element, declare it as an entry point with `root <name>;`, or remove
+it.
+";
+
+const W0002: &str = "
+Two elements in the same struct have the same name.
+
+    struct root {
+        size: uint16_le,
+        size: uint32_le,    // warning
+    }
+
+Both elements are decoded, but lengths and expressions can only refer to one of them.  This
+includes elements inherited with `extends`.
+";
+
+const W0003: &str = "
+An element has the same name as a builtin type.
+
+    struct root {
+        uint8: uint8,       // warning
+    }
+
+This is allowed, but makes the schema harder to read.  Give the element a name that describes what
+it holds.
+";
+
+const W0004: &str = "
+A struct or union has no elements, so takes up no space and shows nothing.
+
+    struct Reserved {}      // warning
+
+This is usually a placeholder that was never filled in.  Use `bytes[n]` to skip over data that
+isn't understood yet.
+";
+
+const W0005: &str = "
+The length of an array or byte block comes from a signed integer element, either directly or as
+part of an expression.
+
+    struct root {
+        count: int16_le,
+        data: [uint8; count],       // warning
+        more: [uint8; count * 2],   // warning
+    }
+
+If the value is negative, it can't be used as a length.  Lengths are almost always unsigned, so
+use the unsigned type, eg. `uint16_le`.
+";
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::{CartaErrorCode, CartaWarningCode};
+
+    #[test]
+    fn all_codes_explained() {
+        let errors = [
+            CartaErrorCode::UnknownType(String::new()),
+            CartaErrorCode::DuplicateType(String::new()),
+            CartaErrorCode::RecursiveTypes(Vec::new()),
+            CartaErrorCode::UnknownSymbol(' '),
+            CartaErrorCode::UnexpectedSymbol("", ' '),
+            CartaErrorCode::UnclosedBlockComment(),
+            CartaErrorCode::ParseError("", String::new()),
+            CartaErrorCode::MissingRootElement(),
+            CartaErrorCode::BadArrayLen(String::new()),
+            CartaErrorCode::BadArrayLenType(String::new()),
+            CartaErrorCode::LeadingZero(),
+            CartaErrorCode::IntegerTooLarge(),
+            CartaErrorCode::IncompleteInput(),
+            CartaErrorCode::BadBaseType(String::new()),
+            CartaErrorCode::BadExpression(String::new()),
+            CartaErrorCode::BadOffsetOf(String::new()),
+            CartaErrorCode::BadSizeOf(String::new()),
+        ];
+        let warnings = [
+            CartaWarningCode::UnusedStruct(String::new()),
+            CartaWarningCode::DuplicateElement(String::new()),
+            CartaWarningCode::ShadowsBuiltin(String::new()),
+            CartaWarningCode::EmptyStruct(String::new()),
+            CartaWarningCode::SignedArrayLen(String::new()),
+        ];
+
+        let ids: Vec<&str> = errors
+            .iter()
+            .map(|code| code.id())
+            .chain(warnings.iter().map(|code| code.id()))
+            .collect();
+        for (i, id) in ids.iter().enumerate() {
+            assert!(explain(id).is_some(), "No explanation for {}", id);
+            assert!(!ids[..i].contains(id), "Duplicate id {}", id);
+        }
+    }
+
+    #[test]
+    fn lookup() {
+        assert!(explain("E0001").unwrap().starts_with("A type was used"));
+        assert_eq!(
+            CartaErrorCode::MissingRootElement().explanation(),
+            explain("E0008").unwrap()
+        );
+        assert_eq!(CartaWarningCode::EmptyStruct(String::new()).id(), "W0004");
+        assert_eq!(explain("E9999"), None);
+        assert_eq!(explain("e0001"), None);
+    }
+}
@@ -0,0 +1,482 @@
+/*!
+ * Integer expressions, as used by computed fields, array lengths and placements, eg.
+ * `let num_pixels = width * height;` or `@ end - 22`
+ *
+ * The parser collects the tokens making up an expression, and `parse_expr` turns them into an
+ * `Expr` tree.  Expressions are evaluated when the schema is applied, once the values of the
+ * elements they refer to are known.
+ */
+
+use crate::error::CartaError;
+use crate::span::Span;
+use crate::tokeniser::{Token, TokenType};
+
+/// An expression, used for array lengths, placements and computed values.  More kinds of
+/// expression may be added in future versions.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Expr {
+    Integer(i64),
+    // Value of an earlier element in the same struct
+    Identifier(String),
+    Negate(Box<Expr>),
+    BinaryOp(Box<Expr>, BinOp, Box<Expr>),
+    // Length of the data, from `end`
+    End,
+    // Start of the last occurrence of a byte signature, eg. `rfind(0x50, 0x4b, 0x05, 0x06)`
+    FindLast(Vec<u8>),
+    // Size of a type with a fixed size, from `sizeof(Type)`
+    SizeOf(String),
+    // Offset of an earlier element from the start of the struct, from `offsetof(field)`
+    OffsetOf(String),
+    // Absolute offset that the element would start at, from `_pos`
+    Position,
+    // Absolute offset of the start of the enclosing struct, from `_struct_start`
+    StructStart,
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// Everything outside the expression itself that its value can depend on.  Each function returns
+/// None if the value isn't known.
+pub trait Env {
+    /// Value of an earlier element
+    fn value(&self, name: &str) -> Option<i64>;
+    fn end(&self) -> Option<i64>;
+    fn find_last(&self, signature: &[u8]) -> Option<i64>;
+    fn size_of(&self, typename: &str) -> Option<i64>;
+    fn offset_of(&self, name: &str) -> Option<i64>;
+    fn position(&self) -> Option<i64>;
+    fn struct_start(&self) -> Option<i64>;
+}
+
+impl Expr {
+    /// Evaluate the expression.  Returns None if something it refers to has no value, the result
+    /// overflows, or there is a division by zero.
+    pub fn eval(&self, env: &dyn Env) -> Option<i64> {
+        match self {
+            Expr::Integer(i) => Some(*i),
+            Expr::Identifier(name) => env.value(name),
+            Expr::Negate(expr) => expr.eval(env)?.checked_neg(),
+            Expr::BinaryOp(lhs, op, rhs) => {
+                let lhs = lhs.eval(env)?;
+                let rhs = rhs.eval(env)?;
+                match op {
+                    BinOp::Add => lhs.checked_add(rhs),
+                    BinOp::Sub => lhs.checked_sub(rhs),
+                    BinOp::Mul => lhs.checked_mul(rhs),
+                    BinOp::Div => lhs.checked_div(rhs),
+                }
+            }
+            Expr::End => env.end(),
+            Expr::FindLast(signature) => env.find_last(signature),
+            Expr::SizeOf(typename) => env.size_of(typename),
+            Expr::OffsetOf(name) => env.offset_of(name),
+            Expr::Position => env.position(),
+            Expr::StructStart => env.struct_start(),
+        }
+    }
+
+    /// All the sub-expressions of this expression, including itself
+    fn walk(&self) -> Vec<&Expr> {
+        let mut exprs = vec![self];
+        match self {
+            Expr::Negate(expr) => exprs.extend(expr.walk()),
+            Expr::BinaryOp(lhs, _, rhs) => {
+                exprs.extend(lhs.walk());
+                exprs.extend(rhs.walk());
+            }
+            _ => {}
+        }
+        exprs
+    }
+
+    /// Names of all the elements whose values the expression refers to
+    pub fn identifiers(&self) -> Vec<&str> {
+        self.walk()
+            .into_iter()
+            .filter_map(|expr| match expr {
+                Expr::Identifier(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Names of all the types the expression takes the size of
+    pub fn sizeof_types(&self) -> Vec<&str> {
+        self.walk()
+            .into_iter()
+            .filter_map(|expr| match expr {
+                Expr::SizeOf(typename) => Some(typename.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Byte signatures the expression searches for with `rfind`
+    pub fn signatures(&self) -> Vec<&[u8]> {
+        self.walk()
+            .into_iter()
+            .filter_map(|expr| match expr {
+                Expr::FindLast(signature) => Some(signature.as_slice()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Names of all the elements the expression takes the offset of
+    pub fn offsetof_fields(&self) -> Vec<&str> {
+        self.walk()
+            .into_iter()
+            .filter_map(|expr| match expr {
+                Expr::OffsetOf(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Make any `end`, `_pos` or `_struct_start` in the expression refer to the element with that
+    /// name instead, if `is_element` says there is one
+    pub(crate) fn prefer_elements(&mut self, is_element: &dyn Fn(&str) -> bool) {
+        let name = match self {
+            Expr::Negate(expr) => return expr.prefer_elements(is_element),
+            Expr::BinaryOp(lhs, _, rhs) => {
+                lhs.prefer_elements(is_element);
+                return rhs.prefer_elements(is_element);
+            }
+            Expr::End => "end",
+            Expr::Position => "_pos",
+            Expr::StructStart => "_struct_start",
+            _ => return,
+        };
+        if is_element(name) {
+            *self = Expr::Identifier(name.to_string());
+        }
+    }
+
+    /// Whether the value depends on the contents of the data being decoded, rather than just on
+    /// the schema and where in the data it's evaluated
+    pub fn reads_data(&self) -> bool {
+        self.walk()
+            .into_iter()
+            .any(|expr| matches!(expr, Expr::Identifier(_) | Expr::FindLast(_)))
+    }
+}
+
+/// Parse a complete expression from its tokens.  `end` is used for errors if the expression ends
+/// early.
+pub fn parse_expr(tokens: Vec<Token>, end: Span) -> Result<Expr, CartaError> {
+    let mut parser = ExprParser {
+        tokens: tokens.into_iter().peekable(),
+        end,
+    };
+    let expr = parser.sum()?;
+
+    // All tokens must have been used
+    match parser.tokens.next() {
+        None => Ok(expr),
+        Some(t) => Err(CartaError::new_parse_error(
+            t.span,
+            "<operator>",
+            t.get_string(),
+        )),
+    }
+}
+
+/// Recursive descent parser, with one function per level of precedence
+struct ExprParser {
+    tokens: std::iter::Peekable<std::vec::IntoIter<Token>>,
+    end: Span,
+}
+
+impl ExprParser {
+    /// Terms added or subtracted, eg. `a + b * c - d`
+    fn sum(&mut self) -> Result<Expr, CartaError> {
+        let mut expr = self.product()?;
+        while let Some(op) = self.next_op(&[TokenType::Plus, TokenType::Minus]) {
+            let rhs = self.product()?;
+            expr = Expr::BinaryOp(Box::new(expr), op, Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    /// Factors multiplied or divided, eg. `a * b / c`
+    fn product(&mut self) -> Result<Expr, CartaError> {
+        let mut expr = self.factor()?;
+        while let Some(op) = self.next_op(&[TokenType::Star, TokenType::Slash]) {
+            let rhs = self.factor()?;
+            expr = Expr::BinaryOp(Box::new(expr), op, Box::new(rhs));
+        }
+        Ok(expr)
+    }
+
+    /// A single value, function call, negated value or parenthesised expression
+    fn factor(&mut self) -> Result<Expr, CartaError> {
+        let t = self.next_token()?;
+
+        match t.kind {
+            TokenType::Integer => Ok(Expr::Integer(t.get_int() as i64)),
+            TokenType::Word if self.next_is(TokenType::OpenParen) => self.call(t),
+            TokenType::Word => Ok(match t.get_string_ref() {
+                "end" => Expr::End,
+                "_pos" => Expr::Position,
+                "_struct_start" => Expr::StructStart,
+                _ => Expr::Identifier(t.get_string()),
+            }),
+            TokenType::Minus => Ok(Expr::Negate(Box::new(self.factor()?))),
+            TokenType::OpenParen => {
+                let expr = self.sum()?;
+                self.expect(TokenType::CloseParen, ")")?;
+                Ok(expr)
+            }
+            _ => Err(CartaError::new_parse_error(
+                t.span,
+                "<expression>",
+                t.get_string(),
+            )),
+        }
+    }
+
+    /// A function call, eg. `sizeof(Header)`.  The next token is the open parenthesis.
+    fn call(&mut self, name: Token) -> Result<Expr, CartaError> {
+        self.expect(TokenType::OpenParen, "(")?;
+        let expr = match name.get_string_ref() {
+            "sizeof" => Expr::SizeOf(self.expect(TokenType::Word, "<typename>")?.get_string()),
+            "offsetof" => Expr::OffsetOf(self.expect(TokenType::Word, "<name>")?.get_string()),
+            "rfind" => {
+                let mut signature = Vec::new();
+                loop {
+                    // Each entry of the signature must fit in a byte
+                    let t = self.expect(TokenType::Integer, "<byte>")?;
+                    let span = t.span;
+                    let byte = t.get_int();
+                    if byte > u8::MAX as u32 {
+                        return Err(CartaError::new_parse_error(
+                            span,
+                            "<byte>",
+                            byte.to_string(),
+                        ));
+                    }
+                    signature.push(byte as u8);
+                    if !self.next_is(TokenType::Comma) {
+                        break;
+                    }
+                    self.tokens.next();
+                }
+                Expr::FindLast(signature)
+            }
+            _ => {
+                return Err(CartaError::new_parse_error(
+                    name.span,
+                    "<function>",
+                    name.get_string(),
+                ))
+            }
+        };
+        self.expect(TokenType::CloseParen, ")")?;
+        Ok(expr)
+    }
+
+    fn next_token(&mut self) -> Result<Token, CartaError> {
+        match self.tokens.next() {
+            Some(t) => {
+                self.end = Span::at(t.span.end);
+                Ok(t)
+            }
+            None => Err(CartaError::new_incomplete_input(self.end)),
+        }
+    }
+
+    fn next_is(&mut self, kind: TokenType) -> bool {
+        self.tokens.peek().map(|t| &t.kind) == Some(&kind)
+    }
+
+    /// Consume the next token, which must be of type `kind`
+    fn expect(&mut self, kind: TokenType, expected: &'static str) -> Result<Token, CartaError> {
+        let t = self.next_token()?;
+        if t.kind != kind {
+            return Err(CartaError::new_parse_error(
+                t.span,
+                expected,
+                t.get_string(),
+            ));
+        }
+        Ok(t)
+    }
+
+    /// Consume the next token if it's one of the operators in `kinds`
+    fn next_op(&mut self, kinds: &[TokenType]) -> Option<BinOp> {
+        let kind = self.tokens.peek()?.kind.clone();
+        if !kinds.contains(&kind) {
+            return None;
+        }
+        self.tokens.next();
+        match kind {
+            TokenType::Plus => Some(BinOp::Add),
+            TokenType::Minus => Some(BinOp::Sub),
+            TokenType::Star => Some(BinOp::Mul),
+            TokenType::Slash => Some(BinOp::Div),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::span::LineSpans;
+    use crate::tokeniser::Tokeniser;
+
+    fn parse(data: &str) -> Result<Expr, CartaError> {
+        let tokens: Vec<Token> = Tokeniser::new(data)?.into_iter().collect();
+        parse_expr(tokens, Span::from(1)).map_err(CartaError::line_spans)
+    }
+
+    struct TestEnv;
+
+    impl Env for TestEnv {
+        fn value(&self, name: &str) -> Option<i64> {
+            match name {
+                "width" => Some(640),
+                "height" => Some(480),
+                _ => None,
+            }
+        }
+
+        fn end(&self) -> Option<i64> {
+            Some(1000)
+        }
+
+        fn find_last(&self, signature: &[u8]) -> Option<i64> {
+            Some(signature.len() as i64 * 100)
+        }
+
+        fn size_of(&self, typename: &str) -> Option<i64> {
+            (typename == "Header").then_some(16)
+        }
+
+        fn offset_of(&self, name: &str) -> Option<i64> {
+            (name == "height").then_some(2)
+        }
+
+        fn position(&self) -> Option<i64> {
+            Some(20)
+        }
+
+        fn struct_start(&self) -> Option<i64> {
+            Some(12)
+        }
+    }
+
+    #[test]
+    fn precedence() -> Result<(), CartaError> {
+        assert_eq!(parse("1 + 2 * 3")?.eval(&TestEnv), Some(7));
+        assert_eq!(parse("(1 + 2) * 3")?.eval(&TestEnv), Some(9));
+        assert_eq!(parse("10 - 4 - 3")?.eval(&TestEnv), Some(3));
+        assert_eq!(parse("7 / 2 * 2")?.eval(&TestEnv), Some(6));
+        assert_eq!(parse("-width + -(1 - 2)")?.eval(&TestEnv), Some(-639));
+        assert_eq!(parse("width * height")?.eval(&TestEnv), Some(307200));
+        Ok(())
+    }
+
+    #[test]
+    fn builtins() -> Result<(), CartaError> {
+        assert_eq!(parse("end - 22")?.eval(&TestEnv), Some(978));
+        assert_eq!(parse("rfind(0x50, 0x4b) + 1")?.eval(&TestEnv), Some(201));
+        assert_eq!(parse("sizeof(Header) * 2")?.eval(&TestEnv), Some(32));
+        assert_eq!(
+            parse("_struct_start + offsetof(height)")?.eval(&TestEnv),
+            Some(14)
+        );
+        assert_eq!(parse("_pos - _struct_start")?.eval(&TestEnv), Some(8));
+        Ok(())
+    }
+
+    #[test]
+    fn eval_failures() -> Result<(), CartaError> {
+        assert_eq!(parse("width / (height - 480)")?.eval(&TestEnv), None);
+        assert_eq!(parse("unknown + 1")?.eval(&TestEnv), None);
+        assert_eq!(parse("sizeof(Other)")?.eval(&TestEnv), None);
+        Ok(())
+    }
+
+    #[test]
+    fn references() -> Result<(), CartaError> {
+        let expr = parse("(a + sizeof(T)) * -b / a - offsetof(c)")?;
+        assert_eq!(expr.identifiers(), vec!["a", "b", "a"]);
+        assert_eq!(expr.sizeof_types(), vec!["T"]);
+        assert_eq!(expr.offsetof_fields(), vec!["c"]);
+        assert!(expr.reads_data());
+        assert!(!parse("end - sizeof(T) - _pos")?.reads_data());
+        assert!(parse("rfind(0x01)")?.reads_data());
+        Ok(())
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(
+            parse("1 +"),
+            Err(CartaError::new_incomplete_input(Span::from(1)))
+        );
+        assert_eq!(
+            parse("(1 + 2"),
+            Err(CartaError::new_incomplete_input(Span::from(1)))
+        );
+        assert_eq!(
+            parse("1 2"),
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<operator>",
+                "2".to_string()
+            ))
+        );
+        assert_eq!(
+            parse("* 2"),
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<expression>",
+                "*".to_string()
+            ))
+        );
+        assert_eq!(
+            parse("sizeof(1)"),
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<typename>",
+                "1".to_string()
+            ))
+        );
+        assert_eq!(
+            parse("rfind()"),
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<byte>",
+                ")".to_string()
+            ))
+        );
+        assert_eq!(
+            parse("rfind(0x100)"),
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<byte>",
+                "256".to_string()
+            ))
+        );
+        assert_eq!(
+            parse("max(1, 2)"),
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<function>",
+                "max".to_string()
+            ))
+        );
+    }
+}
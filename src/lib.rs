@@ -9,44 +9,155 @@
  *      V
  *   Parsing           Extract file structure definitions.  Returns a schema object that contains
  *      |              a list of the structs, in the order they appeared in the input file.
+ *      |              That order is kept all the way through to the final schema.
  *      V
  * Type checking       Uses the StructDefns and builtin types to do type checking. Returns
  *      |              a tschema object with type checked types.
  *      V
  * Correctness Checks  Final checks on the schema.
- *      |               - Root element is correctly present
+ *      |               - Root element(s) are correctly present
  *      |               - Array lengths can be calculated
  *      V
+ * Warnings            Things that are allowed, but probably mistakes.  Returned along with the
+ *      |              final schema by compile_schema_file_with_warnings.
+ *      V
  * Final schema
+ *
+ * With the `serde` feature, the final schema can be saved and loaded again with `TSchema::to_json`
+ * and `TSchema::to_binary`, to avoid compiling the same source every time.
  */
 
+// Errors carry their source locations, so are fairly large.  They're only returned while compiling
+// a schema, where that doesn't matter.
+#![allow(clippy::result_large_err)]
+
 mod apply;
 mod builtin_types;
+#[cfg(feature = "serde")]
+mod cache;
 mod correctness;
+mod datetime;
+mod diagnostic;
 mod error;
+mod explain;
+mod expression;
+mod ordered_map;
 mod parser;
+mod span;
+mod suggest;
 mod tokeniser;
 mod type_check;
+mod visit;
+mod warnings;
 
-pub use apply::Nugget;
-use error::CartaError;
-pub use type_check::TSchema;
+pub use apply::{ApplyOptions, Nugget};
+#[cfg(feature = "serde")]
+pub use cache::{source_hash, FORMAT_VERSION};
+pub use diagnostic::Renderer;
+#[cfg(feature = "serde")]
+pub use error::CacheError;
+pub use error::{CartaError, CartaErrorCode, CartaWarning, CartaWarningCode, Label};
+pub use explain::explain;
+pub use expression::{BinOp, Env, Expr};
+pub use ordered_map::OrderedMap;
+pub use parser::{ArrayDefn, ArrayLen, Element, ElementTypeRef, StructDefn};
+pub use span::{Position, Span};
+pub use type_check::{StructLayout, TSchema};
+pub use visit::{walk_array, walk_array_len, walk_element, walk_struct, walk_type, Visitor};
+
+/// Compile a schema, returning all errors found in the order they appear in the source.  Each
+/// stage carries on after errors to find as many as it can, but later stages only run once the
+/// earlier ones have succeeded, so that errors aren't reported as a knock-on effect of others.
+pub fn compile_schema_file(data: &str) -> Result<TSchema, Vec<CartaError>> {
+    compile_schema_file_with_warnings(data).map(|(tschema, _)| tschema)
+}
 
-pub fn compile_schema_file(data: &str) -> Result<TSchema, CartaError> {
-    let tokeniser = tokeniser::Tokeniser::new(&data)?;
-    let schema = parser::compile_schema(tokeniser)?;
-    let tschema = type_check::type_check_schema(schema)?;
-    correctness::check_schema(&tschema)?;
-    Ok(tschema)
+/// As `compile_schema_file`, also returning warnings about parts of the schema that are valid but
+/// suspicious, such as unused structs.  Warnings are only checked once there are no errors.
+pub fn compile_schema_file_with_warnings(
+    data: &str,
+) -> Result<(TSchema, Vec<CartaWarning>), Vec<CartaError>> {
+    let (tokeniser, errors) = tokeniser::Tokeniser::with_errors(data);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let (schema, errors) = parser::compile_schema_with_errors(tokeniser);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let tschema = type_check::type_check_schema_with_errors(schema)?;
+    let errors = correctness::check_schema_with_errors(&tschema);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let warnings = warnings::check_warnings(&tschema);
+    Ok((tschema, warnings))
 }
 
+/// Apply the schema's default entry point to the start of `file_data`.  This is the first struct
+/// declared with `root <name>;`, or the struct named "root" if there are no declarations.
 pub fn apply_schema(schema: &TSchema, file_data: &[u8]) -> Nugget {
-    apply::apply_schema(schema, file_data)
+    apply::apply_schema(schema, file_data, &ApplyOptions::default())
+}
+
+/// As `apply_schema`, with options to control the shape of the output
+pub fn apply_schema_with_options(
+    schema: &TSchema,
+    file_data: &[u8],
+    options: &ApplyOptions,
+) -> Nugget {
+    apply::apply_schema(schema, file_data, options)
+}
+
+/// Interpret the bytes of `file_data` at `offset` as the named struct.  Returns None if the schema
+/// has no struct with that name, or `offset` is past the end of the data.
+pub fn apply_struct(
+    schema: &TSchema,
+    name: &str,
+    file_data: &[u8],
+    offset: usize,
+) -> Option<Nugget> {
+    apply::apply_struct(schema, name, file_data, offset, &ApplyOptions::default())
+}
+
+/// As `apply_struct`, with options to control the shape of the output
+pub fn apply_struct_with_options(
+    schema: &TSchema,
+    name: &str,
+    file_data: &[u8],
+    offset: usize,
+    options: &ApplyOptions,
+) -> Option<Nugget> {
+    apply::apply_struct(schema, name, file_data, offset, options)
+}
+
+/// Interpret a single entry of the array element `element_name` in the named struct, with the
+/// struct at `offset` in `file_data`.  Entries before `index` aren't decoded, so this is suited to
+/// large arrays of fixed-size records.  Returns None if there is no such struct or array, its
+/// entries vary in size, or `index` is past the end of the array.
+pub fn apply_array_entry(
+    schema: &TSchema,
+    name: &str,
+    element_name: &str,
+    file_data: &[u8],
+    offset: usize,
+    index: usize,
+) -> Option<Nugget> {
+    apply::apply_array_entry(
+        schema,
+        name,
+        element_name,
+        file_data,
+        offset,
+        index,
+        &ApplyOptions::default(),
+    )
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::span::LineSpans;
 
     #[test]
     fn basic_compile_and_apply() {
@@ -71,6 +182,12 @@ mod test {
                 u_64: uint64_be,
                 f_32: f32_be,
                 f_64: f64_be,
+                i_24: int24_be,
+                u_24: uint24_be,
+                i_128: int128_be,
+                u_128: uint128_be,
+                f_16: f16_be,
+                bf_16: bfloat16_be,
                 arr: [int16_be; i_16]
             }
             struct le {
@@ -82,10 +199,111 @@ mod test {
                 uint64: uint64_le,
                 f32: f32_le,
                 f64: f64_le,
+                int24: int24_le,
+                uint24: uint24_le,
+                int128: int128_le,
+                uint128: uint128_le,
+                f16: f16_le,
+                bf16: bfloat16_le,
+                bool8: bool8,
+                fixed: fixed16_16_le,
+                rational: rational32_le,
             }
         ",
         )
         .unwrap();
-        apply_schema(&schema, &[0; 83]);
+        apply_schema(&schema, &[0; 186]);
+    }
+
+    #[test]
+    fn uint24_array_len() {
+        let schema =
+            compile_schema_file("struct root {len: uint24_be, arr: [uint8; len]}").unwrap();
+        let res = apply_schema(&schema, b"\x00\x00\x02\x05\x06\x07");
+        assert_eq!(res.len, 5);
+        assert_eq!(res.children[1].children.len(), 2);
+        assert_eq!(res.children[1].children[1].value, Some("6".to_string()));
+    }
+
+    #[test]
+    fn inline_struct_errors() {
+        let res = compile_schema_file(
+            "struct root {
+                header: struct {
+                    magic: uint32_le,
+                    version: Version,
+                }
+            }",
+        )
+        .line_spans();
+        assert_eq!(
+            res,
+            Err(vec![CartaError::new_unknown_type(
+                Span::from(4),
+                "Version".to_string()
+            )])
+        );
+    }
+
+    #[test]
+    fn multiple_errors() {
+        let res = compile_schema_file(
+            "struct root {
+                a: uint8
+                b: uint8,
+                c: [uint8; 2
+            }",
+        )
+        .line_spans();
+        assert_eq!(
+            res,
+            Err(vec![
+                CartaError::new_parse_error(Span::from(3), ",", "b".to_string()),
+                CartaError::new_parse_error(Span::from(5), "]", "}".to_string()),
+            ])
+        );
+
+        // Later stages only run once earlier ones succeed
+        let res = compile_schema_file("struct root {a: Unknown, b: [uint8; c]} struct root {}")
+            .line_spans();
+        assert_eq!(
+            res,
+            Err(vec![
+                CartaError::new_unknown_type(Span::from(1), "Unknown".to_string()),
+                CartaError::new_duplicate_type(Span::from(1), "root".to_string())
+                    .with_label(Span::from(1), "first defined here"),
+            ])
+        );
+    }
+
+    #[test]
+    fn warnings() {
+        let (_, warnings) = compile_schema_file_with_warnings(
+            "struct root {a: uint8}
+struct Unused {}",
+        )
+        .line_spans()
+        .unwrap();
+        assert_eq!(
+            warnings,
+            vec![
+                CartaWarning::new_unused_struct(Span::from(2), "Unused"),
+                CartaWarning::new_empty_struct(Span::from(2), "Unused"),
+            ]
+        );
+
+        // Errors take priority over warnings
+        let res = compile_schema_file_with_warnings(
+            "struct root {a: X}
+struct Unused {}",
+        )
+        .line_spans();
+        assert_eq!(
+            res,
+            Err(vec![CartaError::new_unknown_type(
+                Span::from(1),
+                "X".to_string()
+            )])
+        );
     }
 }
@@ -0,0 +1,163 @@
+/*!
+ * A map from names to values, that remembers the order the names were added in.
+ *
+ * The compiled schema keeps its structs in one of these, so that everything that iterates over
+ * them - checks, error messages and tools built on the schema - does so in the same order every
+ * time, rather than in the arbitrary order of a HashMap.
+ */
+
+use std::collections::HashMap;
+use std::ops::Index;
+
+/// Values by name, iterated in the order they were inserted
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderedMap<V> {
+    entries: Vec<(String, V)>,
+
+    // Position of each name in `entries`
+    index: HashMap<String, usize>,
+}
+
+impl<V> OrderedMap<V> {
+    pub fn new() -> OrderedMap<V> {
+        OrderedMap {
+            entries: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    /// Add a value.  If the name is already in the map, its value is replaced, keeping its
+    /// original position, and the old value is returned.
+    pub fn insert(&mut self, name: String, value: V) -> Option<V> {
+        match self.index.get(&name) {
+            Some(&idx) => Some(std::mem::replace(&mut self.entries[idx].1, value)),
+            None => {
+                self.index.insert(name.clone(), self.entries.len());
+                self.entries.push((name, value));
+                None
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&V> {
+        self.index.get(name).map(|&idx| &self.entries[idx].1)
+    }
+
+    pub fn get_mut(&mut self, name: &str) -> Option<&mut V> {
+        let idx = *self.index.get(name)?;
+        Some(&mut self.entries[idx].1)
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.index.contains_key(name)
+    }
+
+    /// Position of the name in the map, counting from 0 in insertion order
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.index.get(name).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Names and values, in insertion order
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &V)> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.iter().map(|(_, value)| value)
+    }
+}
+
+impl<V> Default for OrderedMap<V> {
+    fn default() -> OrderedMap<V> {
+        OrderedMap::new()
+    }
+}
+
+/// Look up a value by name.  Panics if the name isn't in the map.
+impl<V> Index<&str> for OrderedMap<V> {
+    type Output = V;
+
+    fn index(&self, name: &str) -> &V {
+        self.get(name)
+            .unwrap_or_else(|| panic!("No entry for {:?}", name))
+    }
+}
+
+impl<V> FromIterator<(String, V)> for OrderedMap<V> {
+    fn from_iter<I: IntoIterator<Item = (String, V)>>(iter: I) -> OrderedMap<V> {
+        let mut map = OrderedMap::new();
+        for (name, value) in iter {
+            map.insert(name, value);
+        }
+        map
+    }
+}
+
+/// Stored as a list of (name, value) pairs, to keep the order
+#[cfg(feature = "serde")]
+impl<V: serde::Serialize> serde::Serialize for OrderedMap<V> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.entries.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, V: serde::Deserialize<'de>> serde::Deserialize<'de> for OrderedMap<V> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries: Vec<(String, V)> = serde::Deserialize::deserialize(deserializer)?;
+        Ok(entries.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::span::LineSpans;
+
+    impl<V: LineSpans> LineSpans for OrderedMap<V> {
+        fn line_spans(self) -> OrderedMap<V> {
+            self.entries
+                .into_iter()
+                .map(|(name, value)| (name, value.line_spans()))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn insertion_order() {
+        let mut map = OrderedMap::new();
+        for name in ["c", "a", "d", "b"] {
+            map.insert(name.to_string(), name.to_uppercase());
+        }
+        assert_eq!(map.keys().collect::<Vec<_>>(), vec!["c", "a", "d", "b"]);
+        assert_eq!(map.values().collect::<Vec<_>>(), vec!["C", "A", "D", "B"]);
+        assert_eq!(map.position("d"), Some(2));
+        assert_eq!(map["a"], "A");
+        assert_eq!(map.get("e"), None);
+        assert_eq!(map.len(), 4);
+    }
+
+    #[test]
+    fn replace() {
+        let mut map: OrderedMap<u32> = [("x".to_string(), 1), ("y".to_string(), 2)]
+            .into_iter()
+            .collect();
+        assert_eq!(map.insert("x".to_string(), 3), Some(1));
+        *map.get_mut("y").unwrap() += 10;
+        assert_eq!(map.iter().collect::<Vec<_>>(), vec![("x", &3), ("y", &12)]);
+    }
+}
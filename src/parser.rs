@@ -1,226 +1,820 @@
+use crate::error;
 use crate::error::CartaError;
+use crate::expression;
+use crate::expression::Expr;
+use crate::span::{Position, Span};
 use crate::tokeniser::{Token, TokenType, Tokeniser};
 
 #[derive(PartialEq, Debug)]
 pub struct Schema {
     pub structs: Vec<StructDefn>,
+
+    // Entry points declared with `root <name>;`, in the order they appeared
+    pub roots: Vec<RootDecl>,
 }
 
 impl Schema {
     fn add_struct(&mut self, s: StructDefn) {
         self.structs.push(s);
     }
+
+    fn add_root(&mut self, r: RootDecl) {
+        self.roots.push(r);
+    }
 }
 
 #[derive(PartialEq, Debug)]
-pub struct Element {
+pub struct RootDecl {
     pub name: String,
-    pub kind: ElementTypeRef,
 
-    // Line number of the start of the element definition
-    pub line_no: usize,
+    // Struct name in the root declaration
+    pub span: Span,
 }
 
-#[derive(PartialEq, Debug)]
+/// A member of a struct: a value decoded from the data, or computed from other elements
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Element {
+    pub(crate) name: String,
+    pub(crate) kind: ElementTypeRef,
+
+    // Source of the whole element definition, from its name (or modifier) to the end of its type,
+    // placement or value
+    pub(crate) span: Span,
+
+    // Peek elements are decoded, but don't consume any bytes from the enclosing struct
+    pub(crate) peek: bool,
+
+    // Absolute offset of the element, from `name: Type @ <expr>`, for elements that aren't simply
+    // the next thing in their struct.  Placed elements don't consume any bytes from the struct.
+    pub(crate) placement: Option<Expr>,
+}
+
+impl Element {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> &ElementTypeRef {
+        &self.kind
+    }
+
+    pub fn line_no(&self) -> usize {
+        self.span.line_no()
+    }
+
+    /// Source of the whole element definition
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Whether the element is decoded without consuming any bytes, from `peek name: Type`
+    pub fn is_peek(&self) -> bool {
+        self.peek
+    }
+
+    /// Expression for the absolute offset of the element, from `name: Type @ <expr>`
+    pub fn placement(&self) -> Option<&Expr> {
+        self.placement.as_ref()
+    }
+
+    /// All the expressions used by the element - its placement, array lengths and computed value
+    pub fn exprs(&self) -> Vec<&Expr> {
+        let mut exprs: Vec<&Expr> = self.placement.iter().collect();
+        let mut kind = &self.kind;
+        loop {
+            match kind {
+                ElementTypeRef::ArrayElem(array_defn) => {
+                    exprs.extend(array_defn.length.expr());
+                    kind = &array_defn.kind;
+                }
+                ElementTypeRef::Bytes(length) => {
+                    exprs.extend(length.expr());
+                    break;
+                }
+                ElementTypeRef::Computed(expr) => {
+                    exprs.push(expr);
+                    break;
+                }
+                ElementTypeRef::TypeName(_) => break,
+            }
+        }
+        exprs
+    }
+
+    /// Make builtin values in the element's expressions refer to elements with the same name
+    /// instead, where `is_element` says there is one
+    pub(crate) fn prefer_elements(&mut self, is_element: &dyn Fn(&str) -> bool) {
+        if let Some(placement) = &mut self.placement {
+            placement.prefer_elements(is_element);
+        }
+        let mut kind = &mut self.kind;
+        loop {
+            match kind {
+                ElementTypeRef::ArrayElem(array_defn) => {
+                    array_defn.length.prefer_elements(is_element);
+                    kind = &mut array_defn.kind;
+                }
+                ElementTypeRef::Bytes(length) => {
+                    length.prefer_elements(is_element);
+                    break;
+                }
+                ElementTypeRef::Computed(expr) => {
+                    expr.prefer_elements(is_element);
+                    break;
+                }
+                ElementTypeRef::TypeName(_) => break,
+            }
+        }
+    }
+}
+
+/// The type of an element.  More kinds of type may be added in future versions.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum ElementTypeRef {
+    /// A builtin type, or a struct in the schema
     TypeName(String),
     ArrayElem(ArrayDefn),
+    // Opaque block of bytes, with the given length
+    Bytes(ArrayLen),
+    // Value calculated from other elements, from `let name = expr;`.  Takes up no bytes.
+    Computed(Expr),
 }
 
-#[derive(PartialEq, Debug)]
+impl ElementTypeRef {
+    /// Name of the type this element refers to, if any
+    pub fn type_name(&self) -> Option<&str> {
+        match self {
+            ElementTypeRef::TypeName(typename) => Some(typename),
+            ElementTypeRef::ArrayElem(array_defn) => array_defn.kind.type_name(),
+            ElementTypeRef::Bytes(_) | ElementTypeRef::Computed(_) => None,
+        }
+    }
+}
+
+/// Number of entries in an array, or bytes in a byte block.  More kinds of length may be added in
+/// future versions.
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum ArrayLen {
+    /// Value of an earlier element in the same struct
     Identifier(String),
     Static(u32),
+    // Any other expression, eg. `count * 2`
+    Expr(Expr),
 }
 
-#[derive(PartialEq, Debug)]
+impl ArrayLen {
+    /// The length's expression, if it's more than a plain integer or identifier
+    pub fn expr(&self) -> Option<&Expr> {
+        match self {
+            ArrayLen::Expr(expr) => Some(expr),
+            _ => None,
+        }
+    }
+
+    fn prefer_elements(&mut self, is_element: &dyn Fn(&str) -> bool) {
+        if let ArrayLen::Expr(expr) = self {
+            expr.prefer_elements(is_element);
+            if let Expr::Identifier(id) = expr {
+                *self = ArrayLen::Identifier(std::mem::take(id));
+            }
+        }
+    }
+
+    fn from_expr(expr: Expr) -> ArrayLen {
+        match expr {
+            // Integer tokens always fit in a u32
+            Expr::Integer(i) => ArrayLen::Static(i as u32),
+            Expr::Identifier(id) => ArrayLen::Identifier(id),
+            expr => ArrayLen::Expr(expr),
+        }
+    }
+}
+
+/// An array type, eg. `[uint8; count]`
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ArrayDefn {
-    pub kind: String,
-    pub length: ArrayLen,
+    // Type of each entry in the array.  May itself be an array.
+    pub(crate) kind: Box<ElementTypeRef>,
+    pub(crate) length: ArrayLen,
 }
 
+impl ArrayDefn {
+    /// Type of each entry in the array.  May itself be an array.
+    pub fn kind(&self) -> &ElementTypeRef {
+        &self.kind
+    }
+
+    pub fn length(&self) -> &ArrayLen {
+        &self.length
+    }
+}
+
+/// A struct or union, either declared at the top level of the schema or inline as the type of an
+/// element
 #[derive(PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct StructDefn {
-    pub name: String,
-    pub elements: Vec<Element>,
+    pub(crate) name: String,
+    pub(crate) elements: Vec<Element>,
+
+    // Source of the struct definition, from the `struct` or `union` keyword to the closing brace
+    pub(crate) span: Span,
+
+    // Struct this struct extends, from `struct Name extends Base { ... }`
+    pub(crate) base: Option<String>,
 
-    // Line number of the start of the struct definition
-    pub line_no: usize,
+    // Number of elements at the start of `elements` that were inherited from `base`.  Set when
+    // the type checker flattens the base's elements into this struct.
+    pub(crate) num_inherited: usize,
+
+    // All elements of a union start at the same offset, overlaying each other
+    pub(crate) is_union: bool,
+}
+
+impl StructDefn {
+    /// Name of the struct.  Inline structs are named after the struct and element they're defined
+    /// in, eg. `root::header`.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// All the elements, in order.  Once the schema is compiled, this includes the elements
+    /// inherited from the base type, which come first.
+    pub fn elements(&self) -> &[Element] {
+        &self.elements
+    }
+
+    /// Elements inherited from the base type
+    pub fn inherited_elements(&self) -> &[Element] {
+        &self.elements[..self.num_inherited]
+    }
+
+    /// Elements declared in the struct itself
+    pub fn own_elements(&self) -> &[Element] {
+        &self.elements[self.num_inherited..]
+    }
+
+    pub fn line_no(&self) -> usize {
+        self.span.line_no()
+    }
+
+    /// Source of the struct definition, from the `struct` or `union` keyword to the closing brace
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// Struct this struct extends, if any
+    pub fn base(&self) -> Option<&str> {
+        self.base.as_deref()
+    }
+
+    pub fn is_union(&self) -> bool {
+        self.is_union
+    }
+
+    /// Whether the struct was defined inline as the type of an element
+    pub fn is_inline(&self) -> bool {
+        self.name.contains("::")
+    }
+}
+
+/// A parser state that is waiting on a type expression, eg. the type of a struct element, or the
+/// type of the entries in an array.  Once the type has been parsed, it's passed back with
+/// `type_complete`, which returns the state to continue parsing with.
+trait TypeParent {
+    fn type_complete(self: Box<Self>, kind: ElementTypeRef, span: Span) -> Box<dyn CompilerState>;
+
+    /// Name to give to an inline struct defined by the type being parsed
+    fn scope_name(&self) -> String;
+
+    /// The type couldn't be parsed.  Return the state to recover from the syntax error with.
+    fn recover(self: Box<Self>) -> Box<dyn CompilerState>;
+}
+
+/// Output of the parser - the schema, and all syntax errors found along the way
+struct ParseContext {
+    schema: Schema,
+    errors: Vec<CartaError>,
 }
 
 trait CompilerState {
-    fn new_token(
-        self: Box<Self>,
-        t: Token,
-        schema: &mut Schema,
-    ) -> Result<Box<dyn CompilerState>, CartaError>;
+    fn new_token(self: Box<Self>, t: Token, ctx: &mut ParseContext) -> Box<dyn CompilerState>;
 
     // Default implementation.  Parsing the state hasn't completed, so
-    // return an error.  Overwritten in EmptyState to return Ok instead.
-    fn eof(self: Box<Self>) -> Result<(), CartaError> {
-        Err(CartaError::new_incomplete_input(0))
+    // record an error.  Overwritten in EmptyState to do nothing instead.
+    // `end` is the position just past the last token.
+    fn eof(self: Box<Self>, end: Position, ctx: &mut ParseContext) {
+        ctx.errors
+            .push(CartaError::new_incomplete_input(Span::at(end)));
+    }
+}
+
+/// Keywords that start a new top level declaration
+fn is_declaration_keyword(t: &Token) -> bool {
+    t.kind == TokenType::Word && matches!(t.get_string_ref(), "struct" | "union" | "root")
+}
+
+/// Record a syntax error caused by token `t`, and continue with `recovering`, which skips tokens
+/// until parsing can resume.  `t` is skipped too, unless parsing may be able to resume from it -
+/// the end of a struct, or the start of a new declaration.
+fn syntax_error(
+    expected: &'static str,
+    t: Token,
+    recovering: Box<dyn CompilerState>,
+    ctx: &mut ParseContext,
+) -> Box<dyn CompilerState> {
+    if t.kind == TokenType::CloseBrace || is_declaration_keyword(&t) {
+        ctx.errors.push(CartaError::new_parse_error(
+            t.span,
+            expected,
+            t.clone().get_string(),
+        ));
+        recovering.new_token(t, ctx)
+    } else {
+        ctx.errors.push(CartaError::new_parse_error(
+            t.span,
+            expected,
+            t.get_string(),
+        ));
+        recovering
     }
 }
 
 struct EmptyState;
 
 impl CompilerState for EmptyState {
-    fn new_token(
-        self: Box<Self>,
-        t: Token,
-        _: &mut Schema,
-    ) -> Result<Box<dyn CompilerState>, CartaError> {
-        if let Some(s) = new_state(t)? {
-            return Ok(s);
-        }
-        Ok(self)
+    fn new_token(self: Box<Self>, t: Token, ctx: &mut ParseContext) -> Box<dyn CompilerState> {
+        new_state(t, ctx).unwrap_or(self)
     }
 
-    fn eof(self: Box<Self>) -> Result<(), CartaError> {
-        Ok(())
+    fn eof(self: Box<Self>, _: Position, _: &mut ParseContext) {}
+}
+
+/// Skip the rest of a declaration with a syntax error outside of any struct body.  Parsing resumes
+/// after the next semicolon or complete block, or at the next declaration keyword.
+struct SkipState {
+    // Number of currently open braces
+    depth: usize,
+}
+
+impl CompilerState for SkipState {
+    fn new_token(mut self: Box<Self>, t: Token, ctx: &mut ParseContext) -> Box<dyn CompilerState> {
+        match t.kind {
+            TokenType::OpenBrace => self.depth += 1,
+            TokenType::CloseBrace if self.depth <= 1 => return Box::new(EmptyState {}),
+            TokenType::CloseBrace => self.depth -= 1,
+            TokenType::Semicolon if self.depth == 0 => return Box::new(EmptyState {}),
+            _ if self.depth == 0 && is_declaration_keyword(&t) => {
+                return Box::new(EmptyState {}).new_token(t, ctx);
+            }
+            _ => {}
+        }
+        self
     }
+
+    // The error has already been reported
+    fn eof(self: Box<Self>, _: Position, _: &mut ParseContext) {}
 }
 
 struct StructState {
     state: StructSubState,
-    line_no: usize,
+    // Span of the `struct` or `union` keyword
+    start: Span,
     name: Option<String>,
     complete_children: Vec<Element>,
     new_child_name: Option<String>,
+    // Span of the first token of the element being parsed
+    new_child_start: Span,
+    new_child_peek: bool,
+    base: Option<String>,
+    is_union: bool,
+
+    // For inline structs, the state that the struct type is passed back to once it's complete
+    parent: Option<Box<dyn TypeParent>>,
 }
 
 #[derive(PartialEq)]
 enum StructSubState {
     Begin,
     Name,
+    Extends,
+    Base,
     OpenBrace,
     ChildName,
     ChildTypeOf,
     ChildKind,
+    LetName,
+    LetValue,
+    // Skipping the rest of an element with a syntax error.  Parsing resumes at the next element,
+    // or the end of the struct.
+    Recover {
+        // Number of currently open braces, from inline structs
+        depth: usize,
+        // The previous token could be followed by a type, so `struct` or `union` is an inline
+        // struct, rather than the start of the next declaration
+        type_next: bool,
+    },
 }
 
 impl StructState {
-    fn new(line_no: usize) -> StructState {
+    fn new(start: Span, is_union: bool) -> StructState {
         StructState {
             state: StructSubState::Begin,
-            line_no,
+            start,
             name: None,
             complete_children: Vec::new(),
             new_child_name: None,
+            new_child_start: start,
+            new_child_peek: false,
+            base: None,
+            is_union,
+            parent: None,
+        }
+    }
+
+    /// Anonymous struct or union defined inline as the type of an element, eg.
+    /// `header: struct { ... }`.  The `struct` or `union` keyword has been consumed, and the
+    /// struct is given a generated name.
+    fn new_inline(parent: Box<dyn TypeParent>, start: Span, is_union: bool) -> StructState {
+        StructState {
+            state: StructSubState::Name,
+            start,
+            name: Some(parent.scope_name()),
+            complete_children: Vec::new(),
+            new_child_name: None,
+            new_child_start: start,
+            new_child_peek: false,
+            base: None,
+            is_union,
+            parent: Some(parent),
         }
     }
 
-    fn add_complete_struct(self: Box<Self>, schema: &mut Schema) {
+    /// Add the finished struct to the schema, and return the state to continue parsing with.
+    /// `end` is the span of the closing brace.
+    fn add_complete_struct(self, end: Span, schema: &mut Schema) -> Box<dyn CompilerState> {
+        let name = self.name.unwrap();
+        let span = self.start.to(end);
         let defn = StructDefn {
-            name: self.name.unwrap(),
+            name: name.clone(),
             elements: self.complete_children,
-            line_no: self.line_no,
+            span,
+            base: self.base,
+            num_inherited: 0,
+            is_union: self.is_union,
         };
         schema.add_struct(defn);
+
+        match self.parent {
+            Some(parent) => parent.type_complete(ElementTypeRef::TypeName(name), span),
+            None => Box::new(EmptyState {}),
+        }
     }
 
-    fn append_child(self: &mut Self, kind: ElementTypeRef, line_no: usize) {
+    /// Add the element being parsed, given its type and the span of the source it ends with
+    fn append_child(&mut self, kind: ElementTypeRef, end: Span) {
+        let span = self.new_child_start.to(end);
         let elem = Element {
             name: self.new_child_name.take().unwrap(),
             kind,
-            line_no
+            span,
+            peek: self.new_child_peek,
+            placement: None,
         };
+        self.new_child_peek = false;
         self.complete_children.push(elem);
     }
-}
 
-impl CompilerState for StructState {
-    fn new_token(
+    /// Record a syntax error in the struct, and recover from it
+    fn syntax_error(
+        self: Box<Self>,
+        expected: &'static str,
+        t: Token,
+        ctx: &mut ParseContext,
+    ) -> Box<dyn CompilerState> {
+        let recovering = match self.state {
+            // Errors before the struct body can't be recovered from within the struct
+            StructSubState::Begin
+            | StructSubState::Name
+            | StructSubState::Extends
+            | StructSubState::Base => match self.parent {
+                Some(parent) => parent.recover(),
+                None => Box::new(SkipState { depth: 0 }),
+            },
+            _ => TypeParent::recover(self),
+        };
+        syntax_error(expected, t, recovering, ctx)
+    }
+
+    /// Continue parsing a struct that's in recovery.  Once the end of the element with the error
+    /// is found, carry on parsing as usual.
+    fn recover_token(
         mut self: Box<Self>,
         t: Token,
-        schema: &mut Schema,
-    ) -> Result<Box<dyn CompilerState>, CartaError> {
+        depth: usize,
+        ctx: &mut ParseContext,
+    ) -> Box<dyn CompilerState> {
+        let type_next = matches!(t.kind, TokenType::Colon | TokenType::OpenBracket);
+        let depth = match t.kind {
+            TokenType::OpenBrace => depth + 1,
+            TokenType::CloseBrace if depth == 0 => {
+                return self.add_complete_struct(t.span, &mut ctx.schema);
+            }
+            TokenType::CloseBrace => depth - 1,
+            TokenType::Comma if depth == 0 => {
+                self.state = StructSubState::OpenBrace;
+                return self;
+            }
+            _ if depth == 0 && !self.recover_type_next() && is_declaration_keyword(&t) => {
+                // The struct was never closed, so the keyword starts the next declaration.  The
+                // missing brace has already been reported as the cause of the error.
+                return match self.parent {
+                    Some(parent) => parent.recover().new_token(t, ctx),
+                    None => Box::new(EmptyState {}).new_token(t, ctx),
+                };
+            }
+            _ => depth,
+        };
+        self.state = StructSubState::Recover { depth, type_next };
+        self
+    }
+
+    fn recover_type_next(&self) -> bool {
+        matches!(
+            self.state,
+            StructSubState::Recover {
+                type_next: true,
+                ..
+            }
+        )
+    }
+}
+
+impl CompilerState for StructState {
+    fn new_token(mut self: Box<Self>, t: Token, ctx: &mut ParseContext) -> Box<dyn CompilerState> {
         // New lines are ignored in struct definitions
         if t.kind == TokenType::NewLine {
-            return Ok(self);
+            return self;
         }
 
         match self.state {
             StructSubState::Begin => {
                 if t.kind != TokenType::Word {
-                    return Err(CartaError::new_parse_error(t.line_no, "<name>", t.get_string()));
+                    return self.syntax_error("<name>", t, ctx);
                 }
                 self.name = Some(t.get_string());
                 self.state = StructSubState::Name;
             }
-            StructSubState::Name => {
-                // Next token must be OpenBrace
+            StructSubState::Name => match t.kind {
+                // Next token must be OpenBrace, or extends for top level structs
+                TokenType::OpenBrace => self.state = StructSubState::OpenBrace,
+                TokenType::Word
+                    if t.get_string_ref() == "extends"
+                        && self.parent.is_none()
+                        && !self.is_union =>
+                {
+                    self.state = StructSubState::Extends
+                }
+                _ => return self.syntax_error("{", t, ctx),
+            },
+            StructSubState::Extends => {
+                if t.kind != TokenType::Word {
+                    return self.syntax_error("<typename>", t, ctx);
+                }
+                self.base = Some(t.get_string());
+                self.state = StructSubState::Base;
+            }
+            StructSubState::Base => {
                 if t.kind != TokenType::OpenBrace {
-                    return Err(CartaError::new_parse_error(t.line_no, "{", t.get_string()));
+                    return self.syntax_error("{", t, ctx);
                 }
                 self.state = StructSubState::OpenBrace;
             }
             StructSubState::OpenBrace => match t.kind {
                 TokenType::CloseBrace => {
                     // Struct is complete, maybe with child elements
-                    self.add_complete_struct(schema);
-                    return Ok(Box::new(EmptyState {}));
+                    return self.add_complete_struct(t.span, &mut ctx.schema);
                 }
                 TokenType::Word => {
+                    self.new_child_start = t.span;
                     self.new_child_name = Some(t.get_string());
                     self.state = StructSubState::ChildName;
                 }
-                _ => return Err(CartaError::new_parse_error(t.line_no, "}", t.get_string())),
+                _ => return self.syntax_error("}", t, ctx),
             },
-            StructSubState::ChildName => {
+            StructSubState::ChildName => match t.kind {
                 // Next token must be Colon
-                if t.kind != TokenType::Colon {
-                    return Err(CartaError::new_parse_error(t.line_no, ":", t.get_string()));
+                TokenType::Colon => self.state = StructSubState::ChildTypeOf,
+                // Unless the name was actually the peek modifier, in which case the name follows
+                TokenType::Word
+                    if !self.new_child_peek && self.new_child_name.as_deref() == Some("peek") =>
+                {
+                    self.new_child_peek = true;
+                    self.new_child_name = Some(t.get_string());
                 }
-                self.state = StructSubState::ChildTypeOf;
-            }
-            StructSubState::ChildTypeOf => {
-                // Next token must be a type definition - a typename or array
-                match t.kind {
-                    TokenType::Word => {
-                        let line_no = t.line_no;
-                        let kind = ElementTypeRef::TypeName(t.get_string());
-                        self.append_child(kind, line_no);
-
-                        self.state = StructSubState::ChildKind;
-                    }
-                    TokenType::OpenBracket => {
-                        self.state = StructSubState::ChildKind;
-                        let arr = Box::new(ArrayState::new(self));
-                        return Ok(arr);
-                    }
-                    _ => return Err(CartaError::new_parse_error(t.line_no, "<typename>", t.get_string())),
+                // Or a computed field, `let name = expr;`
+                TokenType::Word
+                    if !self.new_child_peek && self.new_child_name.as_deref() == Some("let") =>
+                {
+                    self.new_child_name = Some(t.get_string());
+                    self.state = StructSubState::LetName;
                 }
+                _ => return self.syntax_error(":", t, ctx),
+            },
+            StructSubState::ChildTypeOf => {
+                // Next token must be a type definition
+                self.state = StructSubState::ChildKind;
+                return parse_type(t, self, ctx);
             }
             StructSubState::ChildKind => {
                 match t.kind {
                     // Next state may be a comma
                     TokenType::Comma => self.state = StructSubState::OpenBrace,
+                    // Or the element's placement, unless it already has one
+                    TokenType::At if self.complete_children.last().unwrap().placement.is_none() => {
+                        return Box::new(ExprState::new(self));
+                    }
                     // Or a close brace if there is no comma after the last element
                     TokenType::CloseBrace => {
-                        self.add_complete_struct(schema);
-                        return Ok(Box::new(EmptyState {}));
+                        return self.add_complete_struct(t.span, &mut ctx.schema);
                     }
-                    _ => return Err(CartaError::new_parse_error(t.line_no, ",", t.get_string())),
+                    _ => return self.syntax_error(",", t, ctx),
+                }
+            }
+            StructSubState::LetName => {
+                if t.kind != TokenType::Equals {
+                    return self.syntax_error("=", t, ctx);
                 }
+                return Box::new(ExprState::new(self));
+            }
+            StructSubState::LetValue => {
+                // Computed fields end with a semicolon, rather than a comma
+                if t.kind != TokenType::Semicolon {
+                    return self.syntax_error(";", t, ctx);
+                }
+                self.state = StructSubState::OpenBrace;
+            }
+            StructSubState::Recover { depth, .. } => return self.recover_token(t, depth, ctx),
+        }
+
+        self
+    }
+}
+
+impl TypeParent for StructState {
+    fn type_complete(
+        mut self: Box<Self>,
+        kind: ElementTypeRef,
+        span: Span,
+    ) -> Box<dyn CompilerState> {
+        self.append_child(kind, span);
+        self
+    }
+
+    fn scope_name(&self) -> String {
+        // Inline structs are named after the struct and element they're defined in.  The
+        // separator can't appear in an identifier, so this never clashes with a declared struct.
+        let elem_name = self.new_child_name.as_ref().unwrap();
+        let name = format!("{}::{}", self.name.as_ref().unwrap(), elem_name);
+
+        // Elements may share a name, which is only a warning, so number any later inline structs
+        // to keep their names unique
+        let count = self
+            .complete_children
+            .iter()
+            .filter(|child| child.name == *elem_name)
+            .count();
+        if count == 0 {
+            name
+        } else {
+            format!("{}#{}", name, count + 1)
+        }
+    }
+
+    fn recover(mut self: Box<Self>) -> Box<dyn CompilerState> {
+        // Drop the element with the error
+        self.new_child_name = None;
+        self.new_child_peek = false;
+        self.state = StructSubState::Recover {
+            depth: 0,
+            type_next: false,
+        };
+        self
+    }
+}
+
+impl ExprParent for StructState {
+    fn expr_complete(mut self: Box<Self>, expr: Expr, span: Span) -> Box<dyn CompilerState> {
+        if self.state == StructSubState::ChildKind {
+            // Placement of the element that was just parsed
+            let elem = self.complete_children.last_mut().unwrap();
+            elem.placement = Some(expr);
+            elem.span = elem.span.to(span);
+        } else {
+            self.append_child(ElementTypeRef::Computed(expr), span);
+            self.state = StructSubState::LetValue;
+        }
+        self
+    }
+
+    fn recover(self: Box<Self>) -> Box<dyn CompilerState> {
+        TypeParent::recover(self)
+    }
+}
+
+/// A parser state that is waiting on an expression.  Once the expression has been parsed, it's
+/// passed back with `expr_complete`, and the token that ended it is passed on to the returned
+/// state.
+trait ExprParent {
+    fn expr_complete(self: Box<Self>, expr: Expr, span: Span) -> Box<dyn CompilerState>;
+
+    /// The expression couldn't be parsed.  Return the state to recover from the syntax error with.
+    fn recover(self: Box<Self>) -> Box<dyn CompilerState>;
+}
+
+/// Collect the tokens of an expression, up to the first token at the top level that can't be
+/// part of it.  The collected tokens are then parsed as a whole.
+struct ExprState {
+    parent: Box<dyn ExprParent>,
+    tokens: Vec<Token>,
+    // Number of currently open parentheses
+    depth: usize,
+}
+
+impl ExprState {
+    fn new(parent: Box<dyn ExprParent>) -> ExprState {
+        ExprState {
+            parent,
+            tokens: Vec::new(),
+            depth: 0,
+        }
+    }
+}
+
+impl CompilerState for ExprState {
+    fn new_token(mut self: Box<Self>, t: Token, ctx: &mut ParseContext) -> Box<dyn CompilerState> {
+        // New lines are ignored
+        if t.kind == TokenType::NewLine {
+            return self;
+        }
+
+        match t.kind {
+            TokenType::Word
+            | TokenType::Integer
+            | TokenType::Plus
+            | TokenType::Minus
+            | TokenType::Star
+            | TokenType::Slash => {}
+            TokenType::OpenParen => self.depth += 1,
+            TokenType::CloseParen if self.depth > 0 => self.depth -= 1,
+            // Separates function arguments
+            TokenType::Comma if self.depth > 0 => {}
+            _ if self.depth > 0 => {
+                return syntax_error(")", t, self.parent.recover(), ctx);
+            }
+            _ => {
+                // End of the expression
+                let span = match (self.tokens.first(), self.tokens.last()) {
+                    (Some(first), Some(last)) => first.span.to(last.span),
+                    _ => return syntax_error("<expression>", t, self.parent.recover(), ctx),
+                };
+                return match expression::parse_expr(self.tokens, t.span) {
+                    Ok(expr) => self.parent.expr_complete(expr, span).new_token(t, ctx),
+                    Err(err) => {
+                        // The error is within the expression, so the token after it may be
+                        // where parsing resumes
+                        ctx.errors.push(err);
+                        self.parent.recover().new_token(t, ctx)
+                    }
+                };
             }
         }
 
-        Ok(self)
+        self.tokens.push(t);
+        self
     }
 }
 
 struct ArrayState {
-    parent: Box<StructState>,
+    parent: Box<dyn TypeParent>,
     state: ArraySubState,
-    kind: Option<String>,
-    length: Option<ArrayLen>
+    kind: Option<ElementTypeRef>,
+    length: Option<ArrayLen>,
+    // Span of the opening bracket
+    start: Span,
 }
 
 impl ArrayState {
-    fn new(parent: Box<StructState>) -> ArrayState {
+    fn new(parent: Box<dyn TypeParent>, start: Span) -> ArrayState {
         ArrayState {
             parent,
+            start,
             state: ArraySubState::Begin,
             kind: None,
             length: None,
@@ -237,137 +831,375 @@ enum ArraySubState {
 }
 
 impl CompilerState for ArrayState {
-    fn new_token(
-        mut self: Box<Self>,
-        t: Token,
-        _: &mut Schema,
-    ) -> Result<Box<dyn CompilerState>, CartaError> {
+    fn new_token(mut self: Box<Self>, t: Token, ctx: &mut ParseContext) -> Box<dyn CompilerState> {
         // New lines are ignored
         if t.kind == TokenType::NewLine {
-            return Ok(self);
+            return self;
         }
 
         match self.state {
             ArraySubState::Begin => {
-                // Firstly, mmust have a typename
-                if t.kind != TokenType::Word {
-                    return Err(CartaError::new_parse_error(t.line_no, "<typename>", t.get_string()));
-                }
-
-                self.kind = Some(t.get_string());
+                // Firstly, must have a type.  This may be a typename, or another array.
                 self.state = ArraySubState::Kind;
+                return parse_type(t, self, ctx);
             }
             ArraySubState::Kind => {
                 // Next is semicolon separating type from length
                 if t.kind != TokenType::Semicolon {
-                    return Err(CartaError::new_parse_error(t.line_no, ";", t.get_string()));
+                    return syntax_error(";", t, self.parent.recover(), ctx);
                 }
                 self.state = ArraySubState::Semicolon;
             }
             ArraySubState::Semicolon => {
                 // Next is length
-                match t.kind {
-                    TokenType::Word => {
-                        self.length = Some(ArrayLen::Identifier(t.get_string()));
-                    },
-                    TokenType::Integer => {
-                        self.length = Some(ArrayLen::Static(t.get_int()));
-                    },
-                    _ => return Err(CartaError::new_parse_error(t.line_no, "<array_length>", t.get_string())),
-                }
-
                 self.state = ArraySubState::Length;
+                return Box::new(ExprState::new(self)).new_token(t, ctx);
             }
             ArraySubState::Length => {
                 // Finally, closing bracket
                 if t.kind != TokenType::CloseBracket {
-                    return Err(CartaError::new_parse_error(t.line_no, "]", t.get_string()));
+                    return syntax_error("]", t, self.parent.recover(), ctx);
                 }
 
                 // Aaaand, we're done
                 // We know we have both kind and length values available, as we've successfully
                 // moved through all states
                 let arr_defn = ArrayDefn {
-                    kind: self.kind.unwrap(),
+                    kind: Box::new(self.kind.unwrap()),
                     length: self.length.unwrap(),
                 };
-                self.parent
-                    .append_child(ElementTypeRef::ArrayElem(arr_defn), t.line_no);
-                return Ok(self.parent);
+                let span = self.start.to(t.span);
+                return self
+                    .parent
+                    .type_complete(ElementTypeRef::ArrayElem(arr_defn), span);
             }
         }
 
-        Ok(self)
+        self
     }
 }
 
-fn new_state(t: Token) -> Result<Option<Box<dyn CompilerState>>, CartaError> {
-    if t.kind == TokenType::Word {
-        let line_no = t.line_no;  // Copy line_no before consuming t
-
-        // Match against language keywords
-        return match t.get_string().as_ref() {
-            "struct" => Ok(Some(Box::new(StructState::new(line_no)))),
-            val => Err(CartaError::new_parse_error(line_no, "<keyword>", val.to_string())),
-        };
-    } else if t.kind == TokenType::NewLine {
-        // Empty newline - nothing to parse
-        return Ok(None);
+impl ExprParent for ArrayState {
+    fn expr_complete(mut self: Box<Self>, expr: Expr, _: Span) -> Box<dyn CompilerState> {
+        self.length = Some(ArrayLen::from_expr(expr));
+        self
     }
 
-    return Err(CartaError::new_parse_error(t.line_no, "<keyword>", t.get_string()));
+    fn recover(self: Box<Self>) -> Box<dyn CompilerState> {
+        self.parent.recover()
+    }
 }
 
-pub fn compile_schema(tokeniser: Tokeniser) -> Result<Schema, CartaError> {
-    let mut schema = Schema {
-        structs: Vec::new(),
-    };
-    let mut state: Box<dyn CompilerState> = Box::new(EmptyState {});
-    for token in tokeniser.into_iter() {
-        state = state.new_token(token, &mut schema)?;
+impl TypeParent for ArrayState {
+    fn type_complete(mut self: Box<Self>, kind: ElementTypeRef, _: Span) -> Box<dyn CompilerState> {
+        self.kind = Some(kind);
+        self
     }
 
-    // Check that parsing has completed, and we're not waiting for anything else
-    state.eof()?;
+    fn scope_name(&self) -> String {
+        self.parent.scope_name()
+    }
 
-    Ok(schema)
+    fn recover(self: Box<Self>) -> Box<dyn CompilerState> {
+        self.parent.recover()
+    }
 }
 
-#[cfg(test)]
-mod test {
-    use super::*;
-
-    fn build_basic_element(name: &str, typename: &str, line_no: usize) -> Element {
-        Element {
-            name: name.to_string(),
-            kind: ElementTypeRef::TypeName(typename.to_string()),
-            line_no,
+/// Start parsing a type expression, given its first token.  Either a typename, a block of bytes,
+/// an array or an inline struct or union.  Once complete, the type is passed back to `parent`.
+fn parse_type(
+    t: Token,
+    parent: Box<dyn TypeParent>,
+    ctx: &mut ParseContext,
+) -> Box<dyn CompilerState> {
+    match t.kind {
+        TokenType::Word if t.get_string_ref() == "bytes" => {
+            Box::new(BytesState::new(parent, t.span))
         }
-    }
-
-    fn build_array_element(name: &str, typename: &str, length: &str, line_no: usize) -> Element {
-        Element {
-            name: name.to_string(),
-            kind: ElementTypeRef::ArrayElem(ArrayDefn {
-                kind: typename.to_string(),
-                length: ArrayLen::Identifier(length.to_string()),
-            }),
-            line_no,
+        TokenType::Word if t.get_string_ref() == "struct" => {
+            Box::new(StructState::new_inline(parent, t.span, false))
+        }
+        TokenType::Word if t.get_string_ref() == "union" => {
+            Box::new(StructState::new_inline(parent, t.span, true))
         }
+        TokenType::Word => {
+            let span = t.span;
+            parent.type_complete(ElementTypeRef::TypeName(t.get_string()), span)
+        }
+        TokenType::OpenBracket => Box::new(ArrayState::new(parent, t.span)),
+        _ => syntax_error("<typename>", t, parent.recover(), ctx),
     }
+}
 
-    fn build_struct(name: &str, elements: Vec<Element>, line_no: usize) -> StructDefn {
-        StructDefn {
-            name: name.to_string(),
-            elements: elements,
-            line_no,
+/// Parse a block of bytes, eg. `bytes[len]`.  The `bytes` keyword has already been consumed.
+struct BytesState {
+    parent: Box<dyn TypeParent>,
+    state: BytesSubState,
+    length: Option<ArrayLen>,
+    // Span of the `bytes` keyword
+    start: Span,
+}
+
+impl BytesState {
+    fn new(parent: Box<dyn TypeParent>, start: Span) -> BytesState {
+        BytesState {
+            parent,
+            start,
+            state: BytesSubState::Begin,
+            length: None,
         }
     }
+}
 
-    #[test]
-    fn basic_builtin() -> Result<(), CartaError> {
-        let tokeniser = Tokeniser::new("struct s {new_name: uint64_le}")?;
-        let schema = compile_schema(tokeniser)?;
+#[derive(PartialEq)]
+enum BytesSubState {
+    Begin,
+    OpenBracket,
+    Length,
+}
+
+impl CompilerState for BytesState {
+    fn new_token(mut self: Box<Self>, t: Token, ctx: &mut ParseContext) -> Box<dyn CompilerState> {
+        // New lines are ignored
+        if t.kind == TokenType::NewLine {
+            return self;
+        }
+
+        match self.state {
+            BytesSubState::Begin => {
+                if t.kind != TokenType::OpenBracket {
+                    return syntax_error("[", t, self.parent.recover(), ctx);
+                }
+                self.state = BytesSubState::OpenBracket;
+            }
+            BytesSubState::OpenBracket => {
+                self.state = BytesSubState::Length;
+                return Box::new(ExprState::new(self)).new_token(t, ctx);
+            }
+            BytesSubState::Length => {
+                if t.kind != TokenType::CloseBracket {
+                    return syntax_error("]", t, self.parent.recover(), ctx);
+                }
+                let length = self.length.unwrap();
+                let span = self.start.to(t.span);
+                return self
+                    .parent
+                    .type_complete(ElementTypeRef::Bytes(length), span);
+            }
+        }
+
+        self
+    }
+}
+
+impl ExprParent for BytesState {
+    fn expr_complete(mut self: Box<Self>, expr: Expr, _: Span) -> Box<dyn CompilerState> {
+        self.length = Some(ArrayLen::from_expr(expr));
+        self
+    }
+
+    fn recover(self: Box<Self>) -> Box<dyn CompilerState> {
+        self.parent.recover()
+    }
+}
+
+/// Parse an entry point declaration, eg. `root Png;` or `root Png, Chunk;`.  The `root` keyword
+/// has already been consumed.
+struct RootState {
+    state: RootSubState,
+}
+
+#[derive(PartialEq)]
+enum RootSubState {
+    Begin,
+    Name,
+}
+
+impl RootState {
+    fn new() -> RootState {
+        RootState {
+            state: RootSubState::Begin,
+        }
+    }
+}
+
+impl CompilerState for RootState {
+    fn new_token(mut self: Box<Self>, t: Token, ctx: &mut ParseContext) -> Box<dyn CompilerState> {
+        // New lines are ignored
+        if t.kind == TokenType::NewLine {
+            return self;
+        }
+
+        match self.state {
+            RootSubState::Begin => {
+                if t.kind != TokenType::Word {
+                    return syntax_error("<name>", t, Box::new(SkipState { depth: 0 }), ctx);
+                }
+                let span = t.span;
+                ctx.schema.add_root(RootDecl {
+                    name: t.get_string(),
+                    span,
+                });
+                self.state = RootSubState::Name;
+            }
+            RootSubState::Name => match t.kind {
+                // Another entry point follows
+                TokenType::Comma => self.state = RootSubState::Begin,
+                TokenType::Semicolon => return Box::new(EmptyState {}),
+                _ => return syntax_error(";", t, Box::new(SkipState { depth: 0 }), ctx),
+            },
+        }
+
+        self
+    }
+}
+
+/// Start a new top level declaration from its first token.  Returns None if the token doesn't
+/// start anything.
+fn new_state(t: Token, ctx: &mut ParseContext) -> Option<Box<dyn CompilerState>> {
+    if t.kind == TokenType::Word {
+        // Match against language keywords
+        return match t.get_string_ref() {
+            "struct" => Some(Box::new(StructState::new(t.span, false))),
+            "union" => Some(Box::new(StructState::new(t.span, true))),
+            "root" => Some(Box::new(RootState::new())),
+            _ => Some(syntax_error(
+                "<keyword>",
+                t,
+                Box::new(SkipState { depth: 0 }),
+                ctx,
+            )),
+        };
+    } else if t.kind == TokenType::NewLine {
+        // Empty newline - nothing to parse
+        return None;
+    }
+
+    Some(syntax_error(
+        "<keyword>",
+        t,
+        Box::new(SkipState { depth: 0 }),
+        ctx,
+    ))
+}
+
+/// Parse the tokens into a schema, stopping at the first syntax error.  Used by tests that check
+/// for one error.
+#[cfg(test)]
+pub fn compile_schema(tokeniser: Tokeniser) -> Result<Schema, CartaError> {
+    let (schema, errors) = compile_schema_with_errors(tokeniser);
+    error::first_error(schema, errors)
+}
+
+/// Parse the tokens into a schema, carrying on after syntax errors to find as many as possible.
+/// The schema is incomplete if there are any errors.
+pub fn compile_schema_with_errors(tokeniser: Tokeniser) -> (Schema, Vec<CartaError>) {
+    let mut ctx = ParseContext {
+        schema: Schema {
+            structs: Vec::new(),
+            roots: Vec::new(),
+        },
+        errors: Vec::new(),
+    };
+    let mut state: Box<dyn CompilerState> = Box::new(EmptyState {});
+    let mut end = Position::start();
+    for token in tokeniser.into_iter() {
+        // Errors at the end of the input point just after the last token, ignoring blank lines
+        if token.kind != TokenType::NewLine {
+            end = token.span.end;
+        }
+        state = state.new_token(token, &mut ctx);
+    }
+
+    // Check that parsing has completed, and we're not waiting for anything else
+    state.eof(end, &mut ctx);
+
+    error::sort_errors(&mut ctx.errors);
+    (ctx.schema, ctx.errors)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::error::CartaErrorCode;
+    use crate::expression::BinOp;
+    use crate::span::LineSpans;
+
+    impl LineSpans for Schema {
+        fn line_spans(self) -> Schema {
+            Schema {
+                structs: self.structs.line_spans(),
+                roots: self.roots.line_spans(),
+            }
+        }
+    }
+
+    impl LineSpans for StructDefn {
+        fn line_spans(self) -> StructDefn {
+            StructDefn {
+                elements: self.elements.line_spans(),
+                span: self.span.line_spans(),
+                ..self
+            }
+        }
+    }
+
+    impl LineSpans for Element {
+        fn line_spans(self) -> Element {
+            Element {
+                span: self.span.line_spans(),
+                ..self
+            }
+        }
+    }
+
+    impl LineSpans for RootDecl {
+        fn line_spans(self) -> RootDecl {
+            RootDecl {
+                span: self.span.line_spans(),
+                ..self
+            }
+        }
+    }
+
+    fn build_basic_element(name: &str, typename: &str, line_no: usize) -> Element {
+        Element {
+            name: name.to_string(),
+            kind: ElementTypeRef::TypeName(typename.to_string()),
+            span: Span::from(line_no),
+            peek: false,
+            placement: None,
+        }
+    }
+
+    fn build_array_element(name: &str, typename: &str, length: &str, line_no: usize) -> Element {
+        Element {
+            name: name.to_string(),
+            kind: ElementTypeRef::ArrayElem(ArrayDefn {
+                kind: Box::new(ElementTypeRef::TypeName(typename.to_string())),
+                length: ArrayLen::Identifier(length.to_string()),
+            }),
+            span: Span::from(line_no),
+            peek: false,
+            placement: None,
+        }
+    }
+
+    fn build_struct(name: &str, elements: Vec<Element>, line_no: usize) -> StructDefn {
+        StructDefn {
+            name: name.to_string(),
+            elements,
+            span: Span::from(line_no),
+            base: None,
+            num_inherited: 0,
+            is_union: false,
+        }
+    }
+
+    #[test]
+    fn basic_builtin() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new("struct s {new_name: uint64_le}")?;
+        let schema = compile_schema(tokeniser).line_spans()?;
         let mut iter = schema.structs.iter();
         assert_eq!(
             iter.next(),
@@ -384,7 +1216,7 @@ mod test {
     #[test]
     fn empty_input() -> Result<(), CartaError> {
         let tokeniser = Tokeniser::new("")?;
-        let schema = compile_schema(tokeniser)?;
+        let schema = compile_schema(tokeniser).line_spans()?;
         let mut iter = schema.structs.iter();
         assert_eq!(iter.next(), None);
         Ok(())
@@ -393,7 +1225,7 @@ mod test {
     #[test]
     fn whitespace_input() -> Result<(), CartaError> {
         let tokeniser = Tokeniser::new("\n  \t\n\n")?;
-        let schema = compile_schema(tokeniser)?;
+        let schema = compile_schema(tokeniser).line_spans()?;
         let mut iter = schema.structs.iter();
         assert_eq!(iter.next(), None);
         Ok(())
@@ -408,7 +1240,7 @@ mod test {
                 name3: f64_le,
             }",
         )?;
-        let schema = compile_schema(tokeniser)?;
+        let schema = compile_schema(tokeniser).line_spans()?;
         let mut iter = schema.structs.iter();
         assert_eq!(
             iter.next(),
@@ -434,10 +1266,14 @@ mod test {
                 inner_val2, int8,
             }",
         )?;
-        let ret = compile_schema(tokeniser);
+        let ret = compile_schema(tokeniser).line_spans();
         assert_eq!(
             ret,
-            Err(CartaError::new_parse_error(3, ":", ",".to_string()))
+            Err(CartaError::new_parse_error(
+                Span::from(3),
+                ":",
+                ",".to_string()
+            ))
         );
 
         let tokeniser = Tokeniser::new(
@@ -446,10 +1282,14 @@ mod test {
                 inner_val2: int8,:
             }",
         )?;
-        let ret = compile_schema(tokeniser);
+        let ret = compile_schema(tokeniser).line_spans();
         assert_eq!(
             ret,
-            Err(CartaError::new_parse_error(3, "}", ":".to_string()))
+            Err(CartaError::new_parse_error(
+                Span::from(3),
+                "}",
+                ":".to_string()
+            ))
         );
 
         let tokeniser = Tokeniser::new(
@@ -458,10 +1298,14 @@ mod test {
                 inner_val2: int8,:
             }",
         )?;
-        let ret = compile_schema(tokeniser);
+        let ret = compile_schema(tokeniser).line_spans();
         assert_eq!(
             ret,
-            Err(CartaError::new_parse_error(1, "<name>", "{".to_string()))
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<name>",
+                "{".to_string()
+            ))
         );
 
         let tokeniser = Tokeniser::new(
@@ -470,10 +1314,14 @@ mod test {
                 inner_val2: int8,:
             }",
         )?;
-        let ret = compile_schema(tokeniser);
+        let ret = compile_schema(tokeniser).line_spans();
         assert_eq!(
             ret,
-            Err(CartaError::new_parse_error(2, "{", "inner_val1".to_string()))
+            Err(CartaError::new_parse_error(
+                Span::from(2),
+                "{",
+                "inner_val1".to_string()
+            ))
         );
 
         let tokeniser = Tokeniser::new(
@@ -482,10 +1330,14 @@ mod test {
                 inner_val2: int8,:
             }",
         )?;
-        let ret = compile_schema(tokeniser);
+        let ret = compile_schema(tokeniser).line_spans();
         assert_eq!(
             ret,
-            Err(CartaError::new_parse_error(3, "}", ":".to_string()))
+            Err(CartaError::new_parse_error(
+                Span::from(3),
+                "}",
+                ":".to_string()
+            ))
         );
 
         let tokeniser = Tokeniser::new(
@@ -494,10 +1346,14 @@ mod test {
                 inner_val2: int8,
             }",
         )?;
-        let ret = compile_schema(tokeniser);
+        let ret = compile_schema(tokeniser).line_spans();
         assert_eq!(
             ret,
-            Err(CartaError::new_parse_error(2, "<typename>", ",".to_string()))
+            Err(CartaError::new_parse_error(
+                Span::from(2),
+                "<typename>",
+                ",".to_string()
+            ))
         );
 
         let tokeniser = Tokeniser::new(
@@ -506,10 +1362,14 @@ mod test {
                 inner_val2: int8,
             }",
         )?;
-        let ret = compile_schema(tokeniser);
+        let ret = compile_schema(tokeniser).line_spans();
         assert_eq!(
             ret,
-            Err(CartaError::new_parse_error(3, ",", "inner_val2".to_string()))
+            Err(CartaError::new_parse_error(
+                Span::from(3),
+                ",",
+                "inner_val2".to_string()
+            ))
         );
         Ok(())
     }
@@ -517,7 +1377,7 @@ mod test {
     #[test]
     fn array() -> Result<(), CartaError> {
         let tokeniser = Tokeniser::new("struct s {len: int8, arr1: [int8; len]}")?;
-        let schema = compile_schema(tokeniser)?;
+        let schema = compile_schema(tokeniser).line_spans()?;
         let mut iter = schema.structs.iter();
         assert_eq!(
             iter.next(),
@@ -537,7 +1397,7 @@ mod test {
     #[test]
     fn array_bad_len() -> Result<(), CartaError> {
         let tokeniser = Tokeniser::new("struct s {len: int8, arr1: [int8; blah]}")?;
-        let schema = compile_schema(tokeniser)?;
+        let schema = compile_schema(tokeniser).line_spans()?;
         let mut iter = schema.structs.iter();
         assert_eq!(
             iter.next(),
@@ -557,20 +1417,64 @@ mod test {
     #[test]
     fn array_static_len() -> Result<(), CartaError> {
         let tokeniser = Tokeniser::new("struct s {arr1: [int8; 4]}")?;
-        let schema = compile_schema(tokeniser)?;
+        let schema = compile_schema(tokeniser).line_spans()?;
+        let mut iter = schema.structs.iter();
+        assert_eq!(
+            iter.next(),
+            Some(&build_struct(
+                "s",
+                vec![Element {
+                    name: "arr1".to_string(),
+                    kind: ElementTypeRef::ArrayElem(ArrayDefn {
+                        kind: Box::new(ElementTypeRef::TypeName("int8".to_string())),
+                        length: ArrayLen::Static(4),
+                    }),
+                    span: Span::from(1),
+                    peek: false,
+                    placement: None,
+                }],
+                1
+            ))
+        );
+        assert_eq!(iter.next(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn nested_array() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new(
+            "struct s {width: uint8, pixels: [[uint8; 3]; width], blobs: [bytes[4]; 2]}",
+        )?;
+        let schema = compile_schema(tokeniser).line_spans()?;
         let mut iter = schema.structs.iter();
         assert_eq!(
             iter.next(),
             Some(&build_struct(
                 "s",
                 vec![
+                    build_basic_element("width", "uint8", 1),
+                    Element {
+                        name: "pixels".to_string(),
+                        kind: ElementTypeRef::ArrayElem(ArrayDefn {
+                            kind: Box::new(ElementTypeRef::ArrayElem(ArrayDefn {
+                                kind: Box::new(ElementTypeRef::TypeName("uint8".to_string())),
+                                length: ArrayLen::Static(3),
+                            })),
+                            length: ArrayLen::Identifier("width".to_string()),
+                        }),
+                        span: Span::from(1),
+                        peek: false,
+                        placement: None,
+                    },
                     Element {
-                        name: "arr1".to_string(),
+                        name: "blobs".to_string(),
                         kind: ElementTypeRef::ArrayElem(ArrayDefn {
-                            kind: "int8".to_string(),
-                            length: ArrayLen::Static(4),
+                            kind: Box::new(ElementTypeRef::Bytes(ArrayLen::Static(4))),
+                            length: ArrayLen::Static(2),
                         }),
-                        line_no: 1,
+                        span: Span::from(1),
+                        peek: false,
+                        placement: None,
                     }
                 ],
                 1
@@ -581,23 +1485,867 @@ mod test {
     }
 
     #[test]
-    fn incomplete_input() {
-        let tokeniser = Tokeniser::new("struct s {field_1").unwrap();
-        let result = compile_schema(tokeniser);
-        assert_eq!(result, Err(CartaError::new_incomplete_input(0)));
+    fn nested_array_errors() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new("struct s {arr: [[uint8; 3] 4]}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                ";",
+                "4".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct s {arr: [[uint8; 3]; 4}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "]",
+                "}".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct s {arr: [; 4]}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<typename>",
+                ";".to_string()
+            ))
+        );
+        Ok(())
     }
 
     #[test]
-    fn line_numbers() -> Result<(), CartaError> {
-        let tokeniser = Tokeniser::new("
-            struct s {field_1: int8}
-            struct s2 {field_1: int16_le}
-            struct s3 {
-                field_1: int64_be,
-                arr: [int8; field_1],
-            }
-        ")?;
-        let schema = compile_schema(tokeniser)?;
+    fn inline_struct() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new(
+            "struct root {
+                header: struct {
+                    magic: uint32_le,
+                    ver: struct { major: uint8, minor: uint8 },
+                },
+                entries: [struct { val: int8 }; 2]
+            }",
+        )?;
+        let schema = compile_schema(tokeniser).line_spans()?;
+        let mut iter = schema.structs.iter();
+        assert_eq!(
+            iter.next(),
+            Some(&build_struct(
+                "root::header::ver",
+                vec![
+                    build_basic_element("major", "uint8", 4),
+                    build_basic_element("minor", "uint8", 4)
+                ],
+                4
+            ))
+        );
+        assert_eq!(
+            iter.next(),
+            Some(&build_struct(
+                "root::header",
+                vec![
+                    build_basic_element("magic", "uint32_le", 3),
+                    build_basic_element("ver", "root::header::ver", 4)
+                ],
+                2
+            ))
+        );
+        assert_eq!(
+            iter.next(),
+            Some(&build_struct(
+                "root::entries",
+                vec![build_basic_element("val", "int8", 6)],
+                6
+            ))
+        );
+        assert_eq!(
+            iter.next(),
+            Some(&build_struct(
+                "root",
+                vec![
+                    build_basic_element("header", "root::header", 2),
+                    Element {
+                        name: "entries".to_string(),
+                        kind: ElementTypeRef::ArrayElem(ArrayDefn {
+                            kind: Box::new(ElementTypeRef::TypeName("root::entries".to_string())),
+                            length: ArrayLen::Static(2),
+                        }),
+                        span: Span::from(6),
+                        peek: false,
+                        placement: None,
+                    }
+                ],
+                1
+            ))
+        );
+        assert_eq!(iter.next(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn unions() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new(
+            "struct root {val: union {as_int: uint32_le, as_float: f32_le}}
+            union Named {a: int8}",
+        )?;
+        let schema = compile_schema(tokeniser).line_spans()?;
+        let mut iter = schema.structs.iter();
+        assert_eq!(
+            iter.next(),
+            Some(&StructDefn {
+                is_union: true,
+                ..build_struct(
+                    "root::val",
+                    vec![
+                        build_basic_element("as_int", "uint32_le", 1),
+                        build_basic_element("as_float", "f32_le", 1)
+                    ],
+                    1
+                )
+            })
+        );
+        assert_eq!(
+            iter.next(),
+            Some(&build_struct(
+                "root",
+                vec![build_basic_element("val", "root::val", 1)],
+                1
+            ))
+        );
+        assert_eq!(
+            iter.next(),
+            Some(&StructDefn {
+                is_union: true,
+                ..build_struct("Named", vec![build_basic_element("a", "int8", 2)], 2)
+            })
+        );
+        assert_eq!(iter.next(), None);
+
+        // Unions can't extend other types
+        let tokeniser = Tokeniser::new("union Named extends Base {a: int8}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "{",
+                "extends".to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn inline_struct_same_name() -> Result<(), CartaError> {
+        let tokeniser =
+            Tokeniser::new("struct root {h: struct {a: uint8}, h: uint8, h: struct {b: uint8}}")?;
+        let schema = compile_schema(tokeniser).line_spans()?;
+        let names: Vec<&str> = schema.structs.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["root::h", "root::h#3", "root"]);
+        assert_eq!(
+            schema.structs[2].elements[2].kind,
+            ElementTypeRef::TypeName("root::h#3".to_string())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn inline_struct_errors() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new("struct root {header: struct Header { magic: uint32_le }}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "{",
+                "Header".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct root {header: struct { magic: uint32_le }")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(ret, Err(CartaError::new_incomplete_input(Span::from(1))));
+        Ok(())
+    }
+
+    #[test]
+    fn bytes() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new("struct s {len: int8, data: bytes[len], magic: bytes[4]}")?;
+        let schema = compile_schema(tokeniser).line_spans()?;
+        let mut iter = schema.structs.iter();
+        assert_eq!(
+            iter.next(),
+            Some(&build_struct(
+                "s",
+                vec![
+                    build_basic_element("len", "int8", 1),
+                    Element {
+                        name: "data".to_string(),
+                        kind: ElementTypeRef::Bytes(ArrayLen::Identifier("len".to_string())),
+                        span: Span::from(1),
+                        peek: false,
+                        placement: None,
+                    },
+                    Element {
+                        name: "magic".to_string(),
+                        kind: ElementTypeRef::Bytes(ArrayLen::Static(4)),
+                        span: Span::from(1),
+                        peek: false,
+                        placement: None,
+                    }
+                ],
+                1
+            ))
+        );
+        assert_eq!(iter.next(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn bytes_syntax_errors() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new("struct s {data: bytes}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "[",
+                "}".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct s {data: bytes[]}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<expression>",
+                "]".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct s {data: bytes[4; 2]}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "]",
+                ";".to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn extends() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new(
+            "struct V4Header extends InfoHeader {
+                red_mask: uint32_le
+            }",
+        )?;
+        let schema = compile_schema(tokeniser).line_spans()?;
+        let mut iter = schema.structs.iter();
+        assert_eq!(
+            iter.next(),
+            Some(&StructDefn {
+                name: "V4Header".to_string(),
+                elements: vec![build_basic_element("red_mask", "uint32_le", 2)],
+                span: Span::from(1),
+                base: Some("InfoHeader".to_string()),
+                num_inherited: 0,
+                is_union: false,
+            })
+        );
+        assert_eq!(iter.next(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn extends_syntax_errors() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new("struct V4Header extends {}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<typename>",
+                "{".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct V4Header extends A, B {}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "{",
+                ",".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct V4Header base A {}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "{",
+                "base".to_string()
+            ))
+        );
+
+        // Inline structs can't extend other structs
+        let tokeniser = Tokeniser::new("struct root {val: struct extends A {}}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "{",
+                "extends".to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn peek() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new("struct s {peek tag: uint8, peek: int8, peek peek: int8}")?;
+        let schema = compile_schema(tokeniser).line_spans()?;
+        let mut iter = schema.structs.iter();
+        assert_eq!(
+            iter.next(),
+            Some(&build_struct(
+                "s",
+                vec![
+                    Element {
+                        peek: true,
+                        ..build_basic_element("tag", "uint8", 1)
+                    },
+                    build_basic_element("peek", "int8", 1),
+                    Element {
+                        peek: true,
+                        ..build_basic_element("peek", "int8", 1)
+                    },
+                ],
+                1
+            ))
+        );
+        assert_eq!(iter.next(), None);
+
+        let tokeniser = Tokeniser::new("struct s {peek peek tag: uint8}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                ":",
+                "tag".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct s {other tag: uint8}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                ":",
+                "tag".to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    fn build_placed_element(name: &str, placement: Expr, line_no: usize) -> Element {
+        Element {
+            placement: Some(placement),
+            ..build_basic_element(name, "uint8", line_no)
+        }
+    }
+
+    fn binary_op(lhs: Expr, op: BinOp, rhs: Expr) -> Expr {
+        Expr::BinaryOp(Box::new(lhs), op, Box::new(rhs))
+    }
+
+    #[test]
+    fn placement() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new(
+            "struct s {a: uint8 @ end - 22, b: uint8 @ 128, c: uint8 @ a + 4 - 1,
+                d: uint8 @ rfind(0x50, 0x4b, 5, 6) + 2, e: uint8}",
+        )?;
+        let schema = compile_schema(tokeniser).line_spans()?;
+        let elements = &schema.structs[0].elements;
+        assert_eq!(
+            elements[0],
+            build_placed_element("a", binary_op(Expr::End, BinOp::Sub, Expr::Integer(22)), 1)
+        );
+        assert_eq!(
+            elements[1],
+            build_placed_element("b", Expr::Integer(128), 1)
+        );
+        assert_eq!(
+            elements[2],
+            build_placed_element(
+                "c",
+                binary_op(
+                    binary_op(
+                        Expr::Identifier("a".to_string()),
+                        BinOp::Add,
+                        Expr::Integer(4)
+                    ),
+                    BinOp::Sub,
+                    Expr::Integer(1)
+                ),
+                1
+            )
+        );
+        assert_eq!(
+            elements[3],
+            build_placed_element(
+                "d",
+                binary_op(
+                    Expr::FindLast(vec![0x50, 0x4b, 5, 6]),
+                    BinOp::Add,
+                    Expr::Integer(2)
+                ),
+                2
+            )
+        );
+        assert_eq!(elements[4], build_basic_element("e", "uint8", 2));
+        Ok(())
+    }
+
+    #[test]
+    fn placement_syntax_errors() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new("struct s {a: uint8 @ end - 2 @ end}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                ",",
+                "@".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct s {a: uint8 @ end - }")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(ret, Err(CartaError::new_incomplete_input(Span::from(1))));
+
+        let tokeniser = Tokeniser::new("struct s {a: uint8 @ rfind(0x01, }")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                ")",
+                "}".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct s {a: uint8 @ rfind()}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<byte>",
+                ")".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct s {a: uint8 @ rfind(0x100)}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<byte>",
+                "256".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct s {a: uint8 @ }")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<expression>",
+                "}".to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn length_expressions() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new(
+            "struct s {a: [uint8; (2)], b: bytes[size - sizeof(Header)], c: [[uint8; a * 2]; 3]}",
+        )?;
+        let schema = compile_schema(tokeniser).line_spans()?;
+        let elements = &schema.structs[0].elements;
+        assert_eq!(
+            elements[0].kind,
+            ElementTypeRef::ArrayElem(ArrayDefn {
+                kind: Box::new(ElementTypeRef::TypeName("uint8".to_string())),
+                length: ArrayLen::Static(2),
+            })
+        );
+        assert_eq!(
+            elements[1].kind,
+            ElementTypeRef::Bytes(ArrayLen::Expr(binary_op(
+                Expr::Identifier("size".to_string()),
+                BinOp::Sub,
+                Expr::SizeOf("Header".to_string())
+            )))
+        );
+        assert_eq!(
+            elements[2].kind,
+            ElementTypeRef::ArrayElem(ArrayDefn {
+                kind: Box::new(ElementTypeRef::ArrayElem(ArrayDefn {
+                    kind: Box::new(ElementTypeRef::TypeName("uint8".to_string())),
+                    length: ArrayLen::Expr(binary_op(
+                        Expr::Identifier("a".to_string()),
+                        BinOp::Mul,
+                        Expr::Integer(2)
+                    )),
+                })),
+                length: ArrayLen::Static(3),
+            })
+        );
+        assert_eq!(elements[1].exprs().len(), 1);
+        assert_eq!(elements[2].exprs().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn computed() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new(
+            "struct s {w: uint8, let: uint8, let size = (w + 1)\n * let; data: bytes[size]}",
+        )?;
+        let schema = compile_schema(tokeniser).line_spans()?;
+        let elements = &schema.structs[0].elements;
+        assert_eq!(elements.len(), 4);
+        assert_eq!(elements[1], build_basic_element("let", "uint8", 1));
+        assert_eq!(
+            elements[2],
+            Element {
+                name: "size".to_string(),
+                kind: ElementTypeRef::Computed(Expr::BinaryOp(
+                    Box::new(Expr::BinaryOp(
+                        Box::new(Expr::Identifier("w".to_string())),
+                        BinOp::Add,
+                        Box::new(Expr::Integer(1)),
+                    )),
+                    BinOp::Mul,
+                    Box::new(Expr::Identifier("let".to_string())),
+                )),
+                span: Span::from(1),
+                peek: false,
+                placement: None,
+            }
+        );
+        assert_eq!(elements[3].name, "data");
+        Ok(())
+    }
+
+    #[test]
+    fn computed_syntax_errors() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new("struct s {let a: uint8}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "=",
+                ":".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct s {let a = ;}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<expression>",
+                ";".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct s {let a = 1 + 2}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                ";",
+                "}".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct s {let a = (1 + 2;}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                ")",
+                ";".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("struct s {let a = 1 2;}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<operator>",
+                "2".to_string()
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn roots() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new(
+            "root Png;
+            root Chunk,
+                Header;
+            struct Png {chunk: Chunk}",
+        )?;
+        let schema = compile_schema(tokeniser).line_spans()?;
+        assert_eq!(
+            schema.roots,
+            vec![
+                RootDecl {
+                    name: "Png".to_string(),
+                    span: Span::from(1),
+                },
+                RootDecl {
+                    name: "Chunk".to_string(),
+                    span: Span::from(2),
+                },
+                RootDecl {
+                    name: "Header".to_string(),
+                    span: Span::from(3),
+                },
+            ]
+        );
+        assert_eq!(schema.structs.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn root_syntax_errors() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new("root;")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                "<name>",
+                ";".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("root Png struct Png {}")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(
+            ret,
+            Err(CartaError::new_parse_error(
+                Span::from(1),
+                ";",
+                "struct".to_string()
+            ))
+        );
+
+        let tokeniser = Tokeniser::new("root Png")?;
+        let ret = compile_schema(tokeniser).line_spans();
+        assert_eq!(ret, Err(CartaError::new_incomplete_input(Span::from(1))));
+        Ok(())
+    }
+
+    #[test]
+    fn incomplete_input() {
+        let tokeniser = Tokeniser::new("struct s {field_1").unwrap();
+        let result = compile_schema(tokeniser).line_spans();
+        assert_eq!(result, Err(CartaError::new_incomplete_input(Span::from(1))));
+    }
+
+    // (line, column) of the start and end of a span
+    fn span_cols(span: Span) -> ((usize, usize), (usize, usize)) {
+        (
+            (span.start.line, span.start.column),
+            (span.end.line, span.end.column),
+        )
+    }
+
+    #[test]
+    fn spans() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new(
+            "struct s {
+    a: uint8,
+    peek b: [int8;
+        a],
+    c: bytes[4] @ a + 1,
+    let d = a * 2;
+    e: struct { x: uint8 }
+}",
+        )?;
+        let schema = compile_schema(tokeniser)?;
+        let s = &schema.structs[1];
+        assert_eq!(span_cols(s.span), ((1, 1), (8, 2)));
+        let spans: Vec<_> = s.elements.iter().map(|elem| span_cols(elem.span)).collect();
+        assert_eq!(
+            spans,
+            vec![
+                ((2, 5), (2, 13)),
+                ((3, 5), (4, 11)),
+                ((5, 5), (5, 24)),
+                ((6, 5), (6, 18)),
+                ((7, 5), (7, 27)),
+            ]
+        );
+        assert_eq!(s.elements[1].line_no(), 3);
+        // Inline structs span from their keyword
+        assert_eq!(span_cols(schema.structs[0].span), ((7, 8), (7, 27)));
+
+        let tokeniser = Tokeniser::new("root Png, Chunk;")?;
+        let schema = compile_schema(tokeniser)?;
+        assert_eq!(span_cols(schema.roots[1].span), ((1, 11), (1, 16)));
+        Ok(())
+    }
+
+    #[test]
+    fn error_spans() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new("struct s {\n    a: uint8 b")?;
+        let err = compile_schema(tokeniser).unwrap_err();
+        assert_eq!(span_cols(err.span), ((2, 14), (2, 15)));
+
+        // Incomplete input is reported at the end of the last token
+        let tokeniser = Tokeniser::new("struct s {\n  a: [uint8; 2\n\n")?;
+        let err = compile_schema(tokeniser).unwrap_err();
+        assert_eq!(err.code, CartaErrorCode::IncompleteInput());
+        assert_eq!(span_cols(err.span), ((2, 15), (2, 15)));
+        Ok(())
+    }
+
+    fn parse_with_errors(data: &str) -> (Schema, Vec<CartaError>) {
+        compile_schema_with_errors(Tokeniser::new(data).unwrap()).line_spans()
+    }
+
+    fn struct_names(schema: &Schema) -> Vec<&str> {
+        schema.structs.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    fn element_names(s: &StructDefn) -> Vec<&str> {
+        s.elements.iter().map(|elem| elem.name.as_str()).collect()
+    }
+
+    #[test]
+    fn error_recovery() {
+        // Bad elements are skipped, and parsing continues with the next element
+        let (schema, errors) = parse_with_errors(
+            "struct s {
+                a uint8,
+                b: uint8,
+                c: [uint8 2],
+                d: bytes[1 2],
+                e: uint8
+            }
+            struct t {x: uint8}",
+        );
+        assert_eq!(
+            errors,
+            vec![
+                CartaError::new_parse_error(Span::from(2), ":", "uint8".to_string()),
+                CartaError::new_parse_error(Span::from(4), ";", "2".to_string()),
+                CartaError::new_parse_error(Span::from(5), "<operator>", "2".to_string()),
+            ]
+        );
+        assert_eq!(struct_names(&schema), vec!["s", "t"]);
+        assert_eq!(element_names(&schema.structs[0]), vec!["b", "e"]);
+
+        // A missing close brace ends the struct at the next declaration
+        let (schema, errors) = parse_with_errors(
+            "struct s {
+                a: uint8
+            struct t {x: uint8}",
+        );
+        assert_eq!(
+            errors,
+            vec![CartaError::new_parse_error(
+                Span::from(3),
+                ",",
+                "struct".to_string()
+            )]
+        );
+        assert_eq!(struct_names(&schema), vec!["t"]);
+
+        // Errors in inline structs are recovered from within the inline struct
+        let (schema, errors) = parse_with_errors(
+            "struct s {
+                h: struct {x uint8, y: uint8},
+                z: uint8
+            }",
+        );
+        assert_eq!(
+            errors,
+            vec![CartaError::new_parse_error(
+                Span::from(2),
+                ":",
+                "uint8".to_string()
+            )]
+        );
+        assert_eq!(struct_names(&schema), vec!["s::h", "s"]);
+        assert_eq!(element_names(&schema.structs[0]), vec!["y"]);
+        assert_eq!(element_names(&schema.structs[1]), vec!["h", "z"]);
+
+        // Errors outside of structs skip to the next declaration
+        let (schema, errors) = parse_with_errors(
+            "foo bar { x }
+            struct 5 {y: uint8}
+            root t t;
+            struct t {x: uint8}",
+        );
+        assert_eq!(
+            errors,
+            vec![
+                CartaError::new_parse_error(Span::from(1), "<keyword>", "foo".to_string()),
+                CartaError::new_parse_error(Span::from(2), "<name>", "5".to_string()),
+                CartaError::new_parse_error(Span::from(3), ";", "t".to_string()),
+            ]
+        );
+        assert_eq!(struct_names(&schema), vec!["t"]);
+    }
+
+    #[test]
+    fn line_numbers() -> Result<(), CartaError> {
+        let tokeniser = Tokeniser::new(
+            "
+            struct s {field_1: int8}
+            struct s2 {field_1: int16_le}
+            struct s3 {
+                field_1: int64_be,
+                arr: [int8; field_1],
+            }
+        ",
+        )?;
+        let schema = compile_schema(tokeniser).line_spans()?;
         let mut iter = schema.structs.iter();
         assert_eq!(
             iter.next(),
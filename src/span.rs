@@ -0,0 +1,204 @@
+/*!
+ * Source locations
+ *
+ * Every token, AST node and error records the part of the schema source it came from, so that
+ * tools can point at the exact text responsible for an error.
+ */
+
+/// Location of a single character in the schema source
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Position {
+    // Line number, starting from 1
+    pub line: usize,
+
+    // Column in characters, starting from 1.  Column 0 means the column isn't known.
+    pub column: usize,
+
+    // Byte offset from the start of the source
+    pub offset: usize,
+}
+
+impl Position {
+    /// Position of the first character of the source
+    pub fn start() -> Position {
+        Position {
+            line: 1,
+            column: 1,
+            offset: 0,
+        }
+    }
+
+    /// Position of the character following `c`, which is at this position
+    pub fn after(self, c: char) -> Position {
+        if c == '\n' {
+            Position {
+                line: self.line + 1,
+                column: 1,
+                offset: self.offset + 1,
+            }
+        } else {
+            Position {
+                line: self.line,
+                column: self.column + 1,
+                offset: self.offset + c.len_utf8(),
+            }
+        }
+    }
+}
+
+/// A range of the schema source, from `start` up to but not including `end`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Span {
+        Span { start, end }
+    }
+
+    /// Empty span at a single position, eg. for the end of the input
+    pub fn at(pos: Position) -> Span {
+        Span {
+            start: pos,
+            end: pos,
+        }
+    }
+
+    /// Span for something that isn't anywhere in particular in the source, eg. a missing root
+    /// struct.  It's on line 0, which no real span is.
+    pub fn nowhere() -> Span {
+        Span::at(Position {
+            line: 0,
+            column: 0,
+            offset: 0,
+        })
+    }
+
+    /// Whether this is a span from `nowhere`, rather than for some part of the source
+    pub fn is_nowhere(&self) -> bool {
+        self.start.line == 0
+    }
+
+    /// Span covering both this span and `other`, which comes after it
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+        }
+    }
+
+    pub fn line_no(&self) -> usize {
+        self.start.line
+    }
+}
+
+/// Span for somewhere on the given line, for tests that only know the line number
+#[cfg(test)]
+impl From<usize> for Span {
+    fn from(line_no: usize) -> Span {
+        Span::at(Position {
+            line: line_no,
+            column: 0,
+            offset: 0,
+        })
+    }
+}
+
+/// Lets tests compare values with spans against ones built by hand, which only know the line of
+/// each span
+#[cfg(test)]
+pub(crate) trait LineSpans {
+    /// Replace every span in the value with a span for just its line, as from `Span::from`
+    fn line_spans(self) -> Self;
+}
+
+#[cfg(test)]
+impl LineSpans for Span {
+    fn line_spans(self) -> Span {
+        Span::from(self.line_no())
+    }
+}
+
+#[cfg(test)]
+impl LineSpans for () {
+    fn line_spans(self) {}
+}
+
+#[cfg(test)]
+impl<T: LineSpans> LineSpans for Option<T> {
+    fn line_spans(self) -> Option<T> {
+        self.map(T::line_spans)
+    }
+}
+
+#[cfg(test)]
+impl<T: LineSpans> LineSpans for Vec<T> {
+    fn line_spans(self) -> Vec<T> {
+        self.into_iter().map(T::line_spans).collect()
+    }
+}
+
+#[cfg(test)]
+impl<T: LineSpans, E: LineSpans> LineSpans for Result<T, E> {
+    fn line_spans(self) -> Result<T, E> {
+        self.map(T::line_spans).map_err(E::line_spans)
+    }
+}
+
+#[cfg(test)]
+impl<A: LineSpans, B: LineSpans> LineSpans for (A, B) {
+    fn line_spans(self) -> (A, B) {
+        (self.0.line_spans(), self.1.line_spans())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn position_after() {
+        let pos = Position::start().after('a');
+        assert_eq!(
+            pos,
+            Position {
+                line: 1,
+                column: 2,
+                offset: 1
+            }
+        );
+        let pos = pos.after('é');
+        assert_eq!(
+            pos,
+            Position {
+                line: 1,
+                column: 3,
+                offset: 3
+            }
+        );
+        let pos = pos.after('\n');
+        assert_eq!(
+            pos,
+            Position {
+                line: 2,
+                column: 1,
+                offset: 4
+            }
+        );
+    }
+
+    #[test]
+    fn span_equality() {
+        let a = Span::at(Position::start());
+        let b = Span::from(7);
+        assert_ne!(a, b);
+        assert_eq!(a.to(b), Span::new(a.start, b.end));
+        assert_eq!(a.to(b).line_spans(), Span::from(1));
+        assert!(Span::nowhere().is_nowhere());
+        assert!(!a.is_nowhere());
+    }
+}
@@ -0,0 +1,95 @@
+/*!
+ * Suggestions for names that weren't recognised, from the names that would have been.
+ */
+
+/// Closest of `candidates` to `name`, if any is close enough to be a likely typo.  Ties are broken
+/// alphabetically, so that the suggestion doesn't depend on the order of the candidates.
+pub fn closest_match<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    // Allow about one edit for every three characters, but never replacing the whole name
+    let len = name.chars().count();
+    let max_distance = (len / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance && *distance < len)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Number of single character insertions, deletions, substitutions or swaps of neighbouring
+/// characters needed to turn `a` into `b`.  A change of case only counts as half an edit, and the
+/// total is rounded down.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    // Distances are doubled while working them out, so case changes can cost 1.  dist[i][j] is
+    // the distance between the first i characters of a, and the first j characters of b.
+    let mut dist = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in dist.iter_mut().enumerate() {
+        row[0] = i * 2;
+    }
+    for (j, cell) in dist[0].iter_mut().enumerate() {
+        *cell = j * 2;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitute = if a[i - 1] == b[j - 1] {
+                0
+            } else if a[i - 1].eq_ignore_ascii_case(&b[j - 1]) {
+                1
+            } else {
+                2
+            };
+            let mut cost = (dist[i - 1][j - 1] + substitute)
+                .min(dist[i - 1][j] + 2)
+                .min(dist[i][j - 1] + 2);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                cost = cost.min(dist[i - 2][j - 2] + 2);
+            }
+            dist[i][j] = cost;
+        }
+    }
+    dist[a.len()][b.len()] / 2
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn distances() {
+        assert_eq!(edit_distance("uint32le", "uint32_le"), 1);
+        assert_eq!(edit_distance("uint32_le", "int32_be"), 2);
+        assert_eq!(edit_distance("header", "Header"), 0);
+        assert_eq!(edit_distance("cuont", "count"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("abc", ""), 3);
+    }
+
+    #[test]
+    fn closest() {
+        let candidates = ["uint32_le", "uint32_be", "int32_le", "Header"];
+        assert_eq!(
+            closest_match("uint32le", candidates.iter().copied()),
+            Some("uint32_le")
+        );
+        assert_eq!(
+            closest_match("header", candidates.iter().copied()),
+            Some("Header")
+        );
+        // Equally close candidates are chosen alphabetically
+        assert_eq!(
+            closest_match("uint32_xe", candidates.iter().copied()),
+            Some("uint32_be")
+        );
+        assert_eq!(closest_match("footer", candidates.iter().copied()), None);
+        assert_eq!(closest_match("uint32_le", ["uint32_le"]), None);
+        assert_eq!(closest_match("ab", ["a", "xy"]), Some("a"));
+        assert_eq!(closest_match("C", ["A", "B"]), None);
+    }
+}
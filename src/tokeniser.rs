@@ -11,6 +11,7 @@
  */
 
 use crate::error::CartaError;
+use crate::span::{Position, Span};
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum TokenType {
@@ -24,13 +25,21 @@ pub enum TokenType {
     CloseBracket, // ]
     Semicolon,    // ;
     Integer, // Starts with 1-9, continues with any digit.  Max 9 digits, to guarantee that it will
-             // always fit into a u32
+    // always fit into a u32.  Or 0x followed by up to 8 hex digits.
+    At,         // @
+    Plus,       // +
+    Minus,      // -
+    OpenParen,  // (
+    CloseParen, // )
+    Star,       // *
+    Slash,      // /  (when not the start of a comment)
+    Equals,     // =
 }
 
 #[derive(PartialEq, Debug, Clone)]
 pub struct Token {
     pub kind: TokenType,
-    pub line_no: usize,
+    pub span: Span,
     value: TokenValue,
 }
 
@@ -57,22 +66,35 @@ impl IntoTokenValue for u32 {
 }
 
 impl Token {
-    fn new<V: IntoTokenValue>(kind: TokenType, value: V, line_no: usize) -> Token {
+    fn new<V: IntoTokenValue>(kind: TokenType, value: V, span: Span) -> Token {
         Token {
             kind,
             value: value.into_tokenvalue(),
-            line_no,
+            span,
         }
     }
 
-    pub fn get_string(self: Self) -> String {
+    /// Single character token at `pos`
+    fn new_char(kind: TokenType, c: char, pos: Position) -> Token {
+        Token::new(kind, c.to_string(), Span::new(pos, pos.after(c)))
+    }
+
+    pub fn get_string(self) -> String {
         match self.value {
             TokenValue::StringVal(sval) => sval,
             TokenValue::IntVal(ival) => ival.to_string(),
         }
     }
 
-    pub fn get_int(self: Self) -> u32 {
+    /// Borrow the string value of a `Word` token, without consuming the token
+    pub fn get_string_ref(&self) -> &str {
+        match &self.value {
+            TokenValue::StringVal(sval) => sval,
+            TokenValue::IntVal(_) => "",
+        }
+    }
+
+    pub fn get_int(self) -> u32 {
         match self.value {
             TokenValue::StringVal(_) => panic!("Expected int, got String in token value"),
             TokenValue::IntVal(i) => i,
@@ -86,26 +108,42 @@ pub struct Tokeniser {
 }
 
 impl Tokeniser {
-    /// Iterate over the input by character, and generate a list of output tokens
+    /// Iterate over the input by character, and generate a list of output tokens.  Stops at the
+    /// first error.  Used by tests that check for one error.
+    #[cfg(test)]
     pub fn new(data: &str) -> Result<Tokeniser, CartaError> {
+        let (tokeniser, errors) = Tokeniser::with_errors(data);
+        crate::error::first_error(tokeniser, errors)
+    }
+
+    /// As `new`, but carries on after errors to find as many as possible.  The text that caused
+    /// each error is skipped.
+    pub fn with_errors(data: &str) -> (Tokeniser, Vec<CartaError>) {
         let mut tokens: Vec<Token> = Vec::new();
+        let mut errors = Vec::new();
         let mut state: Box<dyn TokeniserState> = Box::new(EmptyState {});
-        let mut line_no = 1;
+        let mut pos = Position::start();
 
         for c in data.chars() {
-            state = state.new_char(c, &mut tokens, line_no)?;
+            state = match state.new_char(c, &mut tokens, pos) {
+                Ok(state) => state,
+                Err(err) => {
+                    errors.push(err);
+                    Box::new(SkipState)
+                }
+            };
             // Newlines count as being on the line they end, not the new line they start
-            if c == '\n' {
-                line_no += 1;
-            }
+            pos = pos.after(c);
         }
 
         // Once we're done with the input, we may still be in the process of building a token.  If we are,
         // and it's valid, add it to the list.
-        if let Some(t) = state.eof()? {
-            tokens.push(t);
+        match state.eof(pos) {
+            Ok(Some(t)) => tokens.push(t),
+            Ok(None) => {}
+            Err(err) => errors.push(err),
         }
-        Ok(Tokeniser { tokens })
+        (Tokeniser { tokens }, errors)
     }
 
     pub fn into_iter(self) -> std::vec::IntoIter<Token> {
@@ -115,16 +153,18 @@ impl Tokeniser {
 
 /// State machine to handle emitting tokens based on input
 trait TokeniserState {
-    /// Process a new input character `c`.  Maybe emit token(s) by appending to `tokens`.  Return the next state.
+    /// Process a new input character `c`, found at `pos`.  Maybe emit token(s) by appending to
+    /// `tokens`.  Return the next state.
     fn new_char(
         self: Box<Self>,
         c: char,
         tokens: &mut Vec<Token>,
-        line_no: usize,
+        pos: Position,
     ) -> Result<Box<dyn TokeniserState>, CartaError>;
 
-    /// Pick up the final token if there is one waiting to be emmitted on end-of-input.
-    fn eof(self: Box<Self>) -> Result<Option<Token>, CartaError>;
+    /// Pick up the final token if there is one waiting to be emmitted on end-of-input.  `end` is
+    /// the position just past the last character.
+    fn eof(self: Box<Self>, end: Position) -> Result<Option<Token>, CartaError>;
 }
 
 /// Default state representing start of input, or when previous input has all been completely
@@ -136,15 +176,39 @@ impl TokeniserState for EmptyState {
         self: Box<Self>,
         c: char,
         tokens: &mut Vec<Token>,
-        line_no: usize,
+        pos: Position,
     ) -> Result<Box<dyn TokeniserState>, CartaError> {
-        if let Some(s) = new_state(c, tokens, line_no)? {
+        if let Some(s) = new_state(c, tokens, pos)? {
             return Ok(s);
         }
         Ok(self)
     }
 
-    fn eof(self: Box<Self>) -> Result<Option<Token>, CartaError> {
+    fn eof(self: Box<Self>, _: Position) -> Result<Option<Token>, CartaError> {
+        Ok(None)
+    }
+}
+
+/// State after an error, skipping the rest of the word or number that caused it
+struct SkipState;
+
+impl TokeniserState for SkipState {
+    fn new_char(
+        self: Box<Self>,
+        c: char,
+        tokens: &mut Vec<Token>,
+        pos: Position,
+    ) -> Result<Box<dyn TokeniserState>, CartaError> {
+        if c.is_alphanumeric() || c == '_' {
+            return Ok(self);
+        }
+        if let Some(s) = new_state(c, tokens, pos)? {
+            return Ok(s);
+        }
+        Ok(Box::new(EmptyState))
+    }
+
+    fn eof(self: Box<Self>, _: Position) -> Result<Option<Token>, CartaError> {
         Ok(None)
     }
 }
@@ -152,19 +216,19 @@ impl TokeniserState for EmptyState {
 /// State representing processing of a `TokenType::Word`
 struct WordState {
     value: String,
-    line_no: usize,
+    start: Position,
 }
 
 impl WordState {
-    fn new(c: char, line_no: usize) -> WordState {
+    fn new(c: char, start: Position) -> WordState {
         WordState {
             value: c.to_string(),
-            line_no,
+            start,
         }
     }
 
-    fn get_token(self: Box<Self>) -> Token {
-        Token::new(TokenType::Word, self.value, self.line_no)
+    fn get_token(self, end: Position) -> Token {
+        Token::new(TokenType::Word, self.value, Span::new(self.start, end))
     }
 }
 
@@ -173,7 +237,7 @@ impl TokeniserState for WordState {
         mut self: Box<Self>,
         c: char,
         tokens: &mut Vec<Token>,
-        line_no: usize,
+        pos: Position,
     ) -> Result<Box<dyn TokeniserState>, CartaError> {
         // Accept the token, and add it to the saved token value
         if c.is_alphabetic() || c.is_numeric() || c == '_' {
@@ -182,9 +246,9 @@ impl TokeniserState for WordState {
         } else {
             // Otherwise, the next character is not a valid word character.  Emit the Word found so far,
             // and continue processing the input character as a potential new unknown token.
-            tokens.push(self.get_token());
+            tokens.push(self.get_token(pos));
 
-            if let Some(s) = new_state(c, tokens, line_no)? {
+            if let Some(s) = new_state(c, tokens, pos)? {
                 Ok(s)
             } else {
                 Ok(Box::new(EmptyState))
@@ -192,33 +256,36 @@ impl TokeniserState for WordState {
         }
     }
 
-    fn eof(self: Box<Self>) -> Result<Option<Token>, CartaError> {
-        Ok(Some(self.get_token()))
+    fn eof(self: Box<Self>, end: Position) -> Result<Option<Token>, CartaError> {
+        Ok(Some(self.get_token(end)))
     }
 }
 
 struct IntegerState {
     value: u32,
     num_digits: usize,
-    line_no: usize,
+    start: Position,
 }
 
 impl IntegerState {
-    fn new(c: char, line_no: usize) -> Result<IntegerState, CartaError> {
+    fn new(c: char, start: Position) -> Result<IntegerState, CartaError> {
         let val = c.to_digit(10).unwrap();
         // can't start with a 0
         if val == 0 {
-            return Err(CartaError::new_leading_zero(0));
+            return Err(CartaError::new_leading_zero(Span::new(
+                start,
+                start.after(c),
+            )));
         }
         Ok(IntegerState {
             value: val,
             num_digits: 1,
-            line_no,
+            start,
         })
     }
 
-    fn get_token(self: Box<Self>) -> Token {
-        Token::new(TokenType::Integer, self.value, self.line_no)
+    fn get_token(self, end: Position) -> Token {
+        Token::new(TokenType::Integer, self.value, Span::new(self.start, end))
     }
 }
 
@@ -227,12 +294,15 @@ impl TokeniserState for IntegerState {
         mut self: Box<Self>,
         c: char,
         tokens: &mut Vec<Token>,
-        line_no: usize,
+        pos: Position,
     ) -> Result<Box<dyn TokeniserState>, CartaError> {
         if let Some(new_val) = c.to_digit(10) {
             // Check we will still be in bounds
             if self.num_digits > 8 {
-                Err(CartaError::new_integer_too_large(0))
+                Err(CartaError::new_integer_too_large(Span::new(
+                    self.start,
+                    pos.after(c),
+                )))
             } else {
                 self.value *= 10;
                 self.value += new_val;
@@ -241,9 +311,9 @@ impl TokeniserState for IntegerState {
             }
         } else {
             // We've finished the integer
-            tokens.push(self.get_token());
+            tokens.push(self.get_token(pos));
 
-            if let Some(s) = new_state(c, tokens, line_no)? {
+            if let Some(s) = new_state(c, tokens, pos)? {
                 Ok(s)
             } else {
                 Ok(Box::new(EmptyState))
@@ -251,30 +321,124 @@ impl TokeniserState for IntegerState {
         }
     }
 
-    fn eof(self: Box<Self>) -> Result<Option<Token>, CartaError> {
-        Ok(Some(self.get_token()))
+    fn eof(self: Box<Self>, end: Position) -> Result<Option<Token>, CartaError> {
+        Ok(Some(self.get_token(end)))
     }
 }
 
-struct CommentState; // Don't yet know if it's a block comment or a line comment
+/// A leading zero.  Only allowed as the start of a hex integer, eg. `0x1f`.
+struct ZeroState {
+    start: Position,
+}
 
-impl TokeniserState for CommentState {
+impl TokeniserState for ZeroState {
     fn new_char(
         self: Box<Self>,
         c: char,
         _: &mut Vec<Token>,
-        line_no: usize,
+        pos: Position,
+    ) -> Result<Box<dyn TokeniserState>, CartaError> {
+        if c == 'x' {
+            Ok(Box::new(HexIntegerState {
+                value: 0,
+                num_digits: 0,
+                start: self.start,
+            }))
+        } else {
+            Err(CartaError::new_leading_zero(Span::new(
+                self.start,
+                pos.after(c),
+            )))
+        }
+    }
+
+    fn eof(self: Box<Self>, end: Position) -> Result<Option<Token>, CartaError> {
+        Err(CartaError::new_leading_zero(Span::new(self.start, end)))
+    }
+}
+
+struct HexIntegerState {
+    value: u32,
+    num_digits: usize,
+    start: Position,
+}
+
+impl HexIntegerState {
+    fn get_token(self, end: Position) -> Result<Token, CartaError> {
+        let span = Span::new(self.start, end);
+        // Need at least one digit after the 0x
+        if self.num_digits == 0 {
+            return Err(CartaError::new_unexpected_symbol(span, "hex digit", 'x'));
+        }
+        Ok(Token::new(TokenType::Integer, self.value, span))
+    }
+}
+
+impl TokeniserState for HexIntegerState {
+    fn new_char(
+        mut self: Box<Self>,
+        c: char,
+        tokens: &mut Vec<Token>,
+        pos: Position,
+    ) -> Result<Box<dyn TokeniserState>, CartaError> {
+        if let Some(new_val) = c.to_digit(16) {
+            // 8 hex digits fill a u32
+            if self.num_digits > 7 {
+                Err(CartaError::new_integer_too_large(Span::new(
+                    self.start,
+                    pos.after(c),
+                )))
+            } else {
+                self.value = (self.value << 4) | new_val;
+                self.num_digits += 1;
+                Ok(self)
+            }
+        } else {
+            tokens.push(self.get_token(pos)?);
+
+            if let Some(s) = new_state(c, tokens, pos)? {
+                Ok(s)
+            } else {
+                Ok(Box::new(EmptyState))
+            }
+        }
+    }
+
+    fn eof(self: Box<Self>, end: Position) -> Result<Option<Token>, CartaError> {
+        Ok(Some(self.get_token(end)?))
+    }
+}
+
+// Don't yet know if it's a block comment, a line comment, or just a division
+struct CommentState {
+    start: Position,
+}
+
+impl TokeniserState for CommentState {
+    fn new_char(
+        self: Box<Self>,
+        c: char,
+        tokens: &mut Vec<Token>,
+        pos: Position,
     ) -> Result<Box<dyn TokeniserState>, CartaError> {
         // Decide between a block comment and a line comment
-        return match c {
+        match c {
             '/' => Ok(Box::new(LineCommentState)),
-            '*' => Ok(Box::new(BlockCommentState)),
-            _ => Err(CartaError::new_unexpected_symbol(line_no, "* or /", c)),
-        };
+            '*' => Ok(Box::new(BlockCommentState { start: self.start })),
+            _ => {
+                // Not a comment after all
+                tokens.push(Token::new_char(TokenType::Slash, '/', self.start));
+                if let Some(s) = new_state(c, tokens, pos)? {
+                    Ok(s)
+                } else {
+                    Ok(Box::new(EmptyState))
+                }
+            }
+        }
     }
 
-    fn eof(self: Box<Self>) -> Result<Option<Token>, CartaError> {
-        Ok(None)
+    fn eof(self: Box<Self>, _: Position) -> Result<Option<Token>, CartaError> {
+        Ok(Some(Token::new_char(TokenType::Slash, '/', self.start)))
     }
 }
 
@@ -285,64 +449,73 @@ impl TokeniserState for LineCommentState {
         self: Box<Self>,
         c: char,
         _: &mut Vec<Token>,
-        _: usize,
+        _: Position,
     ) -> Result<Box<dyn TokeniserState>, CartaError> {
         if c == '\n' {
             // Newline.  End of comment.
-            return Ok(Box::new(EmptyState));
+            Ok(Box::new(EmptyState))
         } else {
-            return Ok(self);
+            Ok(self)
         }
     }
 
     // Allow eof in line comments
-    fn eof(self: Box<Self>) -> Result<Option<Token>, CartaError> {
+    fn eof(self: Box<Self>, _: Position) -> Result<Option<Token>, CartaError> {
         Ok(None)
     }
 }
 
-struct BlockCommentState;
+struct BlockCommentState {
+    // Position of the opening `/*`
+    start: Position,
+}
 
 impl TokeniserState for BlockCommentState {
     fn new_char(
         self: Box<Self>,
         c: char,
         _: &mut Vec<Token>,
-        _: usize,
+        _: Position,
     ) -> Result<Box<dyn TokeniserState>, CartaError> {
         if c == '*' {
             // Maybe end of comment
-            return Ok(Box::new(EndBlockCommentState));
+            Ok(Box::new(EndBlockCommentState { start: self.start }))
         } else {
-            return Ok(self);
+            Ok(self)
         }
     }
 
-    fn eof(self: Box<Self>) -> Result<Option<Token>, CartaError> {
-        Err(CartaError::new_unclosed_block_comment(0))
+    fn eof(self: Box<Self>, end: Position) -> Result<Option<Token>, CartaError> {
+        Err(CartaError::new_unclosed_block_comment(Span::new(
+            self.start, end,
+        )))
     }
 }
 
-struct EndBlockCommentState;
+struct EndBlockCommentState {
+    start: Position,
+}
 
 impl TokeniserState for EndBlockCommentState {
     fn new_char(
         self: Box<Self>,
         c: char,
         _: &mut Vec<Token>,
-        _: usize,
+        _: Position,
     ) -> Result<Box<dyn TokeniserState>, CartaError> {
         if c == '/' {
             // End of comment
-            return Ok(Box::new(EmptyState));
+            Ok(Box::new(EmptyState))
         } else {
             // Wasn't end of comment after all.  Comment continues
-            return Ok(Box::new(BlockCommentState));
+            Ok(Box::new(BlockCommentState { start: self.start }))
         }
     }
 
-    fn eof(self: Box<Self>) -> Result<Option<Token>, CartaError> {
-        Err(CartaError::new_unclosed_block_comment(0))
+    fn eof(self: Box<Self>, end: Position) -> Result<Option<Token>, CartaError> {
+        Err(CartaError::new_unclosed_block_comment(Span::new(
+            self.start, end,
+        )))
     }
 }
 
@@ -351,10 +524,10 @@ impl TokeniserState for EndBlockCommentState {
 fn new_state(
     c: char,
     tokens: &mut Vec<Token>,
-    line_no: usize,
+    pos: Position,
 ) -> Result<Option<Box<dyn TokeniserState>>, CartaError> {
     if c == '\n' {
-        tokens.push(Token::new(TokenType::NewLine, c.to_string(), line_no));
+        tokens.push(Token::new_char(TokenType::NewLine, c, pos));
         return Ok(None);
     }
 
@@ -365,31 +538,65 @@ fn new_state(
 
     // Word tokens start with a letter or underscore.
     if c.is_alphabetic() || c == '_' {
-        return Ok(Some(Box::new(WordState::new(c, line_no))));
+        return Ok(Some(Box::new(WordState::new(c, pos))));
+    }
+
+    if c == '0' {
+        return Ok(Some(Box::new(ZeroState { start: pos })));
     }
 
-    if c.is_digit(10) {
-        return Ok(Some(Box::new(IntegerState::new(c, line_no)?)));
+    if c.is_ascii_digit() {
+        return Ok(Some(Box::new(IntegerState::new(c, pos)?)));
     }
 
     match c {
-        ':' => tokens.push(Token::new(TokenType::Colon, c.to_string(), line_no)),
-        '{' => tokens.push(Token::new(TokenType::OpenBrace, c.to_string(), line_no)),
-        '}' => tokens.push(Token::new(TokenType::CloseBrace, c.to_string(), line_no)),
-        ',' => tokens.push(Token::new(TokenType::Comma, c.to_string(), line_no)),
-        '[' => tokens.push(Token::new(TokenType::OpenBracket, c.to_string(), line_no)),
-        ']' => tokens.push(Token::new(TokenType::CloseBracket, c.to_string(), line_no)),
-        ';' => tokens.push(Token::new(TokenType::Semicolon, c.to_string(), line_no)),
-        '/' => return Ok(Some(Box::new(CommentState))), // Start a comment
-        _ => return Err(CartaError::new_unknown_symbol(line_no, c)),
+        ':' => tokens.push(Token::new_char(TokenType::Colon, c, pos)),
+        '{' => tokens.push(Token::new_char(TokenType::OpenBrace, c, pos)),
+        '}' => tokens.push(Token::new_char(TokenType::CloseBrace, c, pos)),
+        ',' => tokens.push(Token::new_char(TokenType::Comma, c, pos)),
+        '[' => tokens.push(Token::new_char(TokenType::OpenBracket, c, pos)),
+        ']' => tokens.push(Token::new_char(TokenType::CloseBracket, c, pos)),
+        ';' => tokens.push(Token::new_char(TokenType::Semicolon, c, pos)),
+        '@' => tokens.push(Token::new_char(TokenType::At, c, pos)),
+        '+' => tokens.push(Token::new_char(TokenType::Plus, c, pos)),
+        '-' => tokens.push(Token::new_char(TokenType::Minus, c, pos)),
+        '(' => tokens.push(Token::new_char(TokenType::OpenParen, c, pos)),
+        ')' => tokens.push(Token::new_char(TokenType::CloseParen, c, pos)),
+        '*' => tokens.push(Token::new_char(TokenType::Star, c, pos)),
+        '=' => tokens.push(Token::new_char(TokenType::Equals, c, pos)),
+        '/' => return Ok(Some(Box::new(CommentState { start: pos }))), // Maybe start a comment
+        _ => {
+            return Err(CartaError::new_unknown_symbol(
+                Span::new(pos, pos.after(c)),
+                c,
+            ))
+        }
     }
 
-    return Ok(None);
+    Ok(None)
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::span::LineSpans;
+
+    impl LineSpans for Token {
+        fn line_spans(self) -> Token {
+            Token {
+                span: self.span.line_spans(),
+                ..self
+            }
+        }
+    }
+
+    impl LineSpans for Tokeniser {
+        fn line_spans(self) -> Tokeniser {
+            Tokeniser {
+                tokens: self.tokens.line_spans(),
+            }
+        }
+    }
 
     impl IntoTokenValue for &str {
         fn into_tokenvalue(self) -> TokenValue {
@@ -401,13 +608,13 @@ mod test {
         Some(Token {
             kind,
             value: val.into_tokenvalue(),
-            line_no,
+            span: Span::from(line_no),
         })
     }
 
     #[test]
     fn tokenise_word() -> Result<(), CartaError> {
-        let tok = Tokeniser::new("abc")?;
+        let tok = Tokeniser::new("abc").line_spans()?;
         let mut iter = tok.into_iter();
         assert_eq!(iter.next(), token(TokenType::Word, "abc", 1));
         assert_eq!(iter.next(), None);
@@ -416,7 +623,7 @@ mod test {
 
     #[test]
     fn space_at_start_plus_number() -> Result<(), CartaError> {
-        let tok = Tokeniser::new(" abc23")?;
+        let tok = Tokeniser::new(" abc23").line_spans()?;
         let mut iter = tok.into_iter();
         assert_eq!(iter.next(), token(TokenType::Word, "abc23", 1));
         assert_eq!(iter.next(), None);
@@ -425,7 +632,7 @@ mod test {
 
     #[test]
     fn newline_at_start_plus_underscore() -> Result<(), CartaError> {
-        let tok = Tokeniser::new("\n_abc_abc")?;
+        let tok = Tokeniser::new("\n_abc_abc").line_spans()?;
         let mut iter = tok.into_iter();
         assert_eq!(iter.next(), token(TokenType::NewLine, "\n", 1));
         assert_eq!(iter.next(), token(TokenType::Word, "_abc_abc", 2));
@@ -435,7 +642,7 @@ mod test {
 
     #[test]
     fn whitespace_at_start_tab() -> Result<(), CartaError> {
-        let tok = Tokeniser::new("\tabc")?;
+        let tok = Tokeniser::new("\tabc").line_spans()?;
         let mut iter = tok.into_iter();
         assert_eq!(iter.next(), token(TokenType::Word, "abc", 1));
         assert_eq!(iter.next(), None);
@@ -444,7 +651,7 @@ mod test {
 
     #[test]
     fn multiple_words() -> Result<(), CartaError> {
-        let tok = Tokeniser::new("abc def\nghi\tjkl ")?;
+        let tok = Tokeniser::new("abc def\nghi\tjkl ").line_spans()?;
         let mut iter = tok.into_iter();
         assert_eq!(iter.next(), token(TokenType::Word, "abc", 1));
         assert_eq!(iter.next(), token(TokenType::Word, "def", 1));
@@ -457,7 +664,7 @@ mod test {
 
     #[test]
     fn basic_typeof() -> Result<(), CartaError> {
-        let tok = Tokeniser::new("abc: uint64_le")?;
+        let tok = Tokeniser::new("abc: uint64_le").line_spans()?;
         let mut iter = tok.into_iter();
         assert_eq!(iter.next(), token(TokenType::Word, "abc", 1));
         assert_eq!(iter.next(), token(TokenType::Colon, ":", 1));
@@ -474,7 +681,8 @@ mod test {
             val1: type1,
             val2: type2
         }",
-        )?;
+        )
+        .line_spans()?;
         let mut iter = tok.into_iter();
         assert_eq!(iter.next(), token(TokenType::NewLine, "\n", 1));
         assert_eq!(iter.next(), token(TokenType::Word, "struct", 2));
@@ -496,13 +704,16 @@ mod test {
 
     #[test]
     fn unknown_token() {
-        let tok = Tokeniser::new("\tabc😃");
-        assert_eq!(tok, Err(CartaError::new_unknown_symbol(1, '😃')));
+        let tok = Tokeniser::new("\tabc😃").line_spans();
+        assert_eq!(
+            tok,
+            Err(CartaError::new_unknown_symbol(Span::from(1), '😃'))
+        );
     }
 
     #[test]
     fn line_comment() -> Result<(), CartaError> {
-        let tok = Tokeniser::new("//\nabc//xyz\n")?;
+        let tok = Tokeniser::new("//\nabc//xyz\n").line_spans()?;
         let mut iter = tok.into_iter();
         assert_eq!(iter.next(), token(TokenType::Word, "abc", 2));
         assert_eq!(iter.next(), None);
@@ -511,7 +722,7 @@ mod test {
 
     #[test]
     fn block_comment() -> Result<(), CartaError> {
-        let tok = Tokeniser::new("/*abc*/abc/**/")?;
+        let tok = Tokeniser::new("/*abc*/abc/**/").line_spans()?;
         let mut iter = tok.into_iter();
         assert_eq!(iter.next(), token(TokenType::Word, "abc", 1));
         assert_eq!(iter.next(), None);
@@ -520,13 +731,16 @@ mod test {
 
     #[test]
     fn incomplete_block_comment() {
-        let tok = Tokeniser::new("/*");
-        assert_eq!(tok, Err(CartaError::new_unclosed_block_comment(0)));
+        let tok = Tokeniser::new("/*").line_spans();
+        assert_eq!(
+            tok,
+            Err(CartaError::new_unclosed_block_comment(Span::from(1)))
+        );
     }
 
     #[test]
     fn integer() -> Result<(), CartaError> {
-        let tok = Tokeniser::new("123456789")?;
+        let tok = Tokeniser::new("123456789").line_spans()?;
         let mut iter = tok.into_iter();
         assert_eq!(iter.next(), token(TokenType::Integer, 123456789, 1));
         assert_eq!(iter.next(), None);
@@ -535,13 +749,131 @@ mod test {
 
     #[test]
     fn large_integer() {
-        let tok = Tokeniser::new("1000000000");
-        assert_eq!(tok, Err(CartaError::new_integer_too_large(0)));
+        let tok = Tokeniser::new("1000000000").line_spans();
+        assert_eq!(tok, Err(CartaError::new_integer_too_large(Span::from(1))));
     }
 
     #[test]
     fn leading_zero() {
-        let tok = Tokeniser::new("01");
-        assert_eq!(tok, Err(CartaError::new_leading_zero(0)));
+        let tok = Tokeniser::new("01").line_spans();
+        assert_eq!(tok, Err(CartaError::new_leading_zero(Span::from(1))));
+    }
+
+    #[test]
+    fn hex_integer() -> Result<(), CartaError> {
+        let tok = Tokeniser::new("0x1f 0xFFFFFFFF").line_spans()?;
+        let mut iter = tok.into_iter();
+        assert_eq!(iter.next(), token(TokenType::Integer, 0x1f, 1));
+        assert_eq!(iter.next(), token(TokenType::Integer, 0xffff_ffff, 1));
+        assert_eq!(iter.next(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn bad_hex_integer() {
+        let tok = Tokeniser::new("0x123456789").line_spans();
+        assert_eq!(tok, Err(CartaError::new_integer_too_large(Span::from(1))));
+        let tok = Tokeniser::new("0x").line_spans();
+        assert_eq!(
+            tok,
+            Err(CartaError::new_unexpected_symbol(
+                Span::from(1),
+                "hex digit",
+                'x'
+            ))
+        );
+    }
+
+    #[test]
+    fn placement() -> Result<(), CartaError> {
+        let tok = Tokeniser::new("@ end-22+(x)").line_spans()?;
+        let mut iter = tok.into_iter();
+        assert_eq!(iter.next(), token(TokenType::At, "@", 1));
+        assert_eq!(iter.next(), token(TokenType::Word, "end", 1));
+        assert_eq!(iter.next(), token(TokenType::Minus, "-", 1));
+        assert_eq!(iter.next(), token(TokenType::Integer, 22, 1));
+        assert_eq!(iter.next(), token(TokenType::Plus, "+", 1));
+        assert_eq!(iter.next(), token(TokenType::OpenParen, "(", 1));
+        assert_eq!(iter.next(), token(TokenType::Word, "x", 1));
+        assert_eq!(iter.next(), token(TokenType::CloseParen, ")", 1));
+        assert_eq!(iter.next(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn expression() -> Result<(), CartaError> {
+        let tok = Tokeniser::new("let a = b*2/c//comment\n/").line_spans()?;
+        let mut iter = tok.into_iter();
+        assert_eq!(iter.next(), token(TokenType::Word, "let", 1));
+        assert_eq!(iter.next(), token(TokenType::Word, "a", 1));
+        assert_eq!(iter.next(), token(TokenType::Equals, "=", 1));
+        assert_eq!(iter.next(), token(TokenType::Word, "b", 1));
+        assert_eq!(iter.next(), token(TokenType::Star, "*", 1));
+        assert_eq!(iter.next(), token(TokenType::Integer, 2, 1));
+        assert_eq!(iter.next(), token(TokenType::Slash, "/", 1));
+        assert_eq!(iter.next(), token(TokenType::Word, "c", 1));
+        assert_eq!(iter.next(), token(TokenType::Slash, "/", 2));
+        assert_eq!(iter.next(), None);
+        Ok(())
+    }
+
+    #[test]
+    fn spans() -> Result<(), CartaError> {
+        let tok = Tokeniser::new("ab: 0x1f,\n  é 12")?;
+        let spans: Vec<(usize, usize, usize, usize)> = tok
+            .into_iter()
+            .map(|t| {
+                (
+                    t.span.start.line,
+                    t.span.start.column,
+                    t.span.end.column,
+                    t.span.end.offset,
+                )
+            })
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                (1, 1, 3, 2),
+                (1, 3, 4, 3),
+                (1, 5, 9, 8),
+                (1, 9, 10, 9),
+                (1, 10, 1, 10),
+                (2, 3, 4, 14),
+                (2, 5, 7, 17),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn error_spans() {
+        let err = Tokeniser::new("a\n  /* x").unwrap_err();
+        assert_eq!(err.span.line_no(), 2);
+        assert_eq!((err.span.start.column, err.span.end.column), (3, 7));
+        let err = Tokeniser::new("x 1234567890").unwrap_err();
+        assert_eq!((err.span.start.column, err.span.end.column), (3, 13));
+        let err = Tokeniser::new("  01").unwrap_err();
+        assert_eq!((err.span.start.column, err.span.end.column), (3, 5));
+    }
+
+    #[test]
+    fn error_recovery() {
+        let (tok, errors) = Tokeniser::with_errors("a 😃 1234567890 b\n01 c €").line_spans();
+        assert_eq!(
+            errors,
+            vec![
+                CartaError::new_unknown_symbol(Span::from(1), '😃'),
+                CartaError::new_integer_too_large(Span::from(1)),
+                CartaError::new_leading_zero(Span::from(2)),
+                CartaError::new_unknown_symbol(Span::from(2), '€'),
+            ]
+        );
+        let words: Vec<String> = tok
+            .into_iter()
+            .filter(|t| t.kind == TokenType::Word)
+            .map(Token::get_string)
+            .collect();
+        assert_eq!(words, vec!["a", "b", "c"]);
     }
 }
@@ -1,55 +1,406 @@
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 
 use crate::builtin_types;
+use crate::error;
 use crate::error::CartaError;
-use crate::parser::{ElementTypeRef, Schema, StructDefn};
+use crate::expression::{Env, Expr};
+use crate::ordered_map::OrderedMap;
+use crate::parser::{ArrayLen, Element, ElementTypeRef, RootDecl, Schema, StructDefn};
+use crate::visit::Visitor;
 
+/// A compiled schema, ready to be applied to data
 #[derive(PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TSchema {
-    pub types: HashMap<String, StructDefn>,
+    // Every struct, by name, in the order they were declared.  Inline structs come just before the
+    // struct they're defined in.
+    pub(crate) types: OrderedMap<StructDefn>,
+
+    // Names of the structs that can be used as entry points.  The first is the default.
+    pub(crate) roots: Vec<String>,
+
+    // Layout of every struct in `types`, by name, in the same order
+    pub(crate) layouts: OrderedMap<StructLayout>,
+}
+
+/// The parts of a struct's layout that are known without looking at any data
+#[derive(PartialEq, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StructLayout {
+    /// Total size of the struct, if it's the same wherever the struct appears
+    pub size: Option<usize>,
+
+    /// Offset of each element from the start of the struct, in the same order as the struct's
+    /// elements.  None for elements after one whose size depends on the data, and for placed
+    /// elements.
+    pub offsets: Vec<Option<usize>>,
+}
+
+impl TSchema {
+    /// All the structs, in the order they were declared
+    pub fn structs(&self) -> impl Iterator<Item = &StructDefn> {
+        self.types.values()
+    }
+
+    pub fn get_struct(&self, name: &str) -> Option<&StructDefn> {
+        self.types.get(name)
+    }
+
+    /// All the structs by name, in the order they were declared
+    pub fn types(&self) -> &OrderedMap<StructDefn> {
+        &self.types
+    }
+
+    /// Names of the structs that can be used as entry points.  The first is the default.
+    pub fn roots(&self) -> &[String] {
+        &self.roots
+    }
+
+    /// Visit every struct in the schema, in the order they were declared
+    pub fn visit(&self, visitor: &mut dyn Visitor) {
+        for struct_defn in self.structs() {
+            visitor.visit_struct(struct_defn);
+        }
+    }
+
+    /// Size of a type, if it's the same wherever the type appears.  That's the case for builtin
+    /// types, and structs made up only of elements with fixed sizes.
+    pub fn static_size(&self, typename: &str) -> Option<usize> {
+        match builtin_types::get_size(typename) {
+            Some(size) => Some(size),
+            None => self.layouts.get(typename)?.size,
+        }
+    }
+
+    /// Size of an element of type `kind`, if it's fixed
+    pub fn static_elem_size(&self, kind: &ElementTypeRef) -> Option<usize> {
+        let env = StaticEnv {
+            size_of: |typename: &str| self.static_size(typename),
+        };
+        static_elem_size(kind, &env)
+    }
+
+    pub fn layout(&self, name: &str) -> Option<&StructLayout> {
+        self.layouts.get(name)
+    }
+}
+
+/// Size of an element of type `kind`, if it's fixed.  `env` gives the sizes of other types.
+fn static_elem_size(kind: &ElementTypeRef, env: &dyn Env) -> Option<usize> {
+    match kind {
+        ElementTypeRef::TypeName(typename) => usize::try_from(env.size_of(typename)?).ok(),
+        // The length must be known before looking at the type of the entries, as recursive types
+        // are only allowed through arrays with dynamic lengths
+        ElementTypeRef::ArrayElem(array_defn) => {
+            let len = static_len(&array_defn.length, env)?;
+            len.checked_mul(static_elem_size(&array_defn.kind, env)?)
+        }
+        ElementTypeRef::Bytes(length) => static_len(length, env),
+        ElementTypeRef::Computed(_) => Some(0),
+    }
+}
+
+fn static_len(length: &ArrayLen, env: &dyn Env) -> Option<usize> {
+    match length {
+        ArrayLen::Static(len) => Some(*len as usize),
+        ArrayLen::Identifier(_) => None,
+        ArrayLen::Expr(expr) => usize::try_from(expr.eval(env)?).ok(),
+    }
 }
 
+/// Evaluates expressions that only depend on the schema, such as `sizeof(Header) * 2`.  `size_of`
+/// gives the sizes of types.
+struct StaticEnv<F: Fn(&str) -> Option<usize>> {
+    size_of: F,
+}
+
+impl<F: Fn(&str) -> Option<usize>> Env for StaticEnv<F> {
+    fn value(&self, _name: &str) -> Option<i64> {
+        None
+    }
+
+    fn end(&self) -> Option<i64> {
+        None
+    }
+
+    fn find_last(&self, _signature: &[u8]) -> Option<i64> {
+        None
+    }
+
+    fn size_of(&self, typename: &str) -> Option<i64> {
+        (self.size_of)(typename)?.try_into().ok()
+    }
+
+    fn offset_of(&self, _name: &str) -> Option<i64> {
+        None
+    }
+
+    fn position(&self) -> Option<i64> {
+        None
+    }
+
+    fn struct_start(&self) -> Option<i64> {
+        None
+    }
+}
+
+/// Works out the layout of each struct.  The sizes of structs are remembered as they're found, so
+/// that types used in many places are only looked at once.
+struct LayoutBuilder<'a> {
+    types_map: &'a OrderedMap<StructDefn>,
+    sizes: RefCell<HashMap<String, Option<usize>>>,
+}
+
+impl<'a> LayoutBuilder<'a> {
+    fn static_size(&self, typename: &str) -> Option<usize> {
+        if let Some(size) = builtin_types::get_size(typename) {
+            return Some(size);
+        }
+        if let Some(size) = self.sizes.borrow().get(typename) {
+            return *size;
+        }
+
+        let size = self.layout(self.types_map.get(typename)?).size;
+        self.sizes.borrow_mut().insert(typename.to_string(), size);
+        size
+    }
+
+    fn layout(&self, struct_defn: &StructDefn) -> StructLayout {
+        let env = StaticEnv {
+            size_of: |typename: &str| self.static_size(typename),
+        };
+
+        // Position of the next element, while it's still known
+        let mut pos = Some(0usize);
+        let mut union_size = Some(0);
+        let mut offsets = Vec::new();
+        for element in &struct_defn.elements {
+            // Placed elements aren't positioned relative to the struct
+            if element.placement.is_some() {
+                offsets.push(None);
+                continue;
+            }
+
+            if struct_defn.is_union {
+                offsets.push(Some(0));
+            } else {
+                offsets.push(pos);
+            }
+
+            // Peek elements don't take up any space in the struct
+            if element.peek {
+                continue;
+            }
+            let elem_size = static_elem_size(&element.kind, &env);
+            if struct_defn.is_union {
+                union_size = union_size.zip(elem_size).map(|(a, b)| a.max(b));
+            } else {
+                pos = pos.zip(elem_size).and_then(|(a, b)| a.checked_add(b));
+            }
+        }
+
+        StructLayout {
+            size: if struct_defn.is_union {
+                union_size
+            } else {
+                pos
+            },
+            offsets,
+        }
+    }
+}
+
+/// Work out the layout of every struct.  Must only be called once we know there are no loops in
+/// the types.
+fn build_layouts(types_map: &OrderedMap<StructDefn>) -> OrderedMap<StructLayout> {
+    let builder = LayoutBuilder {
+        types_map,
+        sizes: RefCell::new(HashMap::new()),
+    };
+    types_map
+        .iter()
+        .map(|(name, struct_defn)| (name.to_string(), builder.layout(struct_defn)))
+        .collect()
+}
+
+/// Type check the schema, stopping at the first error.  Used by tests that check for one error.
+#[cfg(test)]
 pub fn type_check_schema(schema: Schema) -> Result<TSchema, CartaError> {
-    let types = check_types(schema.structs)?;
-    Ok(TSchema { types })
+    type_check_schema_with_errors(schema).map_err(|errors| errors.into_iter().next().unwrap())
 }
 
-fn build_structs_map(types: Vec<StructDefn>) -> Result<HashMap<String, StructDefn>, CartaError> {
-    let mut types_map: HashMap<String, StructDefn> = HashMap::new();
+/// Type check the schema, carrying on after errors to find as many as possible
+pub fn type_check_schema_with_errors(schema: Schema) -> Result<TSchema, Vec<CartaError>> {
+    let mut errors = Vec::new();
+    let types = check_types(schema.structs, &mut errors);
+    let roots = check_roots(schema.roots, &types, &mut errors);
+    if !errors.is_empty() {
+        error::sort_errors(&mut errors);
+        return Err(errors);
+    }
+
+    let layouts = build_layouts(&types);
+    Ok(TSchema {
+        types,
+        roots,
+        layouts,
+    })
+}
+
+/// Check that all declared entry points are structs.  If there are no declarations, the struct
+/// named "root" is the entry point.
+fn check_roots(
+    roots: Vec<RootDecl>,
+    types_map: &OrderedMap<StructDefn>,
+    errors: &mut Vec<CartaError>,
+) -> Vec<String> {
+    if roots.is_empty() {
+        return vec!["root".to_string()];
+    }
+
+    let mut names = Vec::new();
+    for root in roots.into_iter() {
+        if !types_map.contains_key(&root.name) {
+            let err = CartaError::new_unknown_type(root.span, root.name.clone());
+            errors.push(err.with_suggestion(&root.name, struct_names(types_map)));
+        } else if !names.contains(&root.name) {
+            names.push(root.name);
+        }
+    }
+    names
+}
+
+/// Names of the structs declared in the schema, for suggesting corrections to misspelt types.
+/// Inline structs can't be referred to by name, so aren't included.
+fn struct_names(types_map: &OrderedMap<StructDefn>) -> impl Iterator<Item = &str> {
+    types_map
+        .values()
+        .filter(|struct_defn| !struct_defn.is_inline())
+        .map(|struct_defn| struct_defn.name())
+}
+
+/// Names of all the types an element could use
+fn type_names(types_map: &OrderedMap<StructDefn>) -> impl Iterator<Item = &str> {
+    let builtins = builtin_types::builtin_type_names().map(|name| -> &str { name });
+    builtins.chain(struct_names(types_map))
+}
+
+/// Map of types by name.  Only the first definition of each name is kept.
+fn build_structs_map(
+    types: Vec<StructDefn>,
+    errors: &mut Vec<CartaError>,
+) -> OrderedMap<StructDefn> {
+    let mut types_map: OrderedMap<StructDefn> = OrderedMap::new();
 
     for kind in types.into_iter() {
-        if types_map.contains_key::<str>(&kind.name) {
-            return Err(CartaError::new_duplicate_type(kind.line_no, kind.name));
+        if let Some(first) = types_map.get(&kind.name) {
+            errors.push(
+                CartaError::new_duplicate_type(kind.span, kind.name)
+                    .with_label(first.span, "first defined here"),
+            );
+        } else {
+            types_map.insert(kind.name.clone(), kind);
         }
-        types_map.insert(kind.name.clone(), kind);
     }
 
-    Ok(types_map)
+    types_map
 }
 
-fn check_all_types_defined(types_map: &HashMap<String, StructDefn>) -> Result<(), CartaError> {
+fn check_all_types_defined(types_map: &OrderedMap<StructDefn>, errors: &mut Vec<CartaError>) {
     // All types are now stored in types_map.  We can now go over all members of all types, and
     // check that they've all been defined.
     for kind in types_map.values() {
         for member in &kind.elements {
-            let typename = match &member.kind {
-                ElementTypeRef::TypeName(typename) => &typename,
-                ElementTypeRef::ArrayElem(array_defn) => &array_defn.kind,
+            let typename = match member.kind.type_name() {
+                Some(typename) => typename,
+                // Byte blocks don't refer to any other type
+                None => continue,
             };
 
-            if !builtin_types::is_builtin_type(&typename)
-                && types_map.get::<str>(&typename).is_none()
-            {
-                return Err(CartaError::new_unknown_type(member.line_no, typename.to_string()));
+            if !builtin_types::is_builtin_type(typename) && types_map.get(typename).is_none() {
+                let err = CartaError::new_unknown_type(member.span, typename.to_string());
+                errors.push(err.with_suggestion(typename, type_names(types_map)));
+            }
+        }
+
+        // Types used in sizeof expressions
+        for member in &kind.elements {
+            for expr in member.exprs() {
+                for typename in expr.sizeof_types() {
+                    if !builtin_types::is_builtin_type(typename)
+                        && !types_map.contains_key(typename)
+                    {
+                        let err = CartaError::new_unknown_type(member.span, typename.to_string());
+                        errors.push(err.with_suggestion(typename, type_names(types_map)));
+                    }
+                }
+            }
+        }
+
+        // Base types must be other structs
+        if let Some(base) = &kind.base {
+            if builtin_types::is_builtin_type(base) {
+                errors.push(CartaError::new_bad_base_type(kind.span, base));
+            } else {
+                match types_map.get(base) {
+                    None => errors.push(
+                        CartaError::new_unknown_type(kind.span, base.to_string())
+                            .with_suggestion(base, struct_names(types_map)),
+                    ),
+                    Some(base_kind) if base_kind.is_union => {
+                        errors.push(CartaError::new_bad_base_type(kind.span, base))
+                    }
+                    Some(_) => {}
+                }
             }
         }
     }
+}
 
-    Ok(())
+/// Name of the type an element refers to, unless the reference is guarded so that it may
+/// terminate at runtime.  References are guarded by an array with a length read from the data,
+/// which may be empty, or by a placement that's read from the data, which acts as a pointer.
+fn unguarded_type_name(member: &Element) -> Option<&str> {
+    if member.placement.as_ref().is_some_and(Expr::reads_data) {
+        return None;
+    }
+
+    let mut kind = &member.kind;
+    loop {
+        match kind {
+            ElementTypeRef::TypeName(typename) => return Some(typename),
+            ElementTypeRef::ArrayElem(array_defn) => match &array_defn.length {
+                ArrayLen::Static(_) => kind = &array_defn.kind,
+                ArrayLen::Expr(expr) if !expr.reads_data() => kind = &array_defn.kind,
+                ArrayLen::Identifier(_) | ArrayLen::Expr(_) => return None,
+            },
+            // Byte blocks and computed values don't refer to any other type
+            ElementTypeRef::Bytes(_) | ElementTypeRef::Computed(_) => return None,
+        }
+    }
 }
 
-/// Check that there are no types that recursively depend on themselves.
-fn check_types_no_loops(types_map: &HashMap<String, StructDefn>) -> Result<(), CartaError> {
+/// Names of all types a struct can't be decoded without - the types of its elements, except
+/// where the reference is guarded, the types its expressions take the size of, and its base type
+fn struct_dependencies(kind: &StructDefn) -> Vec<&str> {
+    let mut deps: Vec<&str> = kind
+        .elements
+        .iter()
+        .filter_map(unguarded_type_name)
+        .collect();
+    for member in &kind.elements {
+        deps.extend(member.exprs().into_iter().flat_map(Expr::sizeof_types));
+    }
+    if let Some(base) = &kind.base {
+        deps.push(base);
+    }
+    deps
+}
+
+/// Check that there are no types that recursively depend on themselves, other than through guarded
+/// references.  Guarded recursion is limited by the maximum depth when the schema is applied.
+fn check_types_no_loops(types_map: &OrderedMap<StructDefn>) -> Result<(), CartaError> {
     // Set of all types that have been fully resolved to depend only on builtin types, or
     // other types that depend transitively on only built-in types.
     // Hopefully we can eventually add all the types to this set.  If we can't, there must be a loop
@@ -67,21 +418,17 @@ fn check_types_no_loops(types_map: &HashMap<String, StructDefn>) -> Result<(), C
     // detected here as well.
     for kind in types_map.values() {
         let mut all_builtin = true;
-        for member in &kind.elements {
-            let typename = match &member.kind {
-                ElementTypeRef::TypeName(typename) => &typename,
-                ElementTypeRef::ArrayElem(array_defn) => &array_defn.kind,
-            };
-            if !builtin_types::is_builtin_type(&typename)
-                && types_resolved.get::<str>(&typename).is_none()
+        for typename in struct_dependencies(kind) {
+            if !builtin_types::is_builtin_type(typename)
+                && !types_resolved.contains::<str>(typename)
             {
                 all_builtin = false;
 
-                if !dependant_types.contains_key::<str>(&typename) {
-                    dependant_types.insert(&typename, Vec::new());
+                if !dependant_types.contains_key::<str>(typename) {
+                    dependant_types.insert(typename, Vec::new());
                 }
                 dependant_types
-                    .get_mut::<str>(&typename)
+                    .get_mut::<str>(typename)
                     .unwrap()
                     .push(&kind.name);
             }
@@ -96,9 +443,9 @@ fn check_types_no_loops(types_map: &HashMap<String, StructDefn>) -> Result<(), C
     // Go over the stack of resolved types.  Use the dependant_types map to check if the types
     // the depend on the resolved type can be marked as resolved.
     while let Some(kind_name) = types_stack.pop() {
-        if let Some(parents) = dependant_types.get::<str>(&kind_name) {
+        if let Some(parents) = dependant_types.get::<str>(kind_name) {
             for parent in parents.iter() {
-                let parent = match types_map.get::<str>(parent) {
+                let parent = match types_map.get(parent) {
                     Some(p) => p,
                     // Should not be possible for this to happen, as we've previously called
                     // check_all_types_defined to check that all types are known.
@@ -106,13 +453,9 @@ fn check_types_no_loops(types_map: &HashMap<String, StructDefn>) -> Result<(), C
                 };
 
                 let mut all_resolved = true;
-                for member in &parent.elements {
-                    let typename = match &member.kind {
-                        ElementTypeRef::TypeName(typename) => &typename,
-                        ElementTypeRef::ArrayElem(array_defn) => &array_defn.kind,
-                    };
-                    if !builtin_types::is_builtin_type(&typename)
-                        && types_resolved.get::<str>(&typename).is_none()
+                for typename in struct_dependencies(parent) {
+                    if !builtin_types::is_builtin_type(typename)
+                        && !types_resolved.contains::<str>(typename)
                     {
                         all_resolved = false;
                     }
@@ -129,64 +472,138 @@ fn check_types_no_loops(types_map: &HashMap<String, StructDefn>) -> Result<(), C
 
     // If any types remain that aren't listed in types_resolved, then we must have a loop
     let mut recursive_types = Vec::new();
-    // Report the line number of the first type with an error
-    let mut line_no = usize::max_value();
+    // Report the location of the first type with an error
+    let mut first: Option<&StructDefn> = None;
     for kind in types_map.values() {
-        if types_resolved.get::<str>(&kind.name).is_none() {
+        if !types_resolved.contains::<str>(&kind.name) {
             recursive_types.push(kind.name.clone());
-            if kind.line_no < line_no {
-                line_no = kind.line_no;
+            if first.map_or(true, |first| kind.span.start < first.span.start) {
+                first = Some(kind);
             }
         }
     }
-    if !recursive_types.is_empty() {
-        return Err(CartaError::new_recursive_types(line_no, recursive_types));
+    if let Some(first) = first {
+        return Err(CartaError::new_recursive_types(first.span, recursive_types));
     }
 
     Ok(())
 }
 
-fn check_types(types: Vec<StructDefn>) -> Result<HashMap<String, StructDefn>, CartaError> {
-    let types_map = build_structs_map(types)?;
-    check_all_types_defined(&types_map)?;
-    check_types_no_loops(&types_map)?;
+/// Copy the elements of each struct's base types into the struct, ahead of its own elements.
+/// Must only be called once we know there are no loops in the base types.
+fn flatten_base_types(types_map: &mut OrderedMap<StructDefn>) {
+    let mut flattened = HashMap::new();
+    for kind in types_map.values() {
+        // Walk up the chain of base types, collecting their own elements from the most derived
+        // up to the root of the chain
+        let mut inherited: Vec<&[Element]> = Vec::new();
+        let mut base = &kind.base;
+        while let Some(base_name) = base {
+            let base_kind = &types_map[base_name.as_str()];
+            inherited.push(&base_kind.elements);
+            base = &base_kind.base;
+        }
+
+        if !inherited.is_empty() {
+            let elements: Vec<Element> = inherited.into_iter().rev().flatten().cloned().collect();
+            flattened.insert(kind.name.clone(), elements);
+        }
+    }
+
+    for (name, mut elements) in flattened.into_iter() {
+        let kind = types_map.get_mut(&name).unwrap();
+        kind.num_inherited = elements.len();
+        elements.append(&mut kind.elements);
+        kind.elements = elements;
+    }
+}
+
+/// Make `end`, `_pos` and `_struct_start` in expressions refer to an earlier element with the same
+/// name, including inherited elements, rather than to the builtin value.  Base types may not exist
+/// or may loop, as this runs before those are checked.
+fn prefer_elements(types_map: &mut OrderedMap<StructDefn>) {
+    let mut inherited_names = HashMap::new();
+    for kind in types_map.values() {
+        let mut names = HashSet::new();
+        let mut seen = HashSet::new();
+        let mut base = &kind.base;
+        while let Some(base_name) = base {
+            let base_kind = match types_map.get(base_name) {
+                Some(base_kind) if seen.insert(base_name) => base_kind,
+                _ => break,
+            };
+            names.extend(
+                base_kind
+                    .elements
+                    .iter()
+                    .map(|element| element.name.clone()),
+            );
+            base = &base_kind.base;
+        }
+        inherited_names.insert(kind.name.clone(), names);
+    }
+
+    for (name, mut names) in inherited_names.into_iter() {
+        let kind = types_map.get_mut(&name).unwrap();
+        for element in kind.elements.iter_mut() {
+            element.prefer_elements(&|name| names.contains(name));
+            names.insert(element.name.clone());
+        }
+    }
+}
+
+fn check_types(types: Vec<StructDefn>, errors: &mut Vec<CartaError>) -> OrderedMap<StructDefn> {
+    let mut types_map = build_structs_map(types, errors);
+    prefer_elements(&mut types_map);
+    let num_errors = errors.len();
+    check_all_types_defined(&types_map, errors);
+
+    // Loops can only be found once every type is known to exist, and base types can only be
+    // flattened once there are no loops
+    if errors.len() == num_errors {
+        match check_types_no_loops(&types_map) {
+            Ok(()) => flatten_base_types(&mut types_map),
+            Err(err) => errors.push(err),
+        }
+    }
 
-    Ok(types_map)
+    types_map
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::parser::Element;
-    use std::fmt::Debug;
-    use crate::error::CartaErrorCode;
+    use crate::parser;
+    use crate::span::{LineSpans, Span};
+    use crate::tokeniser::Tokeniser;
+
+    impl LineSpans for TSchema {
+        fn line_spans(self) -> TSchema {
+            TSchema {
+                types: self.types.line_spans(),
+                ..self
+            }
+        }
+    }
 
     fn build_element(name: &str, typename: &str, line_no: usize) -> Element {
         Element {
             name: name.to_string(),
             kind: ElementTypeRef::TypeName(typename.to_string()),
-            line_no,
+            span: Span::from(line_no),
+            peek: false,
+            placement: None,
         }
     }
 
     fn build_struct(name: &str, elements: Vec<Element>, line_no: usize) -> StructDefn {
         StructDefn {
             name: name.to_string(),
-            elements: elements,
-            line_no
-        }
-    }
-
-    // Compare two vectors, where element order doesn't matter
-    fn compare_vec_unordered<T: PartialEq + Debug>(a: Vec<T>, b: Vec<T>) {
-        if a.len() != b.len() {
-            panic!("{:?} != {:?}", &a, &b);
-        }
-        // Assumes elements in a are unique
-        for elem in &a {
-            if !b.contains(&elem) {
-                panic!("{:?} != {:?}", a, b);
-            }
+            elements,
+            span: Span::from(line_no),
+            base: None,
+            num_inherited: 0,
+            is_union: false,
         }
     }
 
@@ -195,6 +612,7 @@ mod test {
         let elem1 = build_element("inner1", "uint16_le", 1);
         let schema = Schema {
             structs: vec![build_struct("type1", vec![elem1], 1)],
+            roots: Vec::new(),
         };
         type_check_schema(schema)?;
         Ok(())
@@ -213,6 +631,7 @@ mod test {
         let t2 = build_struct("type2", vec![build_element("inner3", "int8", 2)], 2);
         let schema = Schema {
             structs: vec![t1, t2],
+            roots: Vec::new(),
         };
         type_check_schema(schema)?;
         Ok(())
@@ -228,9 +647,18 @@ mod test {
             ],
             1
         );
-        let schema = Schema { structs: vec![t1] };
-        let res = type_check_schema(schema);
-        assert_eq!(res, Err(CartaError::new_unknown_type(2, "type2".to_string())));
+        let schema = Schema {
+            structs: vec![t1],
+            roots: Vec::new(),
+        };
+        let res = type_check_schema(schema).line_spans();
+        assert_eq!(
+            res,
+            Err(
+                CartaError::new_unknown_type(Span::from(2), "type2".to_string())
+                    .with_help("did you mean `type1`?")
+            )
+        );
     }
 
     #[test]
@@ -253,13 +681,17 @@ mod test {
         );
         let schema = Schema {
             structs: vec![t1, t2],
+            roots: Vec::new(),
         };
-        let res = type_check_schema(schema);
-        if let Err(CartaError {line_no: 1, code: CartaErrorCode::RecursiveTypes(data)}) = res {
-            compare_vec_unordered(data, vec!["type1".to_string(), "type2".to_string()])
-        } else {
-            panic!("Unexpected value: {:?}", res);
-        }
+        let res = type_check_schema(schema).line_spans();
+        // Types are listed in the order they were declared
+        assert_eq!(
+            res,
+            Err(CartaError::new_recursive_types(
+                Span::from(1),
+                vec!["type1".to_string(), "type2".to_string()]
+            ))
+        );
     }
 
     #[test]
@@ -293,6 +725,7 @@ mod test {
         );
         let schema = Schema {
             structs: vec![t1, t2, t3, t4, t5, t6],
+            roots: Vec::new(),
         };
         type_check_schema(schema)?;
         Ok(())
@@ -360,22 +793,22 @@ mod test {
         );
         let schema = Schema {
             structs: vec![t1, t2, t3, t4, t5, t6, t7],
+            roots: Vec::new(),
         };
-        let res = type_check_schema(schema);
-        if let Err(CartaError {line_no: 1, code: CartaErrorCode::RecursiveTypes(data)}) = res {
-            compare_vec_unordered(
-                data,
+        let res = type_check_schema(schema).line_spans();
+        assert_eq!(
+            res,
+            Err(CartaError::new_recursive_types(
+                Span::from(1),
                 vec![
+                    "type1".to_string(),
                     "type2".to_string(),
                     "type3".to_string(),
-                    "type7".to_string(),
-                    "type1".to_string(),
                     "type4".to_string(),
-                ],
-            )
-        } else {
-            panic!("Unexpected value: {:?}", res);
-        }
+                    "type7".to_string(),
+                ]
+            ))
+        );
     }
 
     #[test]
@@ -391,9 +824,16 @@ mod test {
         let t2 = build_struct("type1", vec![build_element("inner3", "type1", 2)], 2);
         let schema = Schema {
             structs: vec![t1, t2],
+            roots: Vec::new(),
         };
-        let res = type_check_schema(schema);
-        assert_eq!(res, Err(CartaError::new_duplicate_type(2, "type1".to_string())));
+        let res = type_check_schema(schema).line_spans();
+        assert_eq!(
+            res,
+            Err(
+                CartaError::new_duplicate_type(Span::from(2), "type1".to_string())
+                    .with_label(Span::from(1), "first defined here")
+            )
+        );
     }
 
     #[test]
@@ -406,11 +846,427 @@ mod test {
             ],
             1
         );
-        let schema = Schema { structs: vec![t1] };
-        let res = type_check_schema(schema);
+        let schema = Schema {
+            structs: vec![t1],
+            roots: Vec::new(),
+        };
+        let res = type_check_schema(schema).line_spans();
+        assert_eq!(
+            res,
+            Err(CartaError::new_recursive_types(
+                Span::from(1),
+                vec!["type1".to_string()]
+            ))
+        );
+    }
+
+    #[test]
+    fn sizeof_types() -> Result<(), CartaError> {
+        let data = "struct A {a: bytes[sizeof(B)]} struct B {b: [uint8; sizeof(A)]}";
+        let schema = parser::compile_schema(Tokeniser::new(data)?)?;
+        let res = type_check_schema(schema).line_spans();
+        assert_eq!(
+            res,
+            Err(CartaError::new_recursive_types(
+                Span::from(1),
+                vec!["A".to_string(), "B".to_string()]
+            ))
+        );
+
+        let data = "struct A {a: bytes[sizeof(C)]}";
+        let schema = parser::compile_schema(Tokeniser::new(data)?)?;
+        let res = type_check_schema(schema).line_spans();
+        assert_eq!(
+            res,
+            Err(CartaError::new_unknown_type(Span::from(1), "C".to_string()))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn static_size() -> Result<(), CartaError> {
+        let data = "
+            struct Fixed {a: uint16_le, b: [Pair; 3], c: bytes[sizeof(Pair) * 2]}
+            struct Pair {x: uint8, y: int8, peek z: uint32_le, let w = x; v: uint64_le @ end - 8}
+            union Either {a: uint32_le, b: Pair}
+            struct Dynamic {len: uint8, data: bytes[len]}
+            struct Placed {pos: uint8, data: bytes[4] @ pos}
+        ";
+        let schema = parser::compile_schema(Tokeniser::new(data)?)?;
+        let tschema = type_check_schema(schema)?;
+        assert_eq!(tschema.static_size("uint32_le"), Some(4));
+        assert_eq!(tschema.static_size("Pair"), Some(2));
+        assert_eq!(tschema.static_size("Fixed"), Some(12));
+        assert_eq!(tschema.static_size("Either"), Some(4));
+        assert_eq!(tschema.static_size("Dynamic"), None);
+        assert_eq!(tschema.static_size("Placed"), Some(1));
+        assert_eq!(tschema.static_size("Unknown"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn builtin_names_as_elements() -> Result<(), CartaError> {
+        let data = "
+            struct root {end: uint8, data: [uint8; end], rest: bytes[end - 1]}
+            struct Later {data: [uint8; end], end: uint8}
+            struct Derived extends Base {a: uint8 @ _pos + 1, let b = _struct_start;}
+            struct Base {_pos: uint8, _struct_start: uint8}
+        ";
+        let schema = parser::compile_schema(Tokeniser::new(data)?)?;
+        let tschema = type_check_schema(schema)?;
+        let id = |name: &str| Expr::Identifier(name.to_string());
+
+        let root = tschema.get_struct("root").unwrap();
+        assert_eq!(
+            root.elements[1].kind,
+            ElementTypeRef::ArrayElem(parser::ArrayDefn {
+                kind: Box::new(ElementTypeRef::TypeName("uint8".to_string())),
+                length: ArrayLen::Identifier("end".to_string()),
+            })
+        );
+        assert_eq!(root.elements[2].exprs()[0].identifiers(), vec!["end"]);
+
+        // Only earlier elements take the place of the builtin value
+        let later = tschema.get_struct("Later").unwrap();
+        assert_eq!(later.elements[0].exprs(), vec![&Expr::End]);
+
+        let derived = tschema.get_struct("Derived").unwrap();
+        assert_eq!(derived.elements[2].exprs()[0].identifiers(), vec!["_pos"]);
+        assert_eq!(derived.elements[3].exprs(), vec![&id("_struct_start")]);
+        Ok(())
+    }
+
+    #[test]
+    fn layouts() -> Result<(), CartaError> {
+        let data = "
+            struct root {a: uint16_le, b: [uint8; 3], peek c: uint32_le, d: uint8, len: uint8,
+                data: bytes[len], e: uint8}
+            struct Placed {a: uint8, b: uint32_le @ a, c: uint16_le}
+            union Either {a: uint32_le, b: uint8}
+        ";
+        let schema = parser::compile_schema(Tokeniser::new(data)?)?;
+        let tschema = type_check_schema(schema)?;
+        assert_eq!(
+            tschema.layout("root"),
+            Some(&StructLayout {
+                size: None,
+                offsets: vec![Some(0), Some(2), Some(5), Some(5), Some(6), Some(7), None],
+            })
+        );
+        assert_eq!(
+            tschema.layout("Placed"),
+            Some(&StructLayout {
+                size: Some(3),
+                offsets: vec![Some(0), None, Some(1)],
+            })
+        );
+        assert_eq!(
+            tschema.layout("Either"),
+            Some(&StructLayout {
+                size: Some(4),
+                offsets: vec![Some(0), Some(0)],
+            })
+        );
+        assert_eq!(tschema.layout("uint8"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn error_spans() -> Result<(), CartaError> {
+        let data = "struct root {\n    a: uint8,\n    b: [Unknown; a],\n}";
+        let schema = parser::compile_schema(Tokeniser::new(data)?)?;
+        let err = type_check_schema(schema).unwrap_err();
+        assert_eq!((err.span.start.column, err.span.end.column), (5, 20));
+        assert_eq!(
+            err.line_spans(),
+            CartaError::new_unknown_type(Span::from(3), "Unknown".to_string())
+        );
+
+        let data = "struct A {b: B}\n  struct B {a: A}";
+        let schema = parser::compile_schema(Tokeniser::new(data)?)?;
+        let err = type_check_schema(schema).unwrap_err();
+        assert_eq!((err.span.start.line, err.span.start.column), (1, 1));
+        assert_eq!((err.span.end.line, err.span.end.column), (1, 16));
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_errors() -> Result<(), CartaError> {
+        let data = "
+            root Missing;
+            struct A {a: Unknown1, b: uint8}
+            struct A {c: uint8}
+            struct B extends uint8 {d: [Unknown2; 2]}
+        ";
+        let schema = parser::compile_schema(Tokeniser::new(data)?)?;
+        let res = type_check_schema_with_errors(schema).line_spans();
+        assert_eq!(
+            res,
+            Err(vec![
+                CartaError::new_unknown_type(Span::from(2), "Missing".to_string()),
+                CartaError::new_unknown_type(Span::from(3), "Unknown1".to_string()),
+                CartaError::new_duplicate_type(Span::from(4), "A".to_string())
+                    .with_label(Span::from(3), "first defined here"),
+                CartaError::new_bad_base_type(Span::from(5), "uint8"),
+                CartaError::new_unknown_type(Span::from(5), "Unknown2".to_string()),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn suggestions() -> Result<(), CartaError> {
+        let data = "
+            root Heeder;
+            struct Header {magic: uint32le, size: bytes[sizeof(header)], inline: struct {a: uint8}}
+            struct root extends Hedaer {d: inlin, e: Unrelated}
+        ";
+        let schema = parser::compile_schema(Tokeniser::new(data)?)?;
+        let res = type_check_schema_with_errors(schema).line_spans();
+        assert_eq!(
+            res,
+            Err(vec![
+                CartaError::new_unknown_type(Span::from(2), "Heeder".to_string())
+                    .with_help("did you mean `Header`?"),
+                CartaError::new_unknown_type(Span::from(3), "uint32le".to_string())
+                    .with_help("did you mean `uint32_le`?"),
+                CartaError::new_unknown_type(Span::from(3), "header".to_string())
+                    .with_help("did you mean `Header`?"),
+                CartaError::new_unknown_type(Span::from(4), "Hedaer".to_string())
+                    .with_help("did you mean `Header`?"),
+                CartaError::new_unknown_type(Span::from(4), "inlin".to_string()),
+                CartaError::new_unknown_type(Span::from(4), "Unrelated".to_string()),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn declaration_order() -> Result<(), CartaError> {
+        let data = "
+            struct Zeta {a: uint8}
+            struct root {b: Zeta, c: struct {d: Alpha}}
+            struct Alpha {e: uint16_le}
+            struct Middle extends Alpha {}
+        ";
+        let tschema = type_check_schema(parser::compile_schema(Tokeniser::new(data)?)?)?;
+        let names: Vec<&str> = tschema.structs().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["Zeta", "root::c", "root", "Alpha", "Middle"]);
+        let names: Vec<&str> = tschema.layouts.keys().collect();
+        assert_eq!(names, vec!["Zeta", "root::c", "root", "Alpha", "Middle"]);
+        assert_eq!(tschema.get_struct("Middle").unwrap().elements.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn guarded_recursion() -> Result<(), CartaError> {
+        let guarded = [
+            "struct Node {count: uint8, children: [Node; count]}",
+            "struct Node {count: uint8, children: [[Node; 2]; count]}",
+            "struct Node {next_offset: uint32_le, next: Node @ next_offset}",
+            "struct A {b: B} struct B {next: A @ rfind(0x01)}",
+            "struct Node {count: uint8, children: [Node; count * 2]}",
+        ];
+        for data in guarded.iter() {
+            let schema = parser::compile_schema(Tokeniser::new(data)?)?;
+            type_check_schema(schema)?;
+        }
+
+        let unguarded = [
+            "struct Node {children: [Node; 2]}",
+            "struct Node {next: Node @ end - 4}",
+            "struct Node {peek next: Node}",
+            "struct Node {children: [Node; sizeof(uint16_le) - 2]}",
+        ];
+        for data in unguarded.iter() {
+            let schema = parser::compile_schema(Tokeniser::new(data)?)?;
+            let res = type_check_schema(schema).line_spans();
+            assert_eq!(
+                res,
+                Err(CartaError::new_recursive_types(
+                    Span::from(1),
+                    vec!["Node".to_string()]
+                ))
+            );
+        }
+        Ok(())
+    }
+
+    fn build_derived_struct(
+        name: &str,
+        base: &str,
+        elements: Vec<Element>,
+        line_no: usize,
+    ) -> StructDefn {
+        StructDefn {
+            base: Some(base.to_string()),
+            ..build_struct(name, elements, line_no)
+        }
+    }
+
+    #[test]
+    fn extends() -> Result<(), CartaError> {
+        let t1 = build_struct("Base", vec![build_element("size", "uint32_le", 2)], 1);
+        let t2 = build_derived_struct("V4", "Base", vec![build_element("red", "uint8", 5)], 4);
+        let t3 = build_derived_struct("V5", "V4", vec![build_element("intent", "uint8", 8)], 7);
+        let schema = Schema {
+            structs: vec![t3, t2, t1],
+            roots: Vec::new(),
+        };
+        let tschema = type_check_schema(schema)?;
+        assert_eq!(
+            tschema.types["V5"],
+            StructDefn {
+                num_inherited: 2,
+                ..build_derived_struct(
+                    "V5",
+                    "V4",
+                    vec![
+                        build_element("size", "uint32_le", 2),
+                        build_element("red", "uint8", 5),
+                        build_element("intent", "uint8", 8),
+                    ],
+                    7
+                )
+            }
+        );
+        assert_eq!(tschema.types["V4"].num_inherited, 1);
+        assert_eq!(tschema.types["V4"].elements.len(), 2);
+        assert_eq!(tschema.types["Base"].num_inherited, 0);
+        assert_eq!(tschema.types["Base"].elements.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn extends_loop() {
+        let t1 = build_derived_struct("V4", "V5", vec![build_element("red", "uint8", 2)], 1);
+        let t2 = build_derived_struct("V5", "V4", vec![build_element("intent", "uint8", 5)], 4);
+        let schema = Schema {
+            structs: vec![t1, t2],
+            roots: Vec::new(),
+        };
+        let res = type_check_schema(schema).line_spans();
+        assert_eq!(
+            res,
+            Err(CartaError::new_recursive_types(
+                Span::from(1),
+                vec!["V4".to_string(), "V5".to_string()]
+            ))
+        );
+
+        let t1 = build_derived_struct("V4", "V4", vec![build_element("red", "uint8", 2)], 1);
+        let schema = Schema {
+            structs: vec![t1],
+            roots: Vec::new(),
+        };
+        let res = type_check_schema(schema).line_spans();
+        assert_eq!(
+            res,
+            Err(CartaError::new_recursive_types(
+                Span::from(1),
+                vec!["V4".to_string()]
+            ))
+        );
+    }
+
+    #[test]
+    fn extends_bad_base() {
+        let t1 = build_derived_struct("V4", "V3", vec![build_element("red", "uint8", 2)], 1);
+        let schema = Schema {
+            structs: vec![t1],
+            roots: Vec::new(),
+        };
+        let res = type_check_schema(schema).line_spans();
+        assert_eq!(
+            res,
+            Err(
+                CartaError::new_unknown_type(Span::from(1), "V3".to_string())
+                    .with_help("did you mean `V4`?")
+            )
+        );
+
+        let t1 = build_derived_struct("V4", "uint8", vec![build_element("red", "uint8", 2)], 1);
+        let schema = Schema {
+            structs: vec![t1],
+            roots: Vec::new(),
+        };
+        let res = type_check_schema(schema).line_spans();
         assert_eq!(
             res,
-            Err(CartaError::new_recursive_types(1, vec!["type1".to_string()]))
+            Err(CartaError::new_bad_base_type(Span::from(1), "uint8"))
+        );
+
+        let t1 = build_derived_struct("V4", "U", vec![build_element("red", "uint8", 2)], 1);
+        let t2 = StructDefn {
+            is_union: true,
+            ..build_struct("U", vec![build_element("a", "uint8", 4)], 3)
+        };
+        let schema = Schema {
+            structs: vec![t1, t2],
+            roots: Vec::new(),
+        };
+        let res = type_check_schema(schema).line_spans();
+        assert_eq!(res, Err(CartaError::new_bad_base_type(Span::from(1), "U")));
+    }
+
+    #[test]
+    fn roots() -> Result<(), CartaError> {
+        let t1 = build_struct("type1", vec![build_element("inner1", "uint8", 1)], 1);
+        let t2 = build_struct("type2", vec![build_element("inner1", "uint8", 2)], 2);
+        let schema = Schema {
+            structs: vec![t1, t2],
+            roots: vec![
+                RootDecl {
+                    name: "type2".to_string(),
+                    span: Span::from(3),
+                },
+                RootDecl {
+                    name: "type1".to_string(),
+                    span: Span::from(4),
+                },
+                RootDecl {
+                    name: "type2".to_string(),
+                    span: Span::from(5),
+                },
+            ],
+        };
+        let tschema = type_check_schema(schema)?;
+        assert_eq!(
+            tschema.roots,
+            vec!["type2".to_string(), "type1".to_string()]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn default_root() -> Result<(), CartaError> {
+        let t1 = build_struct("type1", vec![build_element("inner1", "uint8", 1)], 1);
+        let schema = Schema {
+            structs: vec![t1],
+            roots: Vec::new(),
+        };
+        let tschema = type_check_schema(schema)?;
+        assert_eq!(tschema.roots, vec!["root".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_root() {
+        let t1 = build_struct("type1", vec![build_element("inner1", "uint8", 1)], 1);
+        let schema = Schema {
+            structs: vec![t1],
+            roots: vec![RootDecl {
+                name: "Png".to_string(),
+                span: Span::from(3),
+            }],
+        };
+        let res = type_check_schema(schema).line_spans();
+        assert_eq!(
+            res,
+            Err(CartaError::new_unknown_type(
+                Span::from(3),
+                "Png".to_string()
+            ))
         );
     }
 
@@ -424,8 +1280,17 @@ mod test {
             ],
             1
         );
-        let schema = Schema { structs: vec![t1] };
-        let res = type_check_schema(schema);
-        assert_eq!(res, Err(CartaError::new_unknown_type(1, "bad_type".to_string())));
+        let schema = Schema {
+            structs: vec![t1],
+            roots: Vec::new(),
+        };
+        let res = type_check_schema(schema).line_spans();
+        assert_eq!(
+            res,
+            Err(CartaError::new_unknown_type(
+                Span::from(1),
+                "bad_type".to_string()
+            ))
+        );
     }
 }
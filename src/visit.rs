@@ -0,0 +1,181 @@
+/*!
+ * Walking a compiled schema.
+ *
+ * Implement `Visitor`, overriding the methods for the parts of the schema of interest, and pass it
+ * to `TSchema::visit`.  Each method's default carries on walking down into that part of the schema
+ * with the matching `walk_*` function, so an overriding method should call it too in order to
+ * keep visiting the parts inside.
+ */
+
+use crate::expression::Expr;
+use crate::parser::{ArrayDefn, ArrayLen, Element, ElementTypeRef, StructDefn};
+
+pub trait Visitor {
+    /// A struct or union.  Inline structs are visited where they're declared in the schema, as
+    /// well as through the type names of the elements that use them.
+    fn visit_struct(&mut self, struct_defn: &StructDefn) {
+        walk_struct(self, struct_defn);
+    }
+
+    /// An element of a struct, including elements inherited from its base type
+    fn visit_element(&mut self, element: &Element) {
+        walk_element(self, element);
+    }
+
+    /// The type of an element, or of the entries of an array
+    fn visit_type(&mut self, kind: &ElementTypeRef) {
+        walk_type(self, kind);
+    }
+
+    fn visit_array(&mut self, array_defn: &ArrayDefn) {
+        walk_array(self, array_defn);
+    }
+
+    /// Length of an array or byte block
+    fn visit_array_len(&mut self, length: &ArrayLen) {
+        walk_array_len(self, length);
+    }
+
+    /// Name of a builtin type or struct used by an element
+    fn visit_type_name(&mut self, _name: &str) {}
+
+    /// An expression used by an element - a placement, length or computed value
+    fn visit_expr(&mut self, _expr: &Expr) {}
+}
+
+pub fn walk_struct<V: Visitor + ?Sized>(visitor: &mut V, struct_defn: &StructDefn) {
+    for element in struct_defn.elements() {
+        visitor.visit_element(element);
+    }
+}
+
+/// Visits the type of the element, then its placement
+pub fn walk_element<V: Visitor + ?Sized>(visitor: &mut V, element: &Element) {
+    visitor.visit_type(element.kind());
+    if let Some(placement) = element.placement() {
+        visitor.visit_expr(placement);
+    }
+}
+
+pub fn walk_type<V: Visitor + ?Sized>(visitor: &mut V, kind: &ElementTypeRef) {
+    match kind {
+        ElementTypeRef::TypeName(name) => visitor.visit_type_name(name),
+        ElementTypeRef::ArrayElem(array_defn) => visitor.visit_array(array_defn),
+        ElementTypeRef::Bytes(length) => visitor.visit_array_len(length),
+        ElementTypeRef::Computed(expr) => visitor.visit_expr(expr),
+    }
+}
+
+/// Visits the length of the array, then the type of its entries
+pub fn walk_array<V: Visitor + ?Sized>(visitor: &mut V, array_defn: &ArrayDefn) {
+    visitor.visit_array_len(array_defn.length());
+    visitor.visit_type(array_defn.kind());
+}
+
+pub fn walk_array_len<V: Visitor + ?Sized>(visitor: &mut V, length: &ArrayLen) {
+    if let ArrayLen::Expr(expr) = length {
+        visitor.visit_expr(expr);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::compile_schema_file;
+
+    /// Records everything it visits, in order
+    #[derive(Default)]
+    struct Recorder {
+        visited: Vec<String>,
+    }
+
+    impl Visitor for Recorder {
+        fn visit_struct(&mut self, struct_defn: &StructDefn) {
+            self.visited.push(format!("struct {}", struct_defn.name()));
+            walk_struct(self, struct_defn);
+        }
+
+        fn visit_element(&mut self, element: &Element) {
+            self.visited.push(format!("element {}", element.name()));
+            walk_element(self, element);
+        }
+
+        fn visit_array_len(&mut self, length: &ArrayLen) {
+            if let ArrayLen::Identifier(id) = length {
+                self.visited.push(format!("len {}", id));
+            }
+            walk_array_len(self, length);
+        }
+
+        fn visit_type_name(&mut self, name: &str) {
+            self.visited.push(format!("type {}", name));
+        }
+
+        fn visit_expr(&mut self, expr: &Expr) {
+            self.visited.push(format!("expr {:?}", expr.identifiers()));
+        }
+    }
+
+    #[test]
+    fn visit_order() {
+        let schema = compile_schema_file(
+            "struct root extends Base {
+                count: uint8,
+                items: [[Item; 2]; count],
+                rest: bytes[count * 2],
+                let total = count + 1;
+                placed: uint8 @ total,
+            }
+            struct Base {magic: uint32_le}
+            struct Item {a: uint8}",
+        )
+        .unwrap();
+        let mut recorder = Recorder::default();
+        schema.visit(&mut recorder);
+        assert_eq!(
+            recorder.visited,
+            vec![
+                "struct root",
+                "element magic",
+                "type uint32_le",
+                "element count",
+                "type uint8",
+                "element items",
+                "len count",
+                "type Item",
+                "element rest",
+                "expr [\"count\"]",
+                "element total",
+                "expr [\"count\"]",
+                "element placed",
+                "type uint8",
+                "expr [\"total\"]",
+                "struct Base",
+                "element magic",
+                "type uint32_le",
+                "struct Item",
+                "element a",
+                "type uint8",
+            ]
+        );
+    }
+
+    #[test]
+    fn default_walk() {
+        // Only overriding visit_type_name still reaches every type name
+        struct TypeNames(Vec<String>);
+        impl Visitor for TypeNames {
+            fn visit_type_name(&mut self, name: &str) {
+                self.0.push(name.to_string());
+            }
+        }
+
+        let schema = compile_schema_file(
+            "struct root {a: [[Inner; 1]; 2], b: struct {c: int8}} struct Inner {}",
+        )
+        .unwrap();
+        let mut names = TypeNames(Vec::new());
+        schema.visit(&mut names);
+        assert_eq!(names.0, vec!["int8", "Inner", "root::b"]);
+    }
+}
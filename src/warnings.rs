@@ -0,0 +1,237 @@
+/*!
+ * Warnings - things in a schema that are allowed, but probably aren't what was intended.  Only
+ * checked once the schema has compiled without errors.
+ */
+
+use std::collections::HashSet;
+
+use crate::builtin_types;
+use crate::error::CartaWarning;
+use crate::parser::{ArrayLen, ElementTypeRef, StructDefn};
+use crate::type_check::TSchema;
+
+/// Find all warnings for the schema, in the order they appear in the source
+pub fn check_warnings(schema: &TSchema) -> Vec<CartaWarning> {
+    let mut warnings = Vec::new();
+    check_unused_structs(schema, &mut warnings);
+    for struct_defn in schema.types.values() {
+        check_empty_struct(struct_defn, &mut warnings);
+        check_element_names(struct_defn, &mut warnings);
+        check_signed_lengths(struct_defn, &mut warnings);
+    }
+    warnings.sort_by_key(|warning| warning.span.start);
+    warnings
+}
+
+/// Structs that can't be reached from any of the roots are never applied to any data
+fn check_unused_structs(schema: &TSchema, warnings: &mut Vec<CartaWarning>) {
+    let mut used: HashSet<&str> = HashSet::new();
+    let mut to_visit: Vec<&str> = schema.roots.iter().map(|root| root.as_str()).collect();
+    while let Some(name) = to_visit.pop() {
+        let struct_defn = match schema.types.get(name) {
+            Some(struct_defn) if used.insert(name) => struct_defn,
+            _ => continue,
+        };
+        to_visit.extend(struct_defn.base.as_deref());
+        for element in &struct_defn.elements {
+            to_visit.extend(element.kind.type_name());
+            for expr in element.exprs() {
+                to_visit.extend(expr.sizeof_types());
+            }
+        }
+    }
+
+    for (name, struct_defn) in schema.types.iter() {
+        if !used.contains(name) {
+            warnings.push(CartaWarning::new_unused_struct(struct_defn.span, name));
+        }
+    }
+}
+
+fn check_empty_struct(struct_defn: &StructDefn, warnings: &mut Vec<CartaWarning>) {
+    if struct_defn.elements.is_empty() {
+        warnings.push(CartaWarning::new_empty_struct(
+            struct_defn.span,
+            &struct_defn.name,
+        ));
+    }
+}
+
+/// Elements with the same name as an earlier element hide it from expressions and lengths, and
+/// elements named after builtin types are easily mistaken for them.  Inherited elements are
+/// checked with the struct they were defined in.
+fn check_element_names(struct_defn: &StructDefn, warnings: &mut Vec<CartaWarning>) {
+    let elements = &struct_defn.elements;
+    for (i, element) in elements.iter().enumerate().skip(struct_defn.num_inherited) {
+        if let Some(first) = elements[..i].iter().find(|elem| elem.name == element.name) {
+            warnings.push(
+                CartaWarning::new_duplicate_element(element.span, &element.name)
+                    .with_label(first.span, "first defined here"),
+            );
+        }
+        if builtin_types::is_builtin_type(&element.name) {
+            warnings.push(CartaWarning::new_shadows_builtin(
+                element.span,
+                &element.name,
+            ));
+        }
+    }
+}
+
+/// Array lengths taken from signed integers, directly or through an expression, are invalid if
+/// the value is negative
+fn check_signed_lengths(struct_defn: &StructDefn, warnings: &mut Vec<CartaWarning>) {
+    let elements = &struct_defn.elements;
+    for (i, element) in elements.iter().enumerate().skip(struct_defn.num_inherited) {
+        let mut lengths = Vec::new();
+        let mut kind = &element.kind;
+        loop {
+            match kind {
+                ElementTypeRef::ArrayElem(array_defn) => {
+                    lengths.push(&array_defn.length);
+                    kind = &array_defn.kind;
+                }
+                ElementTypeRef::Bytes(length) => {
+                    lengths.push(length);
+                    break;
+                }
+                ElementTypeRef::TypeName(_) | ElementTypeRef::Computed(_) => break,
+            }
+        }
+
+        for length in lengths {
+            let ids = match length {
+                ArrayLen::Identifier(id) => vec![id.as_str()],
+                ArrayLen::Expr(expr) => expr.identifiers(),
+                _ => Vec::new(),
+            };
+            for id in ids {
+                let len_elem = elements[..i].iter().rev().find(|elem| elem.name == id);
+                let len_type = len_elem.and_then(|elem| elem.kind.type_name());
+                if len_type.is_some_and(builtin_types::is_signed_integer) {
+                    warnings.push(CartaWarning::new_signed_array_len(element.span, id));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser;
+    use crate::span::{LineSpans, Span};
+    use crate::tokeniser;
+    use crate::type_check;
+
+    fn warnings(data: &str) -> Vec<CartaWarning> {
+        let tokeniser = tokeniser::Tokeniser::new(data).unwrap();
+        let schema = parser::compile_schema(tokeniser).unwrap();
+        let tschema = type_check::type_check_schema(schema).unwrap();
+        check_warnings(&tschema).line_spans()
+    }
+
+    #[test]
+    fn no_warnings() {
+        let data = "
+            struct root {len: uint8, header: Header, data: [Entry; len], extra: bytes[len]}
+            struct Header {magic: uint32_le}
+            struct Entry extends Base {size: uint16_le @ sizeof(Trailer)}
+            struct Base {kind: uint8}
+            struct Trailer {crc: uint32_le}
+        ";
+        assert_eq!(warnings(data), vec![]);
+    }
+
+    #[test]
+    fn unused_structs() {
+        let data = "
+            root first;
+            struct first {a: uint8, b: struct {c: uint8}}
+            struct Unused {d: Other}
+            struct Other {e: uint8}
+        ";
+        assert_eq!(
+            warnings(data),
+            vec![
+                CartaWarning::new_unused_struct(Span::from(4), "Unused"),
+                CartaWarning::new_unused_struct(Span::from(5), "Other"),
+            ]
+        );
+    }
+
+    #[test]
+    fn element_names() {
+        let data = "
+            struct root extends Base {
+                a: uint8,
+                uint8: uint8,
+                a: uint16_le,
+            }
+            struct Base {a: uint8}
+        ";
+        assert_eq!(
+            warnings(data),
+            vec![
+                CartaWarning::new_duplicate_element(Span::from(3), "a")
+                    .with_label(Span::from(7), "first defined here"),
+                CartaWarning::new_shadows_builtin(Span::from(4), "uint8"),
+                CartaWarning::new_duplicate_element(Span::from(5), "a")
+                    .with_label(Span::from(7), "first defined here"),
+            ]
+        );
+    }
+
+    #[test]
+    fn inline_structs_same_name() {
+        // Only a warning, as the inline structs are given different names
+        let data = "struct root {h: struct {a: uint8}, h: struct {b: uint8}}";
+        assert_eq!(
+            warnings(data),
+            vec![CartaWarning::new_duplicate_element(Span::from(1), "h")
+                .with_label(Span::from(1), "first defined here")]
+        );
+    }
+
+    #[test]
+    fn empty_structs() {
+        let data = "
+            struct root {a: Empty, b: union {}}
+            struct Empty {}
+        ";
+        assert_eq!(
+            warnings(data),
+            vec![
+                CartaWarning::new_empty_struct(Span::from(2), "root::b"),
+                CartaWarning::new_empty_struct(Span::from(3), "Empty"),
+            ]
+        );
+    }
+
+    #[test]
+    fn signed_lengths() {
+        let data = "
+            struct root {
+                len: int16_le,
+                ulen: uint16_le,
+                a: [uint8; len],
+                b: [[uint8; ulen]; len],
+                c: [bytes[len]; ulen],
+                d: [uint8; ulen],
+                e: [uint8; len + 1],
+                f: bytes[ulen * 2 - len],
+                g: [uint8; ulen + sizeof(uint8)],
+            }
+        ";
+        assert_eq!(
+            warnings(data),
+            vec![
+                CartaWarning::new_signed_array_len(Span::from(5), "len"),
+                CartaWarning::new_signed_array_len(Span::from(6), "len"),
+                CartaWarning::new_signed_array_len(Span::from(7), "len"),
+                CartaWarning::new_signed_array_len(Span::from(9), "len"),
+                CartaWarning::new_signed_array_len(Span::from(10), "len"),
+            ]
+        );
+    }
+}
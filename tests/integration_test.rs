@@ -1,7 +1,7 @@
 extern crate carta_schema;
 
 // start, len, name, value, children
-use carta_schema::Nugget;
+use carta_schema::{CartaErrorCode, ElementTypeRef, Nugget, StructDefn, Visitor};
 
 #[test]
 fn basic_header_with_text_array() {
@@ -28,24 +28,32 @@ fn basic_header_with_text_array() {
             len: 5,
             name: "root".to_string(),
             value: None,
+            raw_value: None,
+            peek: false,
             children: vec![
                 Nugget {
                     start: 0,
                     len:  5,
                     name: "header".to_string(),
                     value: None,
+                    raw_value: None,
+                    peek: false,
                     children: vec![
                         Nugget {
                             start: 0,
                             len: 5,
                             name: "name".to_string(),
                             value: None,
+                            raw_value: None,
+                            peek: false,
                             children: vec![
                                 Nugget {
                                     start: 0,
                                     len: 1,
                                     name: "len".to_string(),
                                     value: Some("4".to_string()),
+                                    raw_value: None,
+                                    peek: false,
                                     children: Vec::new(),
                                 },
                                 Nugget {
@@ -53,6 +61,8 @@ fn basic_header_with_text_array() {
                                     len: 4,
                                     name: "value".to_string(),
                                     value: Some("abcd".to_string()),
+                                    raw_value: None,
+                                    peek: false,
                                     children: Vec::new(),
                                 }
                             ]
@@ -62,4 +72,159 @@ fn basic_header_with_text_array() {
             ]
         }
     );
-}
\ No newline at end of file
+}
+
+#[test]
+fn zip_located_from_end() {
+    let schema_data = "
+        struct root {
+            eocd: EndOfCentralDirectory @ rfind(0x50, 0x4b, 0x05, 0x06)
+        }
+        struct EndOfCentralDirectory {
+            signature: uint32_le,
+            disk_num: uint16_le,
+            cd_disk: uint16_le,
+            disk_entries: uint16_le,
+            num_entries: uint16_le,
+            cd_size: uint32_le,
+            cd_offset: uint32_le,
+            comment_len: uint16_le,
+            comment: [ascii; comment_len],
+            central_directory: [CentralDirectoryEntry; num_entries] @ cd_offset,
+        }
+        struct CentralDirectoryEntry {
+            signature: uint32_le,
+            version_made_by: uint16_le,
+            version_needed: uint16_le,
+            flags: uint16_le,
+            compression: uint16_le,
+            mod_time: uint16_le,
+            mod_date: uint16_le,
+            crc32: uint32_le,
+            compressed_size: uint32_le,
+            uncompressed_size: uint32_le,
+            name_len: uint16_le,
+            extra_len: uint16_le,
+            comment_len: uint16_le,
+            disk_start: uint16_le,
+            internal_attrs: uint16_le,
+            external_attrs: uint32_le,
+            local_offset: uint32_le,
+            name: [ascii; name_len],
+            extra: bytes[extra_len],
+            comment: [ascii; comment_len],
+            local_file: LocalFile @ local_offset,
+        }
+        struct LocalFile {
+            signature: uint32_le,
+            version_needed: uint16_le,
+            flags: uint16_le,
+            compression: uint16_le,
+            mod_time: uint16_le,
+            mod_date: uint16_le,
+            crc32: uint32_le,
+            compressed_size: uint32_le,
+            uncompressed_size: uint32_le,
+            name_len: uint16_le,
+            extra_len: uint16_le,
+            name: [ascii; name_len],
+            extra: bytes[extra_len],
+            data: bytes[compressed_size],
+        }
+    ";
+    let schema = carta_schema::compile_schema_file(schema_data).unwrap();
+
+    // Stored zip file, containing a.txt with contents "hi"
+    let bin_data = b"\x50\x4b\x03\x04\x14\x00\x00\x00\x00\x00\x00\x00\x21\x00\xac\x2a\x93\xd8\
+        \x02\x00\x00\x00\x02\x00\x00\x00\x05\x00\x00\x00\x61\x2e\x74\x78\x74\x68\x69\x50\x4b\x01\
+        \x02\x14\x03\x14\x00\x00\x00\x00\x00\x00\x00\x21\x00\xac\x2a\x93\xd8\x02\x00\x00\x00\x02\
+        \x00\x00\x00\x05\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x80\x01\x00\x00\x00\x00\x61\
+        \x2e\x74\x78\x74\x50\x4b\x05\x06\x00\x00\x00\x00\x01\x00\x01\x00\x33\x00\x00\x00\x25\x00\
+        \x00\x00\x00\x00";
+    let nugget = carta_schema::apply_schema(&schema, bin_data);
+
+    // Placed elements don't take up space in the root
+    assert_eq!(nugget.len, 0);
+    let eocd = &nugget.children[0];
+    assert_eq!(eocd.start, 88);
+    assert_eq!(eocd.len, 22);
+
+    let central_directory = &eocd.children[9];
+    assert_eq!(central_directory.start, 37);
+    assert_eq!(central_directory.len, 51);
+    let entry = &central_directory.children[0];
+    assert_eq!(entry.children[17].value, Some("a.txt".to_string()));
+
+    let local_file = &entry.children[20];
+    assert_eq!(local_file.start, 0);
+    assert_eq!(local_file.len, 37);
+    assert_eq!(local_file.children[13].start, 35);
+    assert_eq!(local_file.children[13].value, Some("68 69".to_string()));
+}
+
+#[test]
+fn compile_errors() {
+    let errors = carta_schema::compile_schema_file("struct root {a: uint32le}").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    let err = &errors[0];
+    match &err.code {
+        CartaErrorCode::UnknownType(kind) => assert_eq!(kind, "uint32le"),
+        code => panic!("Unexpected error: {}", code),
+    }
+    assert_eq!(err.code.id(), "E0001");
+    assert_eq!(err.help, vec!["did you mean `uint32_le`?".to_string()]);
+    assert_eq!(
+        carta_schema::explain(err.code.id()),
+        Some(err.code.explanation())
+    );
+
+    // Errors can be returned through std::error::Error
+    let boxed: Box<dyn std::error::Error> = Box::new(errors.into_iter().next().unwrap());
+    assert_eq!(boxed.to_string(), "Line 1: Unrecognized type: uint32le");
+}
+
+#[test]
+fn inspect_schema() {
+    let schema = carta_schema::compile_schema_file(
+        "
+        root File;
+        struct File {header: Header, entries: [Entry; 2]}
+        struct Header extends Magic {size: uint32_le}
+        struct Magic {magic: [ascii; 4]}
+        struct Entry {value: uint16_be}
+    ",
+    )
+    .unwrap();
+    assert_eq!(schema.roots(), ["File".to_string()]);
+
+    let header = schema.get_struct("Header").unwrap();
+    assert_eq!(header.base(), Some("Magic"));
+    assert_eq!(header.inherited_elements()[0].name(), "magic");
+    assert_eq!(header.own_elements()[0].name(), "size");
+
+    let entries = &schema.get_struct("File").unwrap().elements()[1];
+    match entries.kind() {
+        ElementTypeRef::ArrayElem(array) => assert_eq!(array.kind().type_name(), Some("Entry")),
+        kind => panic!("Unexpected type: {:?}", kind),
+    }
+
+    // Count the elements of each struct with a visitor
+    struct ElementCounter(Vec<(String, usize)>);
+    impl Visitor for ElementCounter {
+        fn visit_struct(&mut self, struct_defn: &StructDefn) {
+            self.0
+                .push((struct_defn.name().to_string(), struct_defn.elements().len()));
+        }
+    }
+    let mut counter = ElementCounter(Vec::new());
+    schema.visit(&mut counter);
+    assert_eq!(
+        counter.0,
+        vec![
+            ("File".to_string(), 2),
+            ("Header".to_string(), 2),
+            ("Magic".to_string(), 1),
+            ("Entry".to_string(), 1),
+        ]
+    );
+}